@@ -1,7 +1,68 @@
+use std::f64::consts::PI;
+
 use trace_math::ray::Ray;
 use trace_math::vec3::{Point, Vec3};
 use trace_math::utils::math_utils;
 
+/// Projection from normalized film coordinates to rays leaving the camera.
+/// Film coordinates `(s, t)` go from 0 to 1, starting at the bottom-left
+/// corner of the image.
+///
+/// Only cameras that can be reached from the scene side, with a finite lens
+/// and film, support light tracing through `sample_importance` and `pdf_we`;
+/// the others are only seen by rays traced from them.
+pub trait CameraModel {
+    fn get_ray(&self, s: f64, t: f64) -> Ray;
+
+    /// Forward viewing direction, i.e. the normal of the lens.
+    fn forward(&self) -> Vec3;
+
+    /// Whether film coordinates `(s, t)` see the scene at all, e.g. false
+    /// outside a fisheye's image circle.
+    fn covers(&self, _s: f64, _t: f64) -> bool {
+        true
+    }
+
+    /// Ray through film coordinates `(s, t)` along with the weight of the
+    /// radiance it brings back, or `None` when nothing is seen through them.
+    /// Cameras whose optics block or dim some rays override it.
+    fn generate_ray(&self, s: f64, t: f64) -> Option<(Ray, f64)> {
+        match self.covers(s, t) {
+            true => Some((self.get_ray(s, t), 1.)),
+            false => None,
+        }
+    }
+
+    /// Whether light paths can be connected to the camera.
+    fn is_connectible(&self) -> bool {
+        false
+    }
+
+    /// Film coordinates at which a ray leaving the lens is seen, or `None`
+    /// when it doesn't go through the film window.
+    fn film_coords(&self, _ray: Ray) -> Option<(f64, f64)> {
+        None
+    }
+
+    /// Positional and directional densities with which `get_ray` generates
+    /// `ray`, the former per unit lens area, the latter per solid angle.
+    fn pdf_we(&self, _ray: Ray) -> (f64, f64) {
+        (0., 0.)
+    }
+
+    /// Importance emitted along `ray`, normalized so that it integrates to
+    /// one over the whole film window.
+    fn we(&self, _ray: Ray) -> f64 {
+        0.
+    }
+
+    /// Samples a point on the lens from which `p` is seen.
+    fn sample_importance(&self, _p: Point) -> Option<Importance> {
+        None
+    }
+}
+
+/// Thin-lens perspective camera.
 pub struct Camera {
     origin: Point,
     lower_left_corner: Point,
@@ -11,6 +72,19 @@ pub struct Camera {
     v: Vec3,
     w: Vec3,
     lens_radius: f64,
+    focus_dist: f64,
+}
+
+/// Connection from a point in the scene back to the camera, as needed by
+/// light tracing: where the point lands on the film, the lens point it is
+/// seen from, the importance emitted towards it and the solid-angle density
+/// (as seen from the point) of having picked that lens point.
+pub struct Importance {
+    pub s: f64,
+    pub t: f64,
+    pub lens_point: Point,
+    pub we: f64,
+    pub pdf: f64,
 }
 
 impl Camera {
@@ -30,16 +104,20 @@ impl Camera {
         let lower_left_corner = origin - horizontal / 2. - vertical / 2. - w * focus_dist;
         let lens_radius = aperture / 2.;
 
-        Camera { origin, lower_left_corner, horizontal, vertical, u, v, w, lens_radius }
+        Camera { origin, lower_left_corner, horizontal, vertical, u, v, w, lens_radius, focus_dist }
     }
 
-    pub fn get_ray(&self, s: f64, t: f64) -> Ray {
-        let rd = Vec3::rand_in_unit_disk() * self.lens_radius;
-        let offset = self.u * rd.x + self.v * rd.y;
-        Ray::new(
-            self.origin + offset, 
-            self.lower_left_corner + self.horizontal * s + self.vertical * t - self.origin - offset
-        )
+    /// Area of the lens, or one for a pinhole since it is a delta position.
+    fn lens_area(&self) -> f64 {
+        match self.lens_radius > 0. {
+            true => PI * self.lens_radius * self.lens_radius,
+            false => 1.,
+        }
+    }
+
+    /// Area of the film window, measured on the plane of focus.
+    fn film_area(&self) -> f64 {
+        self.horizontal.length() * self.vertical.length()
     }
 
     pub fn get_u(&self) -> Vec3 {
@@ -53,4 +131,77 @@ impl Camera {
     pub fn get_w(&self) -> Vec3 {
         self.w
     } 
-}
\ No newline at end of file
+}
+
+impl CameraModel for Camera {
+    fn get_ray(&self, s: f64, t: f64) -> Ray {
+        let rd = Vec3::rand_in_unit_disk() * self.lens_radius;
+        let offset = self.u * rd.x + self.v * rd.y;
+        Ray::new(
+            self.origin + offset, 
+            self.lower_left_corner + self.horizontal * s + self.vertical * t - self.origin - offset
+        )
+    }
+
+    fn forward(&self) -> Vec3 {
+        -self.w
+    }
+
+    fn is_connectible(&self) -> bool {
+        true
+    }
+
+    fn film_coords(&self, ray: Ray) -> Option<(f64, f64)> {
+        let cos_theta = Vec3::dot(ray.direction, -self.w);
+        if cos_theta <= 0. {
+            return None;
+        }
+
+        // Lens points lie in the (u, v) plane, so the focus plane is always
+        // `focus_dist` away along the viewing axis
+        let focus_point = ray.at(self.focus_dist / cos_theta);
+        let rel = focus_point - self.lower_left_corner;
+        let s = Vec3::dot(rel, self.horizontal) / self.horizontal.length_squared();
+        let t = Vec3::dot(rel, self.vertical) / self.vertical.length_squared();
+
+        match (0. ..1.).contains(&s) && (0. ..1.).contains(&t) {
+            true => Some((s, t)),
+            false => None,
+        }
+    }
+
+    fn pdf_we(&self, ray: Ray) -> (f64, f64) {
+        if self.film_coords(ray).is_none() {
+            return (0., 0.);
+        }
+
+        let cos_theta = Vec3::dot(ray.direction.normalized(), -self.w);
+        let pdf_pos = 1. / self.lens_area();
+        let pdf_dir = self.focus_dist * self.focus_dist / (self.film_area() * cos_theta.powi(3));
+        (pdf_pos, pdf_dir)
+    }
+
+    fn we(&self, ray: Ray) -> f64 {
+        let (pdf_pos, pdf_dir) = self.pdf_we(ray);
+        if pdf_dir == 0. {
+            return 0.;
+        }
+
+        let cos_theta = Vec3::dot(ray.direction.normalized(), -self.w);
+        pdf_pos * pdf_dir / cos_theta
+    }
+
+    fn sample_importance(&self, p: Point) -> Option<Importance> {
+        let rd = Vec3::rand_in_unit_disk() * self.lens_radius;
+        let lens_point = self.origin + self.u * rd.x + self.v * rd.y;
+
+        let to_point = p - lens_point;
+        let dist_squared = to_point.length_squared();
+        let ray = Ray::new(lens_point, to_point);
+        let (s, t) = self.film_coords(ray)?;
+
+        let cos_theta = Vec3::dot(to_point.normalized(), -self.w);
+        let pdf = dist_squared / (cos_theta * self.lens_area());
+        Some(Importance { s, t, lens_point, we: self.we(ray), pdf })
+    }
+}
@@ -0,0 +1,66 @@
+use trace_math::onb::Onb;
+use trace_math::ray::Ray;
+use trace_math::utils::math_utils;
+use trace_math::vec3::{Point, Vec3};
+
+use crate::camera::CameraModel;
+
+/// Equidistant fisheye: the angle from the viewing direction grows linearly
+/// with the distance from the image center, reaching half of `fov` on the
+/// edge of a circle inscribed in the film. Corners outside it stay black.
+pub struct FisheyeCamera {
+    origin: Point,
+    frame: Onb,
+    half_fov: f64,
+    aspect_ratio: f64,
+}
+
+impl FisheyeCamera {
+    /// Camera at `lookfrom` looking at `lookat`, with `fov` degrees across
+    /// the image circle, up to 360.
+    pub fn new(lookfrom: Point, lookat: Point, vup: Vec3, fov: f64, aspect_ratio: f64) -> FisheyeCamera {
+        let w = (lookfrom - lookat).normalized();
+        let u = Vec3::cross(vup, w).normalized();
+        let v = Vec3::cross(w, u);
+
+        FisheyeCamera {
+            origin: lookfrom,
+            frame: Onb { u, v, w },
+            half_fov: math_utils::degrees_to_radians(fov.clamp(0., 360.)) / 2.,
+            aspect_ratio,
+        }
+    }
+
+    /// Position on the film relative to the image circle, whose radius is one.
+    fn circle_coords(&self, s: f64, t: f64) -> (f64, f64) {
+        let (x, y) = (2. * s - 1., 2. * t - 1.);
+        match self.aspect_ratio >= 1. {
+            true => (x * self.aspect_ratio, y),
+            false => (x, y / self.aspect_ratio),
+        }
+    }
+}
+
+impl CameraModel for FisheyeCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Ray {
+        let (x, y) = self.circle_coords(s, t);
+        let r = (x * x + y * y).sqrt();
+        let theta = r * self.half_fov;
+        let (cos_phi, sin_phi) = match r > 0. {
+            true => (x / r, y / r),
+            false => (1., 0.),
+        };
+
+        let local = Vec3::new(theta.sin() * cos_phi, theta.sin() * sin_phi, -theta.cos());
+        Ray::new(self.origin, self.frame.local(local))
+    }
+
+    fn forward(&self) -> Vec3 {
+        -self.frame.w
+    }
+
+    fn covers(&self, s: f64, t: f64) -> bool {
+        let (x, y) = self.circle_coords(s, t);
+        x * x + y * y <= 1.
+    }
+}
@@ -0,0 +1,98 @@
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+use crate::realistic::LensElement;
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Loads a lens prescription table: one interface per line from the scene
+/// side, as curvature radius, thickness, IOR and aperture diameter, all
+/// lengths in millimeters. An IOR of 0 stands for air, as in pbrt's lens
+/// files, and `#` starts a comment.
+pub fn load_lens<P: AsRef<Path>>(path: P) -> Result<Vec<LensElement>> {
+    parse_lens(&fs::read_to_string(path)?)
+}
+
+pub fn parse_lens(text: &str) -> Result<Vec<LensElement>> {
+    let mut elements = vec![];
+    for line in text.lines() {
+        let line = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let values = line.split_whitespace()
+            .map(|token| token.parse::<f64>().map_err(|_| invalid("invalid number in lens prescription")))
+            .collect::<Result<Vec<f64>>>()?;
+        let [curvature_radius, thickness, ior, aperture] = values[..] else {
+            return Err(invalid("lens elements need a radius, thickness, IOR and aperture"));
+        };
+        // Written so that NaN fails every check; a zero radius is a flat stop
+        if !curvature_radius.is_finite() {
+            return Err(invalid("lens radius must be finite"));
+        }
+        if !(thickness >= 0. && thickness.is_finite()) {
+            return Err(invalid("lens thickness must be finite and non-negative"));
+        }
+        if !(ior >= 0. && ior.is_finite()) {
+            return Err(invalid("lens IOR must be finite and non-negative"));
+        }
+        if !(aperture > 0. && aperture.is_finite()) {
+            return Err(invalid("lens aperture must be finite and positive"));
+        }
+
+        elements.push(LensElement { curvature_radius, thickness, ior, aperture });
+    }
+
+    match elements.is_empty() {
+        true => Err(invalid("lens prescription has no elements")),
+        false => Ok(elements),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_prescription() {
+        let text = "# radius thickness ior aperture\n29.475 3.76 1.67 25.2\n\n0 1.5 0 17.1 # stop\n-80.2 2. 1.5 18\n";
+        let elements = parse_lens(text).unwrap();
+        assert_eq!(elements.len(), 3);
+        assert_eq!(elements[0].curvature_radius, 29.475);
+        assert_eq!(elements[0].thickness, 3.76);
+        assert_eq!(elements[0].ior, 1.67);
+        assert_eq!(elements[0].aperture, 25.2);
+        assert_eq!(elements[1].curvature_radius, 0.);
+        assert_eq!(elements[2].curvature_radius, -80.2);
+    }
+
+    #[test]
+    fn rejects_invalid_elements() {
+        assert!(parse_lens("").is_err());
+        assert!(parse_lens("# only a comment\n").is_err());
+        assert!(parse_lens("29.475 3.76 1.67").is_err());
+        assert!(parse_lens("29.475 3.76 1.67 abc").is_err());
+        assert!(parse_lens("29.475 -1 1.67 25.2").is_err());
+        assert!(parse_lens("29.475 3.76 -1 25.2").is_err());
+        assert!(parse_lens("29.475 3.76 1.67 0").is_err());
+    }
+
+    #[test]
+    fn rejects_non_finite_values() {
+        assert!(parse_lens("NaN 3.76 1.67 25.2").is_err());
+        assert!(parse_lens("inf 3.76 1.67 25.2").is_err());
+        assert!(parse_lens("29.475 NaN 1.67 25.2").is_err());
+        assert!(parse_lens("29.475 inf 1.67 25.2").is_err());
+        assert!(parse_lens("29.475 3.76 NaN 25.2").is_err());
+        assert!(parse_lens("29.475 3.76 inf 25.2").is_err());
+        assert!(parse_lens("29.475 3.76 1.67 NaN").is_err());
+        assert!(parse_lens("29.475 3.76 1.67 inf").is_err());
+    }
+}
@@ -1 +1,7 @@
 pub mod camera;
+pub mod orthographic;
+pub mod fisheye;
+pub mod spherical;
+pub mod stereo;
+pub mod realistic;
+pub mod lens;
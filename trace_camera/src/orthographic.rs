@@ -0,0 +1,39 @@
+use trace_math::ray::Ray;
+use trace_math::vec3::{Point, Vec3};
+
+use crate::camera::CameraModel;
+
+/// Parallel projection: every ray leaves the film plane along the viewing
+/// direction, so sizes don't shrink with distance.
+pub struct OrthographicCamera {
+    lower_left_corner: Point,
+    horizontal: Vec3,
+    vertical: Vec3,
+    w: Vec3,
+}
+
+impl OrthographicCamera {
+    /// Camera at `lookfrom` looking at `lookat`, whose film spans
+    /// `view_height` world units vertically.
+    pub fn new(lookfrom: Point, lookat: Point, vup: Vec3, view_height: f64, aspect_ratio: f64) -> OrthographicCamera {
+        let w = (lookfrom - lookat).normalized();
+        let u = Vec3::cross(vup, w).normalized();
+        let v = Vec3::cross(w, u);
+
+        let horizontal = u * view_height * aspect_ratio;
+        let vertical = v * view_height;
+        let lower_left_corner = lookfrom - horizontal / 2. - vertical / 2.;
+
+        OrthographicCamera { lower_left_corner, horizontal, vertical, w }
+    }
+}
+
+impl CameraModel for OrthographicCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Ray {
+        Ray::new(self.lower_left_corner + self.horizontal * s + self.vertical * t, -self.w)
+    }
+
+    fn forward(&self) -> Vec3 {
+        -self.w
+    }
+}
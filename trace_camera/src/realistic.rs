@@ -0,0 +1,373 @@
+use trace_math::microfacet::refract;
+use trace_math::onb::Onb;
+use trace_math::ray::Ray;
+use trace_math::utils::rand_utils;
+use trace_math::vec3::{Point, Vec3};
+
+use crate::camera::CameraModel;
+
+/// Number of film rings the exit pupil is precomputed for.
+const PUPIL_INTERVALS: usize = 64;
+
+/// Resolution of the grid of rear element points tried for each ring.
+const PUPIL_GRID: usize = 64;
+
+/// Attempts at finding a ray that makes it through the lens in `get_ray`.
+const GET_RAY_ATTEMPTS: usize = 64;
+
+/// One interface of a lens prescription, listed from the scene side towards
+/// the film. Lengths are in millimeters, as in lens design tables.
+#[derive(Debug, Clone, Copy)]
+pub struct LensElement {
+    /// Radius of curvature, positive when the center lies towards the film,
+    /// or zero for a flat interface such as the aperture stop.
+    pub curvature_radius: f64,
+    /// Distance along the axis to the next interface, or to the film.
+    pub thickness: f64,
+    /// Index of refraction of the medium after the interface, 1 for air.
+    pub ior: f64,
+    /// Diameter of the interface's clear aperture.
+    pub aperture: f64,
+}
+
+/// Interface in the lens' own space, in scene units: the film lies at
+/// z = 0 and the scene towards -z.
+#[derive(Debug, Clone, Copy)]
+struct Interface {
+    radius: f64,
+    z: f64,
+    ior: f64,
+    aperture_radius: f64,
+}
+
+impl Interface {
+    fn is_stop(&self) -> bool {
+        self.radius == 0.
+    }
+
+    /// Where `ray` crosses the interface, with the normal there facing
+    /// against the ray.
+    fn intersect(&self, ray: Ray) -> Option<(f64, Vec3)> {
+        if self.is_stop() {
+            let t = (self.z - ray.origin.z) / ray.direction.z;
+            return match t > 0. {
+                true => Some((t, Vec3::new(0., 0., -ray.direction.z.signum()))),
+                false => None,
+            };
+        }
+
+        let center = Point::new(0., 0., self.z + self.radius);
+        let oc = ray.origin - center;
+        let a = ray.direction.length_squared();
+        let half_b = Vec3::dot(oc, ray.direction);
+        let c = oc.length_squared() - self.radius * self.radius;
+        let discriminant = half_b * half_b - a * c;
+        if discriminant < 0. {
+            return None;
+        }
+
+        // The interface is the cap of the sphere closest to its vertex, which
+        // depends on the side the ray comes from
+        let root = discriminant.sqrt();
+        let (t0, t1) = ((-half_b - root) / a, (-half_b + root) / a);
+        let t = match (ray.direction.z > 0.) ^ (self.radius < 0.) {
+            true => t0.min(t1),
+            false => t0.max(t1),
+        };
+        if t <= 0. {
+            return None;
+        }
+
+        let normal = (ray.at(t) - center).normalized();
+        let normal = match Vec3::dot(normal, ray.direction) > 0. {
+            true => -normal,
+            false => normal,
+        };
+        Some((t, normal))
+    }
+}
+
+/// Camera tracing rays through a real lens prescription, which reproduces
+/// its depth of field, bokeh, vignetting and distortion. Rays are aimed at
+/// the exit pupil, the part of the rear element that light from the film
+/// can get through, which is precomputed for rings of the film.
+pub struct RealisticCamera {
+    origin: Point,
+    frame: Onb,
+    interfaces: Vec<Interface>,
+    film_width: f64,
+    film_height: f64,
+    /// Bounds of the exit pupil on the rear element's plane, for film points
+    /// along +x in each ring: min x, min y, max x, max y.
+    pupil_bounds: Vec<[f64; 4]>,
+    /// Area of each ring's pupil bounds relative to the center ring's, as
+    /// rays sampled over larger bounds stand for more light.
+    pupil_areas: Vec<f64>,
+}
+
+impl RealisticCamera {
+    /// Camera with its film at `lookfrom`, looking at `lookat`. The stop is
+    /// closed down to `aperture_diameter` if smaller than in the prescription,
+    /// and `film_diagonal` (43.27 for full frame) is in millimeters too.
+    /// The lens is moved so that objects `focus_distance` away from the film
+    /// are in focus.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        lookfrom: Point,
+        lookat: Point,
+        vup: Vec3,
+        elements: &[LensElement],
+        aperture_diameter: f64,
+        focus_distance: f64,
+        film_diagonal: f64,
+        aspect_ratio: f64,
+        ) -> RealisticCamera
+    {
+        assert!(!elements.is_empty(), "lens prescription has no elements");
+
+        let w = (lookfrom - lookat).normalized();
+        let u = Vec3::cross(vup, w).normalized();
+        let v = Vec3::cross(w, u);
+
+        let diagonal = film_diagonal * 0.001;
+        let film_height = diagonal / (1. + aspect_ratio * aspect_ratio).sqrt();
+        let film_width = film_height * aspect_ratio;
+
+        let mut camera = RealisticCamera {
+            origin: lookfrom,
+            frame: Onb { u, v, w },
+            interfaces: RealisticCamera::layout(elements, aperture_diameter),
+            film_width,
+            film_height,
+            pupil_bounds: vec![],
+            pupil_areas: vec![],
+        };
+        camera.focus(focus_distance);
+        camera.pupil_bounds = (0..PUPIL_INTERVALS).map(|i| camera.bound_exit_pupil(i)).collect();
+
+        let areas: Vec<f64> = camera.pupil_bounds.iter()
+            .map(|&[x0, y0, x1, y1]| match x0 <= x1 {
+                true => (x1 - x0) * (y1 - y0),
+                false => 0.,
+            })
+            .collect();
+        let center = match areas[0] > 0. {
+            true => areas[0],
+            false => areas.iter().cloned().fold(0., f64::max).max(f64::MIN_POSITIVE),
+        };
+        camera.pupil_areas = areas.iter().map(|area| area / center).collect();
+        camera
+    }
+
+    /// Converts the prescription to scene units and places its interfaces.
+    fn layout(elements: &[LensElement], aperture_diameter: f64) -> Vec<Interface> {
+        let mut z = 0.;
+        let mut interfaces: Vec<Interface> = elements.iter()
+            .rev()
+            .map(|element| {
+                z -= element.thickness * 0.001;
+                let aperture = match element.curvature_radius == 0. {
+                    true => element.aperture.min(aperture_diameter),
+                    false => element.aperture,
+                };
+                Interface {
+                    radius: element.curvature_radius * 0.001,
+                    z,
+                    ior: match element.ior > 0. {
+                        true => element.ior,
+                        false => 1.,
+                    },
+                    aperture_radius: aperture * 0.0005,
+                }
+            })
+            .collect();
+        interfaces.reverse();
+        interfaces
+    }
+
+    /// Traces `ray` from the film side out through the lens, `None` if it is
+    /// blocked by an aperture or totally reflected inside.
+    fn trace_from_film(&self, ray: Ray) -> Option<Ray> {
+        let mut ray = ray;
+        for i in (0..self.interfaces.len()).rev() {
+            let interface = self.interfaces[i];
+            let outside = match i {
+                0 => 1.,
+                _ => self.interfaces[i - 1].ior,
+            };
+            ray = self.cross(ray, interface, interface.ior, outside)?;
+        }
+        Some(ray)
+    }
+
+    /// Traces `ray` from the scene side in towards the film.
+    fn trace_from_scene(&self, ray: Ray) -> Option<Ray> {
+        let mut ray = ray;
+        for i in 0..self.interfaces.len() {
+            let interface = self.interfaces[i];
+            let before = match i {
+                0 => 1.,
+                _ => self.interfaces[i - 1].ior,
+            };
+            ray = self.cross(ray, interface, before, interface.ior)?;
+        }
+        Some(ray)
+    }
+
+    /// Moves `ray` to `interface`, refracting it from a medium of IOR
+    /// `eta_i` into one of `eta_t`.
+    fn cross(&self, ray: Ray, interface: Interface, eta_i: f64, eta_t: f64) -> Option<Ray> {
+        let (t, normal) = interface.intersect(ray)?;
+        let point = ray.at(t);
+        if point.x * point.x + point.y * point.y > interface.aperture_radius * interface.aperture_radius {
+            return None;
+        }
+        if interface.is_stop() {
+            return Some(Ray::new(point, ray.direction));
+        }
+
+        let direction = refract(-ray.direction.normalized(), normal, eta_t / eta_i)?;
+        Some(Ray::new(point, direction))
+    }
+
+    /// Focal point and principal plane, along the axis, of the rays coming
+    /// out of the lens parallel to it on the other side at height `x`.
+    fn cardinal_points(x: f64, ray_out: Ray) -> Option<(f64, f64)> {
+        if ray_out.direction.x == 0. {
+            return None;
+        }
+
+        let focal = ray_out.at(-ray_out.origin.x / ray_out.direction.x).z;
+        let principal = ray_out.at((x - ray_out.origin.x) / ray_out.direction.x).z;
+        Some((focal, principal))
+    }
+
+    /// Moves the lens along its axis so that objects `focus_distance` away
+    /// from the film are sharp, using its thick lens approximation.
+    fn focus(&mut self, focus_distance: f64) {
+        let x = 0.001 * (self.film_width * self.film_width + self.film_height * self.film_height).sqrt();
+        let front = self.interfaces[0].z;
+
+        let from_scene = self.trace_from_scene(Ray::new(Point::new(x, 0., front - 1.), Vec3::new(0., 0., 1.)));
+        let from_film = self.trace_from_film(Ray::new(Point::new(x, 0., 0.), Vec3::new(0., 0., -1.)));
+        let (image_focal, image_principal) = match from_scene.and_then(|r| RealisticCamera::cardinal_points(x, r)) {
+            Some(points) => points,
+            None => return,
+        };
+        let object_principal = match from_film.and_then(|r| RealisticCamera::cardinal_points(x, r)) {
+            Some((_, principal)) => principal,
+            None => return,
+        };
+
+        // Object and image distances from their principal planes keep the
+        // same sum as the lens moves, and must satisfy 1/a + 1/b = 1/f
+        let f = image_focal - image_principal;
+        let total = focus_distance + object_principal - image_principal;
+        if f <= 0. || total * total < 4. * f * total {
+            return;
+        }
+        let image_distance = (total - (total * total - 4. * f * total).sqrt()) / 2.;
+        let shift = image_distance + image_principal;
+
+        for interface in self.interfaces.iter_mut() {
+            interface.z -= shift;
+        }
+    }
+
+    /// Radius of the rear element and the plane it lies in.
+    fn rear(&self) -> (f64, f64) {
+        let rear = self.interfaces[self.interfaces.len() - 1];
+        (rear.aperture_radius, rear.z)
+    }
+
+    /// Bounds of the rear element's points through which film points of ring
+    /// `interval`, taken along +x, see out of the lens.
+    fn bound_exit_pupil(&self, interval: usize) -> [f64; 4] {
+        let film_radius = (self.film_width * self.film_width + self.film_height * self.film_height).sqrt() / 2.;
+        let (r0, r1) = (
+            film_radius * interval as f64 / PUPIL_INTERVALS as f64,
+            film_radius * (interval + 1) as f64 / PUPIL_INTERVALS as f64,
+        );
+
+        // Pupils can reach a bit past the rear element's own aperture
+        let (rear_radius, rear_z) = self.rear();
+        let extent = 1.5 * rear_radius;
+        let step = 2. * extent / PUPIL_GRID as f64;
+
+        let mut bounds = [f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY];
+        for i in 0..PUPIL_GRID {
+            for j in 0..PUPIL_GRID {
+                let film_x = r0 + (r1 - r0) * ((i * PUPIL_GRID + j) as f64 + 0.5) / (PUPIL_GRID * PUPIL_GRID) as f64;
+                let rear_x = -extent + (i as f64 + 0.5) * step;
+                let rear_y = -extent + (j as f64 + 0.5) * step;
+
+                let film = Point::new(film_x, 0., 0.);
+                let ray = Ray::new(film, Point::new(rear_x, rear_y, rear_z) - film);
+                if self.trace_from_film(ray).is_some() {
+                    bounds = [bounds[0].min(rear_x), bounds[1].min(rear_y), bounds[2].max(rear_x), bounds[3].max(rear_y)];
+                }
+            }
+        }
+
+        match bounds[0] <= bounds[2] {
+            true => [bounds[0] - step, bounds[1] - step, bounds[2] + step, bounds[3] + step],
+            false => bounds,
+        }
+    }
+
+    /// Samples a point of the exit pupil for a film point, on the rear
+    /// element's plane, with the relative area of the bounds it was sampled
+    /// in, or `None` if no light reaches it.
+    fn sample_exit_pupil(&self, film_x: f64, film_y: f64) -> Option<(Point, f64)> {
+        let film_radius = (self.film_width * self.film_width + self.film_height * self.film_height).sqrt() / 2.;
+        let r = (film_x * film_x + film_y * film_y).sqrt();
+        let interval = ((r / film_radius * PUPIL_INTERVALS as f64) as usize).min(PUPIL_INTERVALS - 1);
+        let [x0, y0, x1, y1] = self.pupil_bounds[interval];
+        if x0 > x1 {
+            return None;
+        }
+
+        // Bounds were found along +x, so rotate them to the film point
+        let x = x0 + (x1 - x0) * rand_utils::rand_f64();
+        let y = y0 + (y1 - y0) * rand_utils::rand_f64();
+        let (cos_phi, sin_phi) = match r > 0. {
+            true => (film_x / r, film_y / r),
+            false => (1., 0.),
+        };
+        let point = Point::new(cos_phi * x - sin_phi * y, sin_phi * x + cos_phi * y, self.rear().1);
+        Some((point, self.pupil_areas[interval]))
+    }
+
+    /// Ray leaving the lens for film coordinates `(s, t)` and its weight,
+    /// the cos⁴ falloff times the relative area of the pupil bounds it went
+    /// through, or `None` if the sampled path was blocked.
+    fn sample_ray(&self, s: f64, t: f64) -> Option<(Ray, f64)> {
+        // The lens flips the image, so the film is flipped to match
+        let film = Point::new((0.5 - s) * self.film_width, (0.5 - t) * self.film_height, 0.);
+        let (rear, pupil_area) = self.sample_exit_pupil(film.x, film.y)?;
+        let ray = self.trace_from_film(Ray::new(film, rear - film))?;
+
+        let cos_theta = (rear - film).normalized().z.abs();
+        let ray = Ray::new(self.origin + self.frame.local(ray.origin), self.frame.local(ray.direction));
+        Some((ray, cos_theta.powi(4) * pupil_area))
+    }
+}
+
+impl CameraModel for RealisticCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Ray {
+        // Callers that can't weight rays get the first one that makes it
+        // through, and the lens axis if none does
+        (0..GET_RAY_ATTEMPTS)
+            .find_map(|_| self.sample_ray(s, t))
+            .map(|(ray, _)| ray)
+            .unwrap_or_else(|| Ray::new(self.origin, -self.frame.w))
+    }
+
+    fn forward(&self) -> Vec3 {
+        -self.frame.w
+    }
+
+    fn generate_ray(&self, s: f64, t: f64) -> Option<(Ray, f64)> {
+        self.sample_ray(s, t)
+    }
+}
@@ -0,0 +1,128 @@
+use std::f64::consts::PI;
+
+use trace_math::onb::Onb;
+use trace_math::ray::Ray;
+use trace_math::vec3::{Point, Vec3};
+
+use crate::camera::CameraModel;
+
+/// How a full sphere of directions is laid out on the film.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SphericalMapping {
+    /// Longitude across and latitude up, with the viewing direction in the
+    /// middle; meant for 2:1 images.
+    Equirectangular,
+    /// Six 90 degree faces in a 3:2 grid: right, left and up on the top row,
+    /// then down, front and back.
+    Cubemap,
+}
+
+/// Camera seeing in every direction from a single point, e.g. to render
+/// 360 degree panoramas or environment maps.
+pub struct SphericalCamera {
+    origin: Point,
+    frame: Onb,
+    mapping: SphericalMapping,
+}
+
+impl SphericalCamera {
+    pub fn new(lookfrom: Point, lookat: Point, vup: Vec3, mapping: SphericalMapping) -> SphericalCamera {
+        let w = (lookfrom - lookat).normalized();
+        let u = Vec3::cross(vup, w).normalized();
+        let v = Vec3::cross(w, u);
+
+        SphericalCamera { origin: lookfrom, frame: Onb { u, v, w }, mapping }
+    }
+
+    /// Direction seen at film coordinates `(s, t)`, in the camera's frame
+    /// (right along +x, up along +y, looking down -z).
+    fn local_direction(&self, s: f64, t: f64) -> Vec3 {
+        match self.mapping {
+            SphericalMapping::Equirectangular => {
+                let phi = (s - 0.5) * 2. * PI;
+                let elevation = (t - 0.5) * PI;
+                Vec3::new(elevation.cos() * phi.sin(), elevation.sin(), -elevation.cos() * phi.cos())
+            },
+            SphericalMapping::Cubemap => {
+                let (x, y) = ((s * 3.).clamp(0., 2.999999), (t * 2.).clamp(0., 1.999999));
+                let (a, b) = (2. * x.fract() - 1., 2. * y.fract() - 1.);
+
+                // Each face looks along `forward`, with `right` and `up` in its image
+                let (forward, right, up) = match (x as usize, y as usize) {
+                    (0, 1) => (Vec3::new(1., 0., 0.), Vec3::new(0., 0., 1.), Vec3::new(0., 1., 0.)),
+                    (1, 1) => (Vec3::new(-1., 0., 0.), Vec3::new(0., 0., -1.), Vec3::new(0., 1., 0.)),
+                    (2, 1) => (Vec3::new(0., 1., 0.), Vec3::new(1., 0., 0.), Vec3::new(0., 0., 1.)),
+                    (0, _) => (Vec3::new(0., -1., 0.), Vec3::new(1., 0., 0.), Vec3::new(0., 0., -1.)),
+                    (1, _) => (Vec3::new(0., 0., -1.), Vec3::new(1., 0., 0.), Vec3::new(0., 1., 0.)),
+                    _ => (Vec3::new(0., 0., 1.), Vec3::new(-1., 0., 0.), Vec3::new(0., 1., 0.)),
+                };
+                forward + right * a + up * b
+            },
+        }
+    }
+}
+
+impl CameraModel for SphericalCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Ray {
+        Ray::new(self.origin, self.frame.local(self.local_direction(s, t)))
+    }
+
+    fn forward(&self) -> Vec3 {
+        -self.frame.w
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn direction(camera: &SphericalCamera, s: f64, t: f64) -> Vec3 {
+        camera.get_ray(s, t).direction.normalized()
+    }
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b.normalized()).length() < 1e-5, "{:?} vs {:?}", a, b);
+    }
+
+    fn cubemap() -> SphericalCamera {
+        SphericalCamera::new(Point::zero(), Point::new(0., 0., -1.), Vec3::new(0., 1., 0.), SphericalMapping::Cubemap)
+    }
+
+    #[test]
+    fn cubemap_face_centers() {
+        let camera = cubemap();
+        let center = |i: usize, j: usize| direction(&camera, (i as f64 + 0.5) / 3., (j as f64 + 0.5) / 2.);
+        assert_close(center(0, 1), Vec3::new(1., 0., 0.));
+        assert_close(center(1, 1), Vec3::new(-1., 0., 0.));
+        assert_close(center(2, 1), Vec3::new(0., 1., 0.));
+        assert_close(center(0, 0), Vec3::new(0., -1., 0.));
+        assert_close(center(1, 0), Vec3::new(0., 0., -1.));
+        assert_close(center(2, 0), Vec3::new(0., 0., 1.));
+    }
+
+    #[test]
+    fn cubemap_faces_meet_at_their_edges() {
+        let camera = cubemap();
+        let eps = 1e-7;
+
+        // Front's right edge is the right face's left edge
+        assert_close(direction(&camera, 2. / 3. - eps, 0.25), Vec3::new(1., 0., -1.));
+        assert_close(direction(&camera, eps, 0.75), Vec3::new(1., 0., -1.));
+
+        // Front's top edge is the up face's bottom edge, in the same order
+        assert_close(direction(&camera, 1.2 / 3., 0.5 - eps), Vec3::new(-0.6, 1., -1.));
+        assert_close(direction(&camera, 2.2 / 3., 0.5 + eps), Vec3::new(-0.6, 1., -1.));
+
+        // Front's bottom edge is the down face's top edge
+        assert_close(direction(&camera, 0.5, eps), Vec3::new(0., -1., -1.));
+        assert_close(direction(&camera, 1. / 6., 0.5 - eps), Vec3::new(0., -1., -1.));
+    }
+
+    #[test]
+    fn equirectangular_is_centered_on_the_view() {
+        let camera = SphericalCamera::new(Point::zero(), Point::new(0., 0., -1.), Vec3::new(0., 1., 0.), SphericalMapping::Equirectangular);
+        assert_close(direction(&camera, 0.5, 0.5), Vec3::new(0., 0., -1.));
+        assert_close(direction(&camera, 0.75, 0.5), Vec3::new(1., 0., 0.));
+        assert_close(direction(&camera, 0.3, 1.), Vec3::new(0., 1., 0.));
+    }
+}
@@ -0,0 +1,176 @@
+use std::f64::consts::PI;
+
+use trace_math::onb::Onb;
+use trace_math::ray::Ray;
+use trace_math::vec3::{Point, Vec3};
+
+use crate::camera::CameraModel;
+
+/// Elevation, in degrees, above which the eyes move closer together so
+/// that the stereo pair merges into a single view at the poles.
+const POLE_FADE_START: f64 = 60.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+/// One eye of an omni-directional stereo (ODS) camera: an equirectangular
+/// panorama where every direction is seen from an eye on a circle of
+/// `ipd` diameter, facing it sideways, as a head turning around would.
+///
+/// Rays of both eyes meet at `convergence` distance in front of the
+/// viewer; an infinite distance keeps them parallel.
+pub struct OdsCamera {
+    origin: Point,
+    frame: Onb,
+    eye: Eye,
+    ipd: f64,
+    convergence: f64,
+}
+
+impl OdsCamera {
+    /// Eye of a viewer at `lookfrom`, with the panorama centered on `lookat`
+    /// and eyes `ipd` apart (about 0.064 for a human head in meters).
+    pub fn new(lookfrom: Point, lookat: Point, vup: Vec3, eye: Eye, ipd: f64, convergence: f64) -> OdsCamera {
+        let w = (lookfrom - lookat).normalized();
+        let u = Vec3::cross(vup, w).normalized();
+        let v = Vec3::cross(w, u);
+
+        OdsCamera { origin: lookfrom, frame: Onb { u, v, w }, eye, ipd, convergence }
+    }
+}
+
+impl CameraModel for OdsCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Ray {
+        let phi = (s - 0.5) * 2. * PI;
+        let elevation = (t - 0.5) * PI;
+        let direction = Vec3::new(elevation.cos() * phi.sin(), elevation.sin(), -elevation.cos() * phi.cos());
+
+        let fade = ((90. - elevation.abs().to_degrees()) / (90. - POLE_FADE_START)).clamp(0., 1.);
+        let side = match self.eye {
+            Eye::Left => -1.,
+            Eye::Right => 1.,
+        };
+        let offset = Vec3::new(phi.cos(), 0., phi.sin()) * (side * fade * self.ipd / 2.);
+
+        let direction = match self.convergence.is_finite() {
+            true => direction * self.convergence - offset,
+            false => direction,
+        };
+        Ray::new(self.origin + self.frame.local(offset), self.frame.local(direction))
+    }
+
+    fn forward(&self) -> Vec3 {
+        -self.frame.w
+    }
+}
+
+/// Stereo pair laid out top-bottom on a single film, left eye on top, as
+/// expected by VR players for 360 degree stereo images.
+pub struct StereoCamera {
+    left: Box<dyn CameraModel>,
+    right: Box<dyn CameraModel>,
+}
+
+impl StereoCamera {
+    pub fn new(left: Box<dyn CameraModel>, right: Box<dyn CameraModel>) -> StereoCamera {
+        StereoCamera { left, right }
+    }
+
+    /// Top-bottom ODS panorama, for a 1:1 image made of two 2:1 ones.
+    pub fn ods(lookfrom: Point, lookat: Point, vup: Vec3, ipd: f64, convergence: f64) -> StereoCamera {
+        StereoCamera::new(
+            Box::new(OdsCamera::new(lookfrom, lookat, vup, Eye::Left, ipd, convergence)),
+            Box::new(OdsCamera::new(lookfrom, lookat, vup, Eye::Right, ipd, convergence)),
+        )
+    }
+
+    /// Eye seeing film coordinate `t`, and the coordinate on its own half.
+    fn eye(&self, t: f64) -> (&dyn CameraModel, f64) {
+        match t >= 0.5 {
+            true => (self.left.as_ref(), 2. * t - 1.),
+            false => (self.right.as_ref(), 2. * t),
+        }
+    }
+}
+
+impl CameraModel for StereoCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Ray {
+        let (eye, t) = self.eye(t);
+        eye.get_ray(s, t)
+    }
+
+    fn forward(&self) -> Vec3 {
+        self.left.forward()
+    }
+
+    fn covers(&self, s: f64, t: f64) -> bool {
+        let (eye, t) = self.eye(t);
+        eye.covers(s, t)
+    }
+
+    fn generate_ray(&self, s: f64, t: f64) -> Option<(Ray, f64)> {
+        let (eye, t) = self.eye(t);
+        eye.generate_ray(s, t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IPD: f64 = 0.064;
+
+    fn eye(eye: Eye, convergence: f64) -> OdsCamera {
+        OdsCamera::new(Point::zero(), Point::new(0., 0., -1.), Vec3::new(0., 1., 0.), eye, IPD, convergence)
+    }
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-9, "{:?} vs {:?}", a, b);
+    }
+
+    #[test]
+    fn eyes_sit_sideways_on_the_viewing_circle() {
+        let (left, right) = (eye(Eye::Left, f64::INFINITY), eye(Eye::Right, f64::INFINITY));
+
+        // Looking forward, the right eye is to the right
+        assert_close(left.get_ray(0.5, 0.5).origin, Vec3::new(-IPD / 2., 0., 0.));
+        assert_close(right.get_ray(0.5, 0.5).origin, Vec3::new(IPD / 2., 0., 0.));
+
+        // Looking along +x, right is +z
+        assert_close(right.get_ray(0.75, 0.5).origin, Vec3::new(0., 0., IPD / 2.));
+        assert_close(left.get_ray(0.75, 0.5).origin, Vec3::new(0., 0., -IPD / 2.));
+
+        // Parallel rays without convergence
+        assert_close(left.get_ray(0.3, 0.6).direction, right.get_ray(0.3, 0.6).direction);
+    }
+
+    #[test]
+    fn eyes_merge_at_the_poles() {
+        let right = eye(Eye::Right, f64::INFINITY);
+        assert_close(right.get_ray(0.2, 1.).origin, Point::zero());
+        assert_close(right.get_ray(0.7, 0.).origin, Point::zero());
+
+        // Half way through the fade the eyes are half as far apart
+        let t = 0.5 + 75. / 180.;
+        assert!((right.get_ray(0.5, t).origin.x - IPD / 4.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rays_meet_at_the_convergence_distance() {
+        let (left, right) = (eye(Eye::Left, 2.), eye(Eye::Right, 2.));
+        for (s, t) in [(0.5, 0.5), (0.8, 0.55), (0.1, 0.4)] {
+            assert_close(left.get_ray(s, t).at(1.), right.get_ray(s, t).at(1.));
+        }
+        assert_close(left.get_ray(0.5, 0.5).at(1.), Vec3::new(0., 0., -2.));
+    }
+
+    #[test]
+    fn stereo_pair_puts_the_left_eye_on_top() {
+        let pair = StereoCamera::ods(Point::zero(), Point::new(0., 0., -1.), Vec3::new(0., 1., 0.), IPD, f64::INFINITY);
+        assert_close(pair.get_ray(0.5, 0.75).origin, Vec3::new(-IPD / 2., 0., 0.));
+        assert_close(pair.get_ray(0.5, 0.25).origin, Vec3::new(IPD / 2., 0., 0.));
+    }
+}
@@ -1,11 +1,20 @@
 use std::env;
+use std::fmt::Display;
+use std::process;
 use simple_bar::ProgressBar;
 
-use trace_utils;
 use trace_math::vec3::{Point, RGBColor, Vec3};
+use trace_math::environment::Environment;
 use trace_math::hittable::HittableList;
-use trace_math::utils::rand_utils;
-use trace_camera::camera::Camera;
+use trace_camera::camera::{Camera, CameraModel};
+use trace_camera::fisheye::FisheyeCamera;
+use trace_camera::orthographic::OrthographicCamera;
+use trace_camera::realistic::RealisticCamera;
+use trace_camera::spherical::{SphericalCamera, SphericalMapping};
+use trace_camera::stereo::StereoCamera;
+use trace_render::film::Film;
+use trace_render::renderer;
+use trace_render::settings::{FogSettings, IntegratorKind, RenderSettings};
 
 use trace_math::sphere::Sphere;
 use trace_math::material;
@@ -14,22 +23,46 @@ const RATIO_WIDTH: f64 = 3.;
 const RATIO_HEIGHT: f64 = 2.;
 const DEFAULT_HEIGHT: u32 = 200;
 const SAMPLES_PER_PIXEL: u32 = 20;
-const MAX_DEPTH: i32 = 50;
+/// Distance between the eyes for stereo cameras, in scene units.
+const IPD: f64 = 0.064;
+/// Film size for lens cameras, in millimeters (full frame).
+const FILM_DIAGONAL: f64 = 43.27;
 
 fn main() {
     // Image Preparations
     let args: Vec<String> = env::args().collect();
     
-    let aspect_ratio = if args.len() < 3 {
+    let camera_kind = args.get(5).map(|s| s.as_str());
+
+    // Top-bottom stereo panoramas stack two 2:1 images
+    let aspect_ratio = if camera_kind == Some("ods") {
+        1.
+    } else if args.len() < 3 {
         RATIO_WIDTH / RATIO_HEIGHT
     } else {
         args[1].parse::<f64>().unwrap() / args[2].parse::<f64>().unwrap()
     };
 
+    let integrator_kind = match args.get(3) {
+        Some(name) => IntegratorKind::from_name(name).unwrap_or_else(|| exit_with(format!("unknown integrator '{}'", name))),
+        None => IntegratorKind::default(),
+    };
+
+    let environment = match args.get(4) {
+        Some(spec) => trace_utils::environment::parse_environment(spec).unwrap_or_else(|e| exit_with(e)),
+        None => Environment::default(),
+    };
+
     let height = DEFAULT_HEIGHT;
     let width = (height as f64 * aspect_ratio) as u32;
 
-    let mut out_img = trace_utils::initialize_ppm(width, height);
+    let mut settings = RenderSettings::new(width, height, SAMPLES_PER_PIXEL, integrator_kind);
+    if let Some(spec) = args.get(6) {
+        let fog = FogSettings::from_spec(spec).unwrap_or_else(|| exit_with(format!("invalid fog '{}'", spec)));
+        settings = settings.with_fog(fog);
+    }
+    let mut integrator = settings.integrator.build();
+    let mut film = Film::new(width, height);
 
     // Scene
     //let scene = HittableList::rand_scene();
@@ -39,16 +72,24 @@ fn main() {
     let mat_right = Box::new(material::Metal::new(RGBColor::new(0.7, 0.7, 0.4), 0.0));
     let mat_left = Box::new(material::Dielectric::new(1.5));
     let mat_center = Box::new(material::Lambertian::new(RGBColor::new(0.1, 0.1, 0.8)));
+    let mat_light = Box::new(material::DiffuseLight::new(RGBColor::new(20.0, 18.0, 14.0)));
 
     let sphere_ground = Box::new(Sphere::new(Point::new(0.0, -100.5, -1.0), 100., mat_ground));
     let sphere_center = Box::new(Sphere::new(Point::new(0.0, 0.0, -1.0), 0.5, mat_center));
     let sphere_left = Box::new(Sphere::new(Point::new(-1.0, 0.0, -1.0), 0.5, mat_left));
     let sphere_right = Box::new(Sphere::new(Point::new(1.0, 0.0, -1.0), 0.5, mat_right));
+    let sphere_light = Box::new(Sphere::new(Point::new(0.0, 1.5, -0.5), 0.15, mat_light));
 
     scene.add(sphere_ground);
     scene.add(sphere_center);
     scene.add(sphere_left);
     scene.add(sphere_right);
+    scene.add_light(sphere_light);
+
+    scene.set_environment(environment);
+    if let Some(fog) = &settings.fog {
+        scene.set_fog(fog.build());
+    }
 
     // Camera
 
@@ -58,48 +99,49 @@ fn main() {
     let dist_to_focus = 10.0;
     let aperture = 0.1;
 
-    let camera = Camera::new(
-        lookfrom,
-        lookat,
-        vup,
-        20.0,
-        aspect_ratio,
-        aperture,
-        dist_to_focus
-    );
+    let camera: Box<dyn CameraModel> = match camera_kind {
+        None | Some("perspective") => Box::new(Camera::new(
+            lookfrom,
+            lookat,
+            vup,
+            20.0,
+            aspect_ratio,
+            aperture,
+            dist_to_focus
+        )),
+        Some("orthographic") => Box::new(OrthographicCamera::new(lookfrom, lookat, vup, 1.5, aspect_ratio)),
+        Some("fisheye") => Box::new(FisheyeCamera::new(lookfrom, lookat, vup, 180.0, aspect_ratio)),
+        Some("equirect") => Box::new(SphericalCamera::new(lookfrom, lookat, vup, SphericalMapping::Equirectangular)),
+        Some("cubemap") => Box::new(SphericalCamera::new(lookfrom, lookat, vup, SphericalMapping::Cubemap)),
+        Some("ods") => Box::new(StereoCamera::ods(lookfrom, lookat, vup, IPD, f64::INFINITY)),
+        Some(kind) if kind.starts_with("lens:") => {
+            let path = &kind["lens:".len()..];
+            let elements = trace_camera::lens::load_lens(path).unwrap_or_else(|e| exit_with(format!("lens '{}': {}", path, e)));
+            let focus_distance = (lookat - lookfrom).length();
+            Box::new(RealisticCamera::new(lookfrom, lookat, vup, &elements, f64::INFINITY, focus_distance, FILM_DIAGONAL, aspect_ratio))
+        },
+        Some(kind) => exit_with(format!("unknown camera '{}'", kind)),
+    };
 
     // Progress Bar
-    let mut bar = ProgressBar::cargo_style(height, 80);
+    let mut bar = ProgressBar::cargo_style(height * settings.samples_per_pixel, 80);
 
     // Rendering
-    for i in (0..height).rev() {
-        bar.next();
-        render_row(width, height, i, &scene, &camera, &mut out_img);
+    scene.prepare();
+    for pass in 0..settings.samples_per_pixel {
+        integrator.begin_pass(pass, &scene);
+        for y in 0..height {
+            bar.next();
+            renderer::render_row(y, &scene, camera.as_ref(), integrator.as_ref(), &settings, &mut film);
+        }
     }
     println!();
 
-    trace_utils::save_ppm(out_img).unwrap();
+    trace_utils::save_ppm(film.to_ppm(settings.samples_per_pixel)).unwrap();
 }
 
-fn render_row(
-    width: u32,
-    height: u32,
-    i: u32,
-    scene: &HittableList,
-    camera: &Camera,
-    out_img: &mut String
-    )
-{
-    for j in 0..width {
-        let mut pixel_color = RGBColor::zero();
-        for _ in 0..SAMPLES_PER_PIXEL {
-            let (u, v) = (
-                (rand_utils::rand_f64() + j as f64) / (width as f64 - 1.),
-                (rand_utils::rand_f64() + i as f64) / (height as f64 - 1.)
-            );
-            let r = camera.get_ray(u, v);
-            pixel_color += r.ray_color(&scene, MAX_DEPTH);
-        }
-        trace_utils::add_pixel_to_ppm(out_img, pixel_color, SAMPLES_PER_PIXEL);
-    }
+/// Reports an invalid command line argument and exits.
+fn exit_with(msg: impl Display) -> ! {
+    eprintln!("error: {}", msg);
+    process::exit(2);
 }
@@ -0,0 +1,63 @@
+use crate::ray::Ray;
+use crate::vec3::Point;
+
+/// Axis-aligned bounding box.
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub min: Point,
+    pub max: Point,
+}
+
+impl Aabb {
+    pub fn new(a: Point, b: Point) -> Aabb {
+        Aabb {
+            min: Point::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+            max: Point::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+        }
+    }
+
+    /// Parametric range of `r` inside the box, clipped to `[t_min, t_max]`.
+    pub fn hit_range(&self, r: Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let (mut t0, mut t1) = (t_min, t_max);
+        let axes = [
+            (r.origin.x, r.direction.x, self.min.x, self.max.x),
+            (r.origin.y, r.direction.y, self.min.y, self.max.y),
+            (r.origin.z, r.direction.z, self.min.z, self.max.z),
+        ];
+
+        for (origin, direction, min, max) in axes {
+            let inv_d = 1. / direction;
+            let mut t_near = (min - origin) * inv_d;
+            let mut t_far = (max - origin) * inv_d;
+            if inv_d < 0. {
+                std::mem::swap(&mut t_near, &mut t_far);
+            }
+            // NaN (origin on a slab with a parallel ray) leaves the range as is
+            if t_near > t0 {
+                t0 = t_near;
+            }
+            if t_far < t1 {
+                t1 = t_far;
+            }
+            if t1 <= t0 {
+                return None;
+            }
+        }
+
+        Some((t0, t1))
+    }
+
+    /// Position of `p` relative to the box, (0, 0, 0) at `min` and (1, 1, 1) at `max`.
+    pub fn offset(&self, p: Point) -> Point {
+        let extent = self.max - self.min;
+        let o = p - self.min;
+        Point::new(o.x / extent.x, o.y / extent.y, o.z / extent.z)
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Point::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y), self.min.z.min(other.min.z)),
+            max: Point::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y), self.max.z.max(other.max.z)),
+        }
+    }
+}
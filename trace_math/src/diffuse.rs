@@ -0,0 +1,179 @@
+use std::f64::consts::PI;
+
+use crate::hit_info::HitInfo;
+use crate::material::{Lobe, Material, ScatterRecord};
+use crate::microfacet;
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::spectrum;
+use crate::texture::Texture;
+use crate::vec3::{RGBColor, Vec3};
+
+/// Outgoing and incoming directions in the shading frame of the hit.
+fn local_directions(ray: Ray, hit_info: &HitInfo, scattered: Vec3) -> (Vec3, Vec3) {
+    let frame = Onb::from_w(hit_info.get_normal());
+    (frame.to_local(-ray.direction.normalized()), frame.to_local(scattered.normalized()))
+}
+
+fn texture_at(texture: &dyn Texture, hit_info: &HitInfo) -> RGBColor {
+    let (u, v) = hit_info.get_uv();
+    texture.value(u, v, hit_info.get_point())
+}
+
+/// Cosine-weighted sampling, shared by the diffuse models: the weight is
+/// the material's `eval` over the cosine density.
+fn scatter_cosine(material: &dyn Material, ray: Ray, hit_info: HitInfo) -> Option<ScatterRecord> {
+    let uvw = Onb::from_w(hit_info.get_normal());
+    let direction = uvw.local(Vec3::rand_cosine_direction());
+    let pdf = Vec3::dot(uvw.w, direction.normalized()) / PI;
+    if pdf <= 0. {
+        return None;
+    }
+
+    let attenuation = material.eval(ray, hit_info, direction) / pdf;
+    let ray = Ray::new(hit_info.get_point(), direction);
+    Some(ScatterRecord { ray, attenuation, pdf, is_specular: false, lobe: Lobe::Diffuse })
+}
+
+fn pdf_cosine(hit_info: &HitInfo, scattered: Vec3) -> f64 {
+    Vec3::dot(hit_info.get_normal(), scattered.normalized()).max(0.) / PI
+}
+
+/// Rough diffuse surface (clay, concrete, plaster) made of V-shaped
+/// Lambertian microfacets, following Oren and Nayar's qualitative model.
+/// Rough surfaces look flatter than `Lambertian` and brighter towards the
+/// light. `sigma` is the standard deviation of the facet slopes, in degrees;
+/// zero gives back Lambertian.
+pub struct OrenNayar {
+    albedo: Box<dyn Texture>,
+    a: f64,
+    b: f64,
+}
+
+impl OrenNayar {
+    pub fn new(albedo: impl Into<Box<dyn Texture>>, sigma: f64) -> OrenNayar {
+        let sigma2 = sigma.to_radians().powi(2);
+        OrenNayar {
+            albedo: albedo.into(),
+            a: 1. - sigma2 / (2. * (sigma2 + 0.33)),
+            b: 0.45 * sigma2 / (sigma2 + 0.09),
+        }
+    }
+}
+
+impl Material for OrenNayar {
+    fn scatter(&self, ray: Ray, hit_info: HitInfo) -> Option<ScatterRecord> {
+        scatter_cosine(self, ray, hit_info)
+    }
+
+    fn eval(&self, ray: Ray, hit_info: HitInfo, scattered: Vec3) -> RGBColor {
+        let (wo, wi) = local_directions(ray, &hit_info, scattered);
+        if wo.z <= 0. || wi.z <= 0. {
+            return RGBColor::zero();
+        }
+
+        let sin_o = (1. - wo.z * wo.z).max(0.).sqrt();
+        let sin_i = (1. - wi.z * wi.z).max(0.).sqrt();
+        let cos_phi = match sin_o > 1e-4 && sin_i > 1e-4 {
+            true => ((wi.x * wo.x + wi.y * wo.y) / (sin_i * sin_o)).max(0.),
+            false => 0.,
+        };
+
+        // sin(alpha) * tan(beta), with alpha the larger of the two angles
+        let (sin_alpha, tan_beta) = match wi.z > wo.z {
+            true => (sin_o, sin_i / wi.z),
+            false => (sin_i, sin_o / wo.z),
+        };
+        let f = (self.a + self.b * cos_phi * sin_alpha * tan_beta) / PI;
+        spectrum::upsample(texture_at(self.albedo.as_ref(), &hit_info) * (f * wi.z))
+    }
+
+    fn pdf(&self, _: Ray, hit_info: HitInfo, scattered: Vec3) -> f64 {
+        pdf_cosine(&hit_info, scattered)
+    }
+
+    fn albedo(&self, hit_info: HitInfo) -> RGBColor {
+        texture_at(self.albedo.as_ref(), &hit_info)
+    }
+}
+
+/// Fabric (velvet, satin, cotton): a diffuse base with Disney-style
+/// retro-reflection at grazing angles, under a sheen lobe from the "Charlie"
+/// fiber distribution of Estevez and Kulla (2017), with Neubelt and
+/// Pettineo's visibility term. Sheen brightens the silhouettes the way
+/// fibers standing out of the cloth catch the light.
+pub struct Cloth {
+    albedo: Box<dyn Texture>,
+    roughness: Box<dyn Texture>,
+    sheen: Box<dyn Texture>,
+    sheen_roughness: Box<dyn Texture>,
+}
+
+impl Cloth {
+    pub fn new(albedo: impl Into<Box<dyn Texture>>) -> Cloth {
+        Cloth {
+            albedo: albedo.into(),
+            roughness: 1.0.into(),
+            sheen: 0.5.into(),
+            sheen_roughness: 0.5.into(),
+        }
+    }
+
+    /// Strength of the diffuse retro-reflection, between 0 and 1.
+    pub fn roughness(mut self, roughness: impl Into<Box<dyn Texture>>) -> Cloth {
+        self.roughness = roughness.into();
+        self
+    }
+
+    /// Color of the sheen lobe.
+    pub fn sheen(mut self, sheen: impl Into<Box<dyn Texture>>) -> Cloth {
+        self.sheen = sheen.into();
+        self
+    }
+
+    /// Spread of the fibers: low values give a tight rim, as with velvet.
+    pub fn sheen_roughness(mut self, sheen_roughness: impl Into<Box<dyn Texture>>) -> Cloth {
+        self.sheen_roughness = sheen_roughness.into();
+        self
+    }
+}
+
+impl Material for Cloth {
+    fn scatter(&self, ray: Ray, hit_info: HitInfo) -> Option<ScatterRecord> {
+        scatter_cosine(self, ray, hit_info)
+    }
+
+    fn eval(&self, ray: Ray, hit_info: HitInfo, scattered: Vec3) -> RGBColor {
+        let (wo, wi) = local_directions(ray, &hit_info, scattered);
+        if wo.z <= 0. || wi.z <= 0. {
+            return RGBColor::zero();
+        }
+        let (u, v) = hit_info.get_uv();
+        let p = hit_info.get_point();
+        let wh = (wo + wi).normalized();
+        let cos_d = Vec3::dot(wi, wh);
+
+        let roughness = self.roughness.scalar(u, v, p).clamp(0., 1.);
+        let fl = microfacet::schlick_weight(wi.z);
+        let fv = microfacet::schlick_weight(wo.z);
+        let retro_reflection = 2. * roughness * cos_d * cos_d;
+        let diffuse = (1. - 0.5 * fl) * (1. - 0.5 * fv) + retro_reflection * (fl + fv + fl * fv * (retro_reflection - 1.));
+
+        let alpha = self.sheen_roughness.scalar(u, v, p).clamp(0.07, 1.).powi(2);
+        let sin_h = (1. - wh.z * wh.z).max(0.).sqrt();
+        let d = (2. + 1. / alpha) * sin_h.powf(1. / alpha) / (2. * PI);
+        let visibility = 1. / (4. * (wi.z + wo.z - wi.z * wo.z));
+
+        let albedo = self.albedo.value(u, v, p);
+        let sheen = self.sheen.value(u, v, p);
+        spectrum::upsample((albedo * (diffuse / PI) + sheen * (d * visibility)) * wi.z)
+    }
+
+    fn pdf(&self, _: Ray, hit_info: HitInfo, scattered: Vec3) -> f64 {
+        pdf_cosine(&hit_info, scattered)
+    }
+
+    fn albedo(&self, hit_info: HitInfo) -> RGBColor {
+        texture_at(self.albedo.as_ref(), &hit_info)
+    }
+}
@@ -0,0 +1,178 @@
+/// Piecewise-constant distribution over [0, 1], with one bucket per value of
+/// the function it was built from.
+pub struct Distribution1D {
+    func: Vec<f64>,
+    cdf: Vec<f64>,
+    integral: f64,
+}
+
+impl Distribution1D {
+    /// Distribution proportional to `func`, which must be non-negative.
+    /// Falls back to a uniform one when `func` is zero everywhere.
+    pub fn new(func: Vec<f64>) -> Distribution1D {
+        assert!(!func.is_empty(), "distribution needs at least one bucket");
+        let n = func.len();
+
+        let mut cdf = Vec::with_capacity(n + 1);
+        cdf.push(0.);
+        for (i, f) in func.iter().enumerate() {
+            cdf.push(cdf[i] + f.max(0.) / n as f64);
+        }
+
+        let integral = cdf[n];
+        for (i, c) in cdf.iter_mut().enumerate() {
+            *c = match integral > 0. {
+                true => *c / integral,
+                false => i as f64 / n as f64,
+            };
+        }
+
+        Distribution1D { func, cdf, integral }
+    }
+
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    /// Average of the function over [0, 1].
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    /// Maps `u` to a point in [0, 1), returned with its density and the
+    /// bucket it falls in.
+    pub fn sample(&self, u: f64) -> (f64, f64, usize) {
+        // Last cdf entry not above u
+        let offset = self.cdf.partition_point(|&c| c <= u).saturating_sub(1).min(self.count() - 1);
+
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        let du = match width > 0. {
+            true => (u - self.cdf[offset]) / width,
+            false => 0.,
+        };
+
+        let x = ((offset as f64 + du) / self.count() as f64).min(1. - f64::EPSILON);
+        (x, self.pdf(offset), offset)
+    }
+
+    /// Density of the bucket at `offset`.
+    pub fn pdf(&self, offset: usize) -> f64 {
+        match self.integral > 0. {
+            true => self.func[offset].max(0.) / self.integral,
+            false => 1.,
+        }
+    }
+
+    fn offset(&self, x: f64) -> usize {
+        ((x * self.count() as f64) as usize).min(self.count() - 1)
+    }
+}
+
+/// Piecewise-constant distribution over [0, 1]^2, sampled by picking a row
+/// from the marginal distribution and then a column within it.
+pub struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    /// Distribution proportional to `func`, given as `height` rows of `width` values.
+    pub fn new(func: &[f64], width: usize, height: usize) -> Distribution2D {
+        assert_eq!(func.len(), width * height, "distribution data doesn't match its resolution");
+
+        let conditional: Vec<Distribution1D> = func.chunks_exact(width)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(conditional.iter().map(|row| row.integral()).collect());
+
+        Distribution2D { conditional, marginal }
+    }
+
+    /// Average of the function over [0, 1]^2.
+    pub fn integral(&self) -> f64 {
+        self.marginal.integral()
+    }
+
+    /// Maps `(u1, u2)` to a point `(u, v)` in [0, 1)^2, returned with its density.
+    pub fn sample(&self, u1: f64, u2: f64) -> ((f64, f64), f64) {
+        let (v, pdf_v, row) = self.marginal.sample(u2);
+        let (u, pdf_u, _) = self.conditional[row].sample(u1);
+        ((u, v), pdf_u * pdf_v)
+    }
+
+    /// Density of `sample` producing `(u, v)`.
+    pub fn pdf(&self, u: f64, v: f64) -> f64 {
+        let row = self.marginal.offset(v);
+        let conditional = &self.conditional[row];
+        self.marginal.pdf(row) * conditional.pdf(conditional.offset(u))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_1d_follow_the_function() {
+        let func = vec![1., 0., 3., 0.5, 2.5];
+        let dist = Distribution1D::new(func.clone());
+        let n = func.len();
+        assert!((dist.integral() - 7. / n as f64).abs() < 1e-12);
+
+        let total: f64 = (0..n).map(|i| dist.pdf(i) / n as f64).sum();
+        assert!((total - 1.).abs() < 1e-12);
+
+        const SAMPLES: usize = 70_000;
+        let mut counts = vec![0; n];
+        for k in 0..SAMPLES {
+            let (x, pdf, offset) = dist.sample((k as f64 + 0.5) / SAMPLES as f64);
+            assert!((0. ..1.).contains(&x));
+            assert_eq!(offset, dist.offset(x));
+            assert_eq!(pdf, dist.pdf(offset));
+            counts[offset] += 1;
+        }
+        for (i, &count) in counts.iter().enumerate() {
+            assert!((count as f64 / SAMPLES as f64 - func[i] / 7.).abs() < 1e-3, "bucket {}", i);
+        }
+    }
+
+    #[test]
+    fn zero_function_samples_uniformly() {
+        let dist = Distribution1D::new(vec![0.; 4]);
+        assert_eq!(dist.integral(), 0.);
+        let (x, pdf, offset) = dist.sample(0.6);
+        assert!((x - 0.6).abs() < 1e-12);
+        assert_eq!((pdf, offset), (1., 2));
+    }
+
+    #[test]
+    fn samples_2d_follow_the_function() {
+        let (width, height) = (4, 3);
+        let func = [1., 2., 0., 1., 0., 0., 0., 0., 4., 1., 2., 1.];
+        let sum: f64 = func.iter().sum();
+        let dist = Distribution2D::new(&func, width, height);
+        assert!((dist.integral() - sum / func.len() as f64).abs() < 1e-12);
+
+        const STRATA: usize = 240;
+        let mut counts = vec![0; func.len()];
+        let mut integral = 0.;
+        for i in 0..STRATA {
+            for j in 0..STRATA {
+                let (u1, u2) = ((i as f64 + 0.5) / STRATA as f64, (j as f64 + 0.5) / STRATA as f64);
+                let ((u, v), pdf) = dist.sample(u1, u2);
+                assert!((pdf - dist.pdf(u, v)).abs() < 1e-12);
+
+                let cell = (v * height as f64) as usize * width + (u * width as f64) as usize;
+                counts[cell] += 1;
+
+                // Density over the grid, which should integrate to one
+                integral += dist.pdf(u1, u2);
+            }
+        }
+        let samples = (STRATA * STRATA) as f64;
+        assert!((integral / samples - 1.).abs() < 1e-9);
+        for (cell, &count) in counts.iter().enumerate() {
+            assert!((count as f64 / samples - func[cell] / sum).abs() < 1e-3, "cell {}", cell);
+        }
+    }
+}
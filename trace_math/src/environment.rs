@@ -0,0 +1,69 @@
+use crate::environment_map::EnvironmentMap;
+use crate::light::{IncidentLight, Light};
+use crate::sky::PhysicalSky;
+use crate::vec3::{Point, RGBColor, Vec3};
+
+/// What lies infinitely far away around the scene, seen by rays that escape it.
+pub enum Environment {
+    Constant(RGBColor),
+    /// Blend from `horizon` to `zenith` color with the ray's height.
+    Gradient { horizon: RGBColor, zenith: RGBColor },
+    Map(EnvironmentMap),
+    /// Procedural daylight, sun included.
+    Sky(PhysicalSky),
+}
+
+impl Environment {
+    /// White to light blue sky.
+    pub fn gradient() -> Environment {
+        Environment::Gradient {
+            horizon: RGBColor::new(1., 1., 1.),
+            zenith: RGBColor::new(0.5, 0.7, 1.),
+        }
+    }
+
+    /// Radiance arriving from infinitely far away along `-direction`.
+    pub fn radiance(&self, direction: Vec3) -> RGBColor {
+        match self {
+            Environment::Constant(color) => *color,
+            Environment::Gradient { horizon, zenith } => {
+                let t = 0.5 * (direction.normalized().y + 1.);
+                *horizon * (1. - t) + *zenith * t
+            },
+            Environment::Map(map) => map.radiance(direction),
+            Environment::Sky(sky) => sky.radiance(direction),
+        }
+    }
+
+    /// Solid-angle density of `sample_li` picking `direction`; zero for
+    /// environments that aren't sampled as lights.
+    pub fn pdf(&self, direction: Vec3) -> f64 {
+        match self {
+            Environment::Map(map) => map.pdf(direction),
+            Environment::Sky(sky) => sky.pdf(direction),
+            _ => 0.,
+        }
+    }
+}
+
+impl Default for Environment {
+    fn default() -> Environment {
+        Environment::gradient()
+    }
+}
+
+/// Only environment maps and the sun of a sky are importance sampled: the
+/// other environments are smooth enough for BSDF sampling to find them.
+impl Light for Environment {
+    fn sample_li(&self, point: Point) -> Option<IncidentLight> {
+        match self {
+            Environment::Map(map) => map.sample_li(point),
+            Environment::Sky(sky) => sky.sample_li(point),
+            _ => None,
+        }
+    }
+
+    fn power(&self) -> f64 {
+        0.
+    }
+}
@@ -0,0 +1,106 @@
+use std::f64::consts::PI;
+
+use crate::distribution::Distribution2D;
+use crate::image::Image;
+use crate::light::{IncidentLight, Light};
+use crate::utils::math_utils::degrees_to_radians;
+use crate::utils::rand_utils::rand_f64;
+use crate::vec3::{Point, RGBColor, Vec3};
+
+/// Image-based lighting from an equirectangular HDR image wrapped around the
+/// scene. The center of the image faces -z before rotation, its top +y.
+///
+/// Directions are importance sampled from a piecewise-constant distribution
+/// over the pixels' luminance, so small bright features like the sun are
+/// found by light sampling instead of by chance.
+pub struct EnvironmentMap {
+    image: Image,
+    intensity: f64,
+    rotation: f64,
+    distribution: Distribution2D,
+}
+
+impl EnvironmentMap {
+    /// Map scaled by `intensity` and turned by `rotation` degrees around +y.
+    pub fn new(image: Image, intensity: f64, rotation: f64) -> EnvironmentMap {
+        let (width, height) = (image.width(), image.height());
+
+        // Rows near the poles cover less solid angle than the ones at the horizon
+        let mut func = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let sin_theta = (PI * (y as f64 + 0.5) / height as f64).sin();
+            for x in 0..width {
+                func.push(image.pixel(x, y).luminance().max(0.) * sin_theta);
+            }
+        }
+
+        EnvironmentMap {
+            distribution: Distribution2D::new(&func, width, height),
+            image,
+            intensity,
+            rotation: degrees_to_radians(rotation),
+        }
+    }
+
+    /// Radiance arriving from infinitely far away along `-direction`.
+    pub fn radiance(&self, direction: Vec3) -> RGBColor {
+        let (u, v) = self.direction_to_uv(direction);
+        self.image.lookup(u, v) * self.intensity
+    }
+
+    /// Solid-angle density of `sample_li` picking `direction`.
+    pub fn pdf(&self, direction: Vec3) -> f64 {
+        let (u, v) = self.direction_to_uv(direction);
+        let sin_theta = (PI * v).sin();
+        if sin_theta <= 0. {
+            return 0.;
+        }
+
+        self.distribution.pdf(u, v) / (2. * PI * PI * sin_theta)
+    }
+
+    fn direction_to_uv(&self, direction: Vec3) -> (f64, f64) {
+        let d = direction.normalized();
+        let phi = f64::atan2(d.x, -d.z) - self.rotation;
+        let u = (0.5 + phi / (2. * PI)).rem_euclid(1.);
+        let v = d.y.clamp(-1., 1.).acos() / PI;
+        (u, v)
+    }
+
+    fn uv_to_direction(&self, u: f64, v: f64) -> Vec3 {
+        let phi = self.rotation / (2. * PI);
+        equirect_direction((u + phi).rem_euclid(1.), v)
+    }
+}
+
+/// Direction seen at `(u, v)` of an unrotated equirectangular image.
+pub fn equirect_direction(u: f64, v: f64) -> Vec3 {
+    let phi = 2. * PI * (u - 0.5);
+    let theta = PI * v;
+    Vec3::new(phi.sin() * theta.sin(), theta.cos(), -phi.cos() * theta.sin())
+}
+
+impl Light for EnvironmentMap {
+    fn sample_li(&self, _point: Point) -> Option<IncidentLight> {
+        let ((u, v), pdf_uv) = self.distribution.sample(rand_f64(), rand_f64());
+        let sin_theta = (PI * v).sin();
+        if pdf_uv <= 0. || sin_theta <= 0. {
+            return None;
+        }
+
+        let direction = self.uv_to_direction(u, v);
+        Some(IncidentLight {
+            direction,
+            distance: f64::INFINITY,
+            radiance: self.image.lookup(u, v) * self.intensity,
+            pdf: pdf_uv / (2. * PI * PI * sin_theta),
+        })
+    }
+
+    fn power(&self) -> f64 {
+        // The distribution averages luminance times sin(theta) over the
+        // image, which is 2 / pi of the average over the sphere
+        let average = self.intensity * self.distribution.integral() * PI / 2.;
+        PI * average
+    }
+}
@@ -1,19 +1,33 @@
 use crate::vec3::{Vec3, Point};
 use crate::ray::Ray;
 use crate::material::Material;
+use crate::onb::Onb;
 
+#[derive(Clone, Copy)]
 pub struct HitInfo<'a> {
     point: Point,
     normal: Vec3,
     t: f64,
+    u: f64,
+    v: f64,
     material: &'a dyn Material,
     front_face: bool,
+    on_surface: bool,
+    light: Option<usize>,
+    tangent: Option<Vec3>,
 }
 
 impl<'a> HitInfo<'a> {
-    pub fn new(point: Point, out_normal: Vec3, t: f64, material: &'a dyn Material, r: Ray) -> HitInfo {
+    pub fn new(point: Point, out_normal: Vec3, t: f64, (u, v): (f64, f64), material: &'a dyn Material, r: Ray) -> HitInfo<'a> {
         let (front_face, normal) = HitInfo::set_normal(r, out_normal);
-        HitInfo { point, normal, t, material, front_face }
+        HitInfo { point, normal, t, u, v, material, front_face, on_surface: true, light: None, tangent: None }
+    }
+
+    /// Scattering event inside a participating medium, where there is no
+    /// surface: the normal just faces back along the ray.
+    pub fn in_medium(point: Point, t: f64, material: &'a dyn Material, r: Ray) -> HitInfo<'a> {
+        let normal = -r.direction.normalized();
+        HitInfo { point, normal, t, u: 0., v: 0., material, front_face: true, on_surface: false, light: None, tangent: None }
     }
 
     pub fn get_point(&self) -> Point {
@@ -28,14 +42,83 @@ impl<'a> HitInfo<'a> {
         self.t
     }
 
+    pub fn get_uv(&self) -> (f64, f64) {
+        (self.u, self.v)
+    }
+
     pub fn get_front_face(&self) -> bool {
         self.front_face
     }
 
+    pub fn is_on_surface(&self) -> bool {
+        self.on_surface
+    }
+
     pub fn get_material(&self) -> &'a dyn Material {
         self.material
     }
 
+    /// Index of the scene light that was hit, if the shape is one.
+    pub fn get_light(&self) -> Option<usize> {
+        self.light
+    }
+
+    /// Same hit, marked as being on the scene's `light`-th light.
+    pub fn on_light(&self, light: usize) -> HitInfo<'a> {
+        HitInfo { light: Some(light), ..*self }
+    }
+
+    /// Same hit, shaded with `shading_normal` (pointing out of the surface,
+    /// e.g. an interpolated vertex normal) while keeping the face given by
+    /// the geometric normal.
+    pub fn with_shading_normal(&self, shading_normal: Vec3) -> HitInfo<'a> {
+        let normal = match self.front_face {
+            true => shading_normal,
+            false => -shading_normal,
+        };
+        HitInfo { normal, ..*self }
+    }
+
+    /// Same hit, with `tangent` (usually dp/du) orienting anisotropic materials.
+    pub fn with_tangent(&self, tangent: Vec3) -> HitInfo<'a> {
+        HitInfo { tangent: Some(tangent), ..*self }
+    }
+
+    /// Shading frame around the normal, with its first axis along the
+    /// surface tangent when there is one, so that it doesn't twist
+    /// arbitrarily across the surface.
+    pub fn shading_frame(&self) -> Onb {
+        let w = self.normal.normalized();
+        let across = match self.tangent {
+            Some(tangent) => tangent - w * Vec3::dot(tangent, w),
+            None => Vec3::zero(),
+        };
+
+        match across.length_squared() > 1e-12 {
+            true => {
+                let u = across.normalized();
+                Onb { u, v: Vec3::cross(w, u), w }
+            },
+            false => Onb::from_w(w),
+        }
+    }
+
+    /// Same hit, seen through another material.
+    pub fn with_material(&self, material: &'a dyn Material) -> HitInfo<'a> {
+        HitInfo { material, ..*self }
+    }
+
+    /// Same hit, with the normal and face flipped to oppose `r` instead of
+    /// the ray that found it.
+    pub fn facing(&self, r: Ray) -> HitInfo<'a> {
+        let out_normal = match self.front_face {
+            true => self.normal,
+            false => -self.normal,
+        };
+        let (front_face, normal) = HitInfo::set_normal(r, out_normal);
+        HitInfo { normal, front_face, ..*self }
+    }
+
     fn set_normal(r: Ray, out_normal: Vec3) -> (bool, Vec3) {
         let front_face = Vec3::dot(r.direction, out_normal) < 0.;
         let normal = if front_face {
@@ -52,7 +135,8 @@ impl<'a> HitInfo<'a> {
         println!("  point: {:?}", self.get_point());
         println!("  normal: {:?}", self.get_normal());
         println!("  t: {:?}", self.get_t());
+        println!("  uv: {:?}", self.get_uv());
         println!("  front_face: {:?}", self.get_front_face());
         println!("}}");
     }
-}
\ No newline at end of file
+}
@@ -1,21 +1,94 @@
+use std::cell::{OnceCell, RefCell};
+use std::f64::consts::PI;
+use std::rc::Rc;
+
+use crate::aabb::Aabb;
+use crate::environment::Environment;
 use crate::hit_info::HitInfo;
+use crate::light::Light;
+use crate::light_sampler::{LightInfo, LightSampler, LightSelection};
+use crate::medium::ConstantMedium;
 use crate::ray::Ray;
 use crate::material;
 use crate::utils::rand_utils::{rand_f64, rand_f64_range};
-use crate::vec3::{RGBColor, Point};
+use crate::utils::sampler::{self, Sampler};
+use crate::vec3::{RGBColor, Point, Vec3};
 use crate::sphere::Sphere;
 
 pub trait Hittable {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitInfo>;
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitInfo<'_>>;
+
+    /// Like `hit`, but looking through participating media, for shadow rays.
+    fn hit_surface(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitInfo<'_>> {
+        self.hit(r, t_min, t_max)
+    }
+
+    /// Fraction of light carried along `r` between `t_min` and `t_max` that
+    /// isn't absorbed or scattered away by participating media, per channel.
+    fn transmittance(&self, _r: Ray, _t_min: f64, _t_max: f64) -> RGBColor {
+        RGBColor::ones()
+    }
+
+    /// Solid-angle density of `random` picking `direction` from `origin`.
+    fn pdf_value(&self, _origin: Point, _direction: Vec3) -> f64 {
+        0.
+    }
+
+    /// Direction from `origin` towards a random point on the shape.
+    fn random(&self, _origin: Point) -> Vec3 {
+        Vec3::new(1., 0., 0.)
+    }
+
+    /// Uniformly picks a point on the surface, returned as a hit seen from
+    /// outside together with its density per unit area.
+    fn sample_surface(&self) -> Option<(HitInfo<'_>, f64)> {
+        None
+    }
+
+    /// Area density of `sample_surface` picking `point`, zero if it isn't on the surface.
+    fn surface_pdf(&self, _point: Point) -> f64 {
+        0.
+    }
+
+    /// Box enclosing the shape, `None` if it isn't bounded or doesn't know.
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
 }
 
 type Shape = Box<dyn Hittable>;
 
-pub struct HittableList(Vec<Shape>);
+/// Light picked for direct lighting.
+pub enum SceneLight<'a> {
+    /// Emissive shape, with its index among the scene's lights.
+    Shape(usize, &'a dyn Hittable),
+    Analytic(&'a dyn Light),
+}
+
+pub struct HittableList {
+    objects: Vec<Shape>,
+    lights: Vec<usize>,
+    analytic_lights: Vec<Box<dyn Light>>,
+    environment: Environment,
+    camera_background: Option<Environment>,
+    fog: Option<ConstantMedium>,
+    light_selection: LightSelection,
+    // Built by `prepare`, or on first use, once all the lights have been added
+    light_sampler: OnceCell<LightSampler>,
+}
 
 impl HittableList {
     pub fn empty() -> HittableList {
-        HittableList(vec![])
+        HittableList {
+            objects: vec![],
+            lights: vec![],
+            analytic_lights: vec![],
+            environment: Environment::default(),
+            camera_background: None,
+            fog: None,
+            light_selection: LightSelection::default(),
+            light_sampler: OnceCell::new(),
+        }
     }
 
     pub fn rand_scene() -> HittableList {
@@ -75,42 +148,246 @@ impl HittableList {
             objects.push(obj);
         }
 
-        HittableList(objects)
+        HittableList { objects, ..HittableList::empty() }
     }
 
     pub fn add(&mut self, obj: Shape) {
-        self.0.push(obj);
+        self.objects.push(obj);
+    }
+
+    /// Adds an emissive shape, which will also be sampled for direct lighting.
+    pub fn add_light(&mut self, obj: Shape) {
+        self.lights.push(self.objects.len());
+        self.objects.push(obj);
+        self.light_sampler.take();
+    }
+
+    /// Adds a point, spot or directional light, which has no geometry.
+    pub fn add_analytic_light(&mut self, light: Box<dyn Light>) {
+        self.analytic_lights.push(light);
+        self.light_sampler.take();
+    }
+
+    /// Changes how direct lighting picks among the lights.
+    pub fn set_light_selection(&mut self, selection: LightSelection) {
+        self.light_selection = selection;
+        self.light_sampler.take();
+    }
+
+    /// Surrounds the scene with `environment`, which lights it and is seen
+    /// by rays that escape it.
+    pub fn set_environment(&mut self, environment: Environment) {
+        self.environment = environment;
+    }
+
+    /// Shows a different environment than the lighting one to camera rays
+    /// that escape directly, e.g. a backplate photo.
+    pub fn set_camera_background(&mut self, background: Environment) {
+        self.camera_background = Some(background);
+    }
+
+    /// Fills the scene with `fog`, a medium seen by every ray, camera and
+    /// shadow rays included, e.g. one made with `ConstantMedium::global`.
+    pub fn set_fog(&mut self, fog: ConstantMedium) {
+        self.fog = Some(fog);
     }
 
     pub fn clear(&mut self) {
-        self.0.clear();
+        self.objects.clear();
+        self.lights.clear();
+        self.analytic_lights.clear();
+        self.environment = Environment::default();
+        self.camera_background = None;
+        self.fog = None;
+        self.light_sampler.take();
+    }
+
+    /// Builds the light sampler ahead of rendering, so that it isn't first
+    /// built in the middle of a path. Call it once the lights are added.
+    pub fn prepare(&self) {
+        self.light_sampler();
     }
 
     pub fn length(&self) -> usize {
-        self.0.len()
+        self.objects.len()
+    }
+
+    pub fn has_lights(&self) -> bool {
+        !self.lights.is_empty() || !self.analytic_lights.is_empty()
+    }
+
+    pub fn analytic_lights(&self) -> &[Box<dyn Light>] {
+        &self.analytic_lights
+    }
+
+    pub fn environment(&self) -> &Environment {
+        &self.environment
+    }
+
+    pub fn camera_background(&self) -> Option<&Environment> {
+        self.camera_background.as_ref()
+    }
+
+    /// Picks a light to sample for direct lighting at `point`, returned with
+    /// the probability of picking it.
+    pub fn sample_light(&self, point: Point) -> Option<(SceneLight<'_>, f64)> {
+        let (idx, pmf) = self.light_sampler().sample(point, rand_f64())?;
+        let light = match idx < self.lights.len() {
+            true => SceneLight::Shape(idx, self.objects[self.lights[idx]].as_ref()),
+            false => SceneLight::Analytic(self.analytic_lights[idx - self.lights.len()].as_ref()),
+        };
+        Some((light, pmf))
+    }
+
+    /// Density of sampling the emissive shape `light` from `origin` and it
+    /// producing `direction`.
+    pub fn light_pdf_value(&self, origin: Point, direction: Vec3, light: usize) -> f64 {
+        let pmf = self.light_sampler().pmf(origin, light);
+        match pmf > 0. {
+            true => pmf * self.objects[self.lights[light]].pdf_value(origin, direction),
+            false => 0.,
+        }
+    }
+
+    /// Point on a uniformly chosen light, with its area density.
+    pub fn sample_light_surface(&self) -> Option<(HitInfo<'_>, f64)> {
+        if self.lights.is_empty() {
+            return None;
+        }
+
+        let (hit, pdf) = self.random_light().sample_surface()?;
+        Some((hit, pdf / self.lights.len() as f64))
+    }
+
+    /// Area density of `sample_light_surface` picking `point`.
+    pub fn light_surface_pdf(&self, point: Point) -> f64 {
+        if self.lights.is_empty() {
+            return 0.;
+        }
+
+        let sum: f64 = self.lights.iter()
+            .map(|&i| self.objects[i].surface_pdf(point))
+            .sum();
+        sum / self.lights.len() as f64
+    }
+
+    fn random_light(&self) -> &dyn Hittable {
+        let idx = ((rand_f64() * self.lights.len() as f64) as usize).min(self.lights.len() - 1);
+        self.objects[self.lights[idx]].as_ref()
+    }
+
+    /// Sampler over the emissive shapes followed by the analytic lights.
+    fn light_sampler(&self) -> &LightSampler {
+        self.light_sampler.get_or_init(|| {
+            let shapes = self.lights.iter().map(|&i| {
+                let shape = self.objects[i].as_ref();
+                LightInfo { bounds: shape.bounding_box(), power: emitted_power(shape) }
+            });
+            let analytic = self.analytic_lights.iter()
+                .map(|light| LightInfo { bounds: light.bounds(), power: light.power() });
+
+            let infos: Vec<LightInfo> = shapes.chain(analytic).collect();
+            LightSampler::new(self.light_selection, &infos)
+        })
+    }
+
+    /// Marks hits on the `object`-th shape if it's one of the lights.
+    fn tag_light<'a>(&self, hit: HitInfo<'a>, object: usize) -> HitInfo<'a> {
+        match self.lights.binary_search(&object) {
+            Ok(light) => hit.on_light(light),
+            Err(_) => hit,
+        }
+    }
+}
+
+/// Power emitted by a shape as luminance, estimated from a fixed set of
+/// points on its surface and assuming they emit equally in all directions.
+/// The points don't depend on the random generator, so building the light
+/// sampler gives the same result every time and draws no random numbers.
+fn emitted_power(shape: &dyn Hittable) -> f64 {
+    const SAMPLES: usize = 16;
+    let total: f64 = (0..SAMPLES)
+        .filter_map(|index| {
+            let points = Rc::new(RefCell::new(HaltonPoint { index: index + 1, dimension: 0 }));
+            sampler::with_sampler(points, || {
+                let (hit, pdf) = shape.sample_surface()?;
+                let seen_from = Ray::new(hit.get_point() + hit.get_normal(), -hit.get_normal());
+                Some(hit.get_material().emitted(seen_from, hit).luminance() / pdf)
+            })
+        })
+        .sum();
+    PI * total / SAMPLES as f64
+}
+
+/// Coordinates of the `index`-th point of the Halton sequence, one
+/// dimension per number drawn.
+struct HaltonPoint {
+    index: usize,
+    dimension: usize,
+}
+
+impl Sampler for HaltonPoint {
+    fn next_f64(&mut self) -> f64 {
+        const PRIMES: [usize; 8] = [2, 3, 5, 7, 11, 13, 17, 19];
+        let base = PRIMES[self.dimension % PRIMES.len()];
+        self.dimension += 1;
+
+        // Radical inverse: the digits of the index mirrored around the point
+        let (mut n, mut value, mut scale) = (self.index, 0., 1. / base as f64);
+        while n > 0 {
+            value += (n % base) as f64 * scale;
+            n /= base;
+            scale /= base as f64;
+        }
+        value
     }
 }
 
 impl Hittable for HittableList {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitInfo> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitInfo<'_>> {
         let mut closest_so_far = t_max;
         let mut hit_rec = None;
 
-        for shape in self.0.iter() {
+        for (i, shape) in self.objects.iter().enumerate() {
             let hit_r = shape.hit(r, t_min, closest_so_far);
 
             hit_rec = match hit_r {
                 Some(hit_record) => {
-                    if !hit_record.get_front_face(){
-                        hit_record.print();
-                    }
                     closest_so_far = hit_record.get_t();
-                    Some(hit_record)
+                    Some((hit_record, i))
                 },
                 None => continue
             };
         }
 
-        hit_rec
+        // Fog scatters the ray before anything it would reach otherwise
+        if let Some(scattered) = self.fog.as_ref().and_then(|fog| fog.hit(r, t_min, closest_so_far)) {
+            return Some(scattered);
+        }
+
+        hit_rec.map(|(hit, i)| self.tag_light(hit, i))
     }
-}
\ No newline at end of file
+
+    fn hit_surface(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitInfo<'_>> {
+        let mut closest_so_far = t_max;
+        let mut hit_rec = None;
+
+        for (i, shape) in self.objects.iter().enumerate() {
+            if let Some(hit_record) = shape.hit_surface(r, t_min, closest_so_far) {
+                closest_so_far = hit_record.get_t();
+                hit_rec = Some((hit_record, i));
+            }
+        }
+
+        hit_rec.map(|(hit, i)| self.tag_light(hit, i))
+    }
+
+    fn transmittance(&self, r: Ray, t_min: f64, t_max: f64) -> RGBColor {
+        let fog = match &self.fog {
+            Some(fog) => fog.transmittance(r, t_min, t_max),
+            None => RGBColor::ones(),
+        };
+        self.objects.iter()
+            .fold(fog, |tr, shape| tr * shape.transmittance(r, t_min, t_max))
+    }
+}
@@ -0,0 +1,179 @@
+use std::f64::consts::PI;
+
+use crate::light::Intensity;
+use crate::vec3::Vec3;
+
+/// Angular intensity distribution of a light fixture, from IES photometric
+/// data (type C). Vertical angles go from 0 at the fixture's nadir, which is
+/// its main direction of emission, to 180 straight opposite; horizontal
+/// angles turn around the nadir axis, starting from the fixture's length.
+///
+/// Candela values are stored per horizontal angle, each row holding one
+/// value per vertical angle. Missing horizontal angles follow from the
+/// usual symmetries: a single angle is symmetric all around, angles up to
+/// 90 repeat in each quadrant, angles up to 180 are mirrored across the
+/// 0-180 plane and angles from 90 to 270 across the 90-270 plane.
+pub struct IesProfile {
+    vertical: Vec<f64>,
+    horizontal: Vec<f64>,
+    candela: Vec<Vec<f64>>,
+    max_candela: f64,
+}
+
+impl IesProfile {
+    /// Angles in degrees, sorted in increasing order.
+    pub fn new(vertical: Vec<f64>, horizontal: Vec<f64>, candela: Vec<Vec<f64>>) -> IesProfile {
+        assert!(!vertical.is_empty() && !horizontal.is_empty(), "IES profile needs at least one angle");
+        assert_eq!(candela.len(), horizontal.len(), "IES candela rows don't match the horizontal angles");
+        assert!(candela.iter().all(|row| row.len() == vertical.len()), "IES candela rows don't match the vertical angles");
+
+        let max_candela = candela.iter().flatten().cloned().fold(0., f64::max);
+        IesProfile { vertical, horizontal, candela, max_candela }
+    }
+
+    /// Brightest intensity of the fixture, for lights using the profile to
+    /// reproduce its absolute output.
+    pub fn peak_intensity(&self) -> Intensity {
+        Intensity::Candela(self.max_candela)
+    }
+
+    /// Intensity in candela at `vertical` and `horizontal` angles, in degrees.
+    pub fn candela(&self, vertical: f64, horizontal: f64) -> f64 {
+        let (v_first, v_last) = (self.vertical[0], self.vertical[self.vertical.len() - 1]);
+        if vertical < v_first || vertical > v_last {
+            return 0.;
+        }
+
+        let (v0, v1, tv) = segment(&self.vertical, vertical);
+        let (h0, h1, th) = segment(&self.horizontal, self.fold_horizontal(horizontal));
+        let at = |h: usize| self.candela[h][v0] * (1. - tv) + self.candela[h][v1] * tv;
+        at(h0) * (1. - th) + at(h1) * th
+    }
+
+    /// Intensity towards `direction`, given in the fixture's frame (nadir
+    /// along +z, zero horizontal angle along +x), relative to the peak.
+    pub fn relative(&self, direction: Vec3) -> f64 {
+        if self.max_candela <= 0. {
+            return 0.;
+        }
+
+        let d = direction.normalized();
+        let vertical = d.z.clamp(-1., 1.).acos().to_degrees();
+        let horizontal = f64::atan2(d.y, d.x).to_degrees().rem_euclid(360.);
+        self.candela(vertical, horizontal) / self.max_candela
+    }
+
+    /// Average of `relative` over the sphere, i.e. the fixture's power
+    /// relative to a point light shining at its peak intensity everywhere.
+    pub fn average_relative(&self) -> f64 {
+        const THETA_STEPS: usize = 90;
+        const PHI_STEPS: usize = 72;
+        let d_theta = PI / THETA_STEPS as f64;
+        let d_phi = 2. * PI / PHI_STEPS as f64;
+
+        let mut sum = 0.;
+        for i in 0..THETA_STEPS {
+            let theta = (i as f64 + 0.5) * d_theta;
+            for j in 0..PHI_STEPS {
+                let phi = (j as f64 + 0.5) * d_phi;
+                let d = Vec3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos());
+                sum += self.relative(d) * theta.sin() * d_theta * d_phi;
+            }
+        }
+        sum / (4. * PI)
+    }
+
+    /// Brings a horizontal angle in [0, 360) into the range covered by the
+    /// data, through the profile's symmetry.
+    fn fold_horizontal(&self, angle: f64) -> f64 {
+        let (first, last) = (self.horizontal[0], self.horizontal[self.horizontal.len() - 1]);
+        let mirrored = match angle > 180. {
+            true => 360. - angle,
+            false => angle,
+        };
+
+        let folded = match (first, last) {
+            (_, l) if l <= 90. => match mirrored > 90. {
+                true => 180. - mirrored,
+                false => mirrored,
+            },
+            (f, _) if f >= 90. => match angle {
+                a if a < 90. => 180. - a,
+                a if a > 270. => 540. - a,
+                a => a,
+            },
+            (_, l) if l <= 180. => mirrored,
+            _ => angle,
+        };
+        folded.clamp(first, last)
+    }
+}
+
+/// Indices of the sorted `angles` around `x`, and the blend between them.
+fn segment(angles: &[f64], x: f64) -> (usize, usize, f64) {
+    if angles.len() == 1 {
+        return (0, 0, 0.);
+    }
+
+    let upper = angles.partition_point(|&a| a <= x).clamp(1, angles.len() - 1);
+    let (a0, a1) = (angles[upper - 1], angles[upper]);
+    let t = match a1 > a0 {
+        true => ((x - a0) / (a1 - a0)).clamp(0., 1.),
+        false => 0.,
+    };
+    (upper - 1, upper, t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Profile with a single vertical angle and the given horizontal ones.
+    fn profile(horizontal: &[f64], candela: &[f64]) -> IesProfile {
+        let rows = candela.iter().map(|&cd| vec![cd]).collect();
+        IesProfile::new(vec![0.], horizontal.to_vec(), rows)
+    }
+
+    fn assert_candela(profile: &IesProfile, expected: &[(f64, f64)]) {
+        for &(horizontal, candela) in expected {
+            assert!((profile.candela(0., horizontal) - candela).abs() < 1e-9, "at {}", horizontal);
+        }
+    }
+
+    #[test]
+    fn single_angle_is_symmetric_all_around() {
+        let profile = profile(&[0.], &[10.]);
+        assert_candela(&profile, &[(0., 10.), (123., 10.), (359., 10.)]);
+    }
+
+    #[test]
+    fn quadrant_repeats_around() {
+        let profile = profile(&[0., 90.], &[10., 20.]);
+        assert_candela(&profile, &[(45., 15.), (90., 20.), (135., 15.), (180., 10.), (270., 20.), (315., 15.)]);
+    }
+
+    #[test]
+    fn half_is_mirrored_across_the_0_180_plane() {
+        let profile = profile(&[0., 180.], &[10., 30.]);
+        assert_candela(&profile, &[(90., 20.), (180., 30.), (270., 20.), (315., 15.)]);
+    }
+
+    #[test]
+    fn half_is_mirrored_across_the_90_270_plane() {
+        let profile = profile(&[90., 270.], &[10., 30.]);
+        assert_candela(&profile, &[(0., 20.), (45., 15.), (90., 10.), (180., 20.), (270., 30.), (315., 25.)]);
+    }
+
+    #[test]
+    fn full_circle_is_used_as_is() {
+        let profile = profile(&[0., 180., 360.], &[10., 30., 10.]);
+        assert_candela(&profile, &[(90., 20.), (270., 20.), (315., 15.)]);
+    }
+
+    #[test]
+    fn vertical_angles_are_interpolated() {
+        let profile = IesProfile::new(vec![0., 90.], vec![0.], vec![vec![100., 50.]]);
+        assert_eq!(profile.candela(45., 0.), 75.);
+        assert_eq!(profile.candela(120., 0.), 0.);
+    }
+}
@@ -0,0 +1,35 @@
+use crate::vec3::RGBColor;
+
+/// High dynamic range image with linear RGB pixels, stored row by row
+/// starting from the top.
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<RGBColor>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize, pixels: Vec<RGBColor>) -> Image {
+        assert_eq!(pixels.len(), width * height, "image data doesn't match its resolution");
+        Image { width, height, pixels }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> RGBColor {
+        self.pixels[y * self.width + x]
+    }
+
+    /// Pixel covering `(u, v)` in [0, 1]^2, with v going down the image.
+    pub fn lookup(&self, u: f64, v: f64) -> RGBColor {
+        let x = ((u * self.width as f64) as usize).min(self.width - 1);
+        let y = ((v * self.height as f64) as usize).min(self.height - 1);
+        self.pixel(x, y)
+    }
+}
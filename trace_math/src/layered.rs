@@ -0,0 +1,254 @@
+use crate::hit_info::HitInfo;
+use crate::material::{self, Lobe, Material, ScatterRecord};
+use crate::microfacet;
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::spectrum;
+use crate::texture::Texture;
+use crate::utils::rand_utils;
+use crate::vec3::{RGBColor, Vec3};
+
+/// Blend of two materials, `weight` giving the fraction of the second one.
+/// A texture weight acts as a mask, e.g. rust patches over painted metal.
+pub struct MixMaterial {
+    first: Box<dyn Material>,
+    second: Box<dyn Material>,
+    weight: Box<dyn Texture>,
+}
+
+impl MixMaterial {
+    pub fn new(first: Box<dyn Material>, second: Box<dyn Material>, weight: impl Into<Box<dyn Texture>>) -> MixMaterial {
+        MixMaterial { first, second, weight: weight.into() }
+    }
+
+    fn weight_at(&self, hit_info: &HitInfo) -> f64 {
+        let (u, v) = hit_info.get_uv();
+        self.weight.scalar(u, v, hit_info.get_point()).clamp(0., 1.)
+    }
+}
+
+impl Material for MixMaterial {
+    fn scatter(&self, ray: Ray, hit_info: HitInfo) -> Option<ScatterRecord> {
+        let w = self.weight_at(&hit_info);
+        let srec = match rand_utils::rand_f64() < w {
+            true => self.second.scatter(ray, hit_info)?,
+            false => self.first.scatter(ray, hit_info)?,
+        };
+        // Picking a delta lobe with the blend probability cancels its weight
+        if srec.is_specular {
+            return Some(srec);
+        }
+
+        // Otherwise the direction could have come from either material
+        let pdf = self.pdf(ray, hit_info, srec.ray.direction);
+        if pdf <= 0. {
+            return None;
+        }
+        let attenuation = self.eval(ray, hit_info, srec.ray.direction) / pdf;
+        Some(ScatterRecord { attenuation, pdf, ..srec })
+    }
+
+    fn emitted(&self, ray: Ray, hit_info: HitInfo) -> RGBColor {
+        let w = self.weight_at(&hit_info);
+        self.first.emitted(ray, hit_info) * (1. - w) + self.second.emitted(ray, hit_info) * w
+    }
+
+    fn eval(&self, ray: Ray, hit_info: HitInfo, scattered: Vec3) -> RGBColor {
+        let w = self.weight_at(&hit_info);
+        self.first.eval(ray, hit_info, scattered) * (1. - w) + self.second.eval(ray, hit_info, scattered) * w
+    }
+
+    fn pdf(&self, ray: Ray, hit_info: HitInfo, scattered: Vec3) -> f64 {
+        let w = self.weight_at(&hit_info);
+        self.first.pdf(ray, hit_info, scattered) * (1. - w) + self.second.pdf(ray, hit_info, scattered) * w
+    }
+
+    fn albedo(&self, hit_info: HitInfo) -> RGBColor {
+        let w = self.weight_at(&hit_info);
+        self.first.albedo(hit_info) * (1. - w) + self.second.albedo(hit_info) * w
+    }
+}
+
+/// Dielectric coat (varnish, lacquer, car paint clearcoat) over any base
+/// material. The coat reflects with the Fresnel term, smooth or with a GGX
+/// microfacet surface, and the base only receives the light it lets
+/// through: `1 - F` on the way in and again on the way out, times the
+/// coat's absorption along both refracted paths.
+///
+/// The base is evaluated with the outside directions, ignoring the bending
+/// of light through the coat. Car paint is a coat over a mix of a colored
+/// diffuse and a rough metal for the flakes.
+pub struct CoatedMaterial {
+    base: Box<dyn Material>,
+    ior: f64,
+    roughness: Box<dyn Texture>,
+    tint: RGBColor,
+}
+
+impl CoatedMaterial {
+    pub fn new(base: Box<dyn Material>, ior: f64, roughness: impl Into<Box<dyn Texture>>) -> CoatedMaterial {
+        CoatedMaterial { base, ior, roughness: roughness.into(), tint: RGBColor::ones() }
+    }
+
+    /// Colored coat, letting through `tint` when light goes in and back out
+    /// at normal incidence.
+    pub fn with_tint(mut self, tint: RGBColor) -> CoatedMaterial {
+        self.tint = tint;
+        self
+    }
+
+    /// Fraction of light crossing the coat once, at `cos` from the normal
+    /// outside: what Fresnel lets through, times half the absorption of a
+    /// straight round trip per unit of relative path length.
+    fn crossing(&self, cos: f64) -> RGBColor {
+        let cos_inside = (1. - (1. - cos * cos) / (self.ior * self.ior)).max(1e-6).sqrt();
+        let exponent = 0.5 / cos_inside;
+        let tint = self.tint;
+        let absorption = RGBColor::new(tint.x.powf(exponent), tint.y.powf(exponent), tint.z.powf(exponent));
+        absorption * (1. - microfacet::fresnel_dielectric(cos, self.ior))
+    }
+
+    /// Fraction of light reaching the base and leaving again, for the
+    /// cosines of the outgoing and incoming directions outside the coat.
+    fn transmission(&self, cos_o: f64, cos_i: f64) -> RGBColor {
+        spectrum::upsample(self.crossing(cos_o) * self.crossing(cos_i))
+    }
+}
+
+impl Material for CoatedMaterial {
+    fn scatter(&self, ray: Ray, hit_info: HitInfo) -> Option<ScatterRecord> {
+        let frame = Onb::from_w(hit_info.get_normal());
+        let wo = frame.to_local(-ray.direction.normalized());
+        if wo.z <= 0. {
+            return None;
+        }
+
+        // Sample the coat as often as it reflects
+        let coat_probability = microfacet::fresnel_dielectric(wo.z, self.ior);
+        let dist = material::roughness_at(self.roughness.as_ref(), &hit_info);
+        if rand_utils::rand_f64() < coat_probability {
+            match dist {
+                Some(dist) => {
+                    let wi = dist.sample_reflection(wo)?;
+                    let scattered = frame.local(wi);
+                    let pdf = self.pdf(ray, hit_info, scattered);
+                    let attenuation = self.eval(ray, hit_info, scattered) / pdf;
+                    let ray = Ray::new(hit_info.get_point(), scattered);
+                    return Some(ScatterRecord { ray, attenuation, pdf, is_specular: false, lobe: Lobe::Specular });
+                },
+                None => {
+                    let direction = frame.local(Vec3::new(-wo.x, -wo.y, wo.z));
+                    return Some(ScatterRecord::specular(Ray::new(hit_info.get_point(), direction), RGBColor::ones()));
+                },
+            }
+        }
+
+        let srec = self.base.scatter(ray, hit_info)?;
+        let cos_i = Vec3::dot(srec.ray.direction.normalized(), hit_info.get_normal()).abs();
+        if srec.is_specular {
+            let attenuation = srec.attenuation * self.transmission(wo.z, cos_i) / (1. - coat_probability);
+            return Some(ScatterRecord { attenuation, ..srec });
+        }
+
+        let pdf = self.pdf(ray, hit_info, srec.ray.direction);
+        if pdf <= 0. {
+            return None;
+        }
+        let attenuation = self.eval(ray, hit_info, srec.ray.direction) / pdf;
+        Some(ScatterRecord { attenuation, pdf, ..srec })
+    }
+
+    fn emitted(&self, ray: Ray, hit_info: HitInfo) -> RGBColor {
+        let le = self.base.emitted(ray, hit_info);
+        if le.is_near_zero() {
+            return le;
+        }
+        let cos_o = Vec3::dot(-ray.direction.normalized(), hit_info.get_normal()).abs();
+        le * self.crossing(cos_o)
+    }
+
+    fn eval(&self, ray: Ray, hit_info: HitInfo, scattered: Vec3) -> RGBColor {
+        let frame = Onb::from_w(hit_info.get_normal());
+        let wo = frame.to_local(-ray.direction.normalized());
+        let wi = frame.to_local(scattered.normalized());
+
+        let coat = match material::roughness_at(self.roughness.as_ref(), &hit_info) {
+            Some(dist) => {
+                let wm = (wo + wi).normalized();
+                microfacet::fresnel_dielectric(Vec3::dot(wo, wm), self.ior) * dist.eval_reflection(wo, wi)
+            },
+            None => 0.,
+        };
+        let base = self.base.eval(ray, hit_info, scattered) * self.transmission(wo.z, wi.z.abs());
+        base + RGBColor::ones() * coat
+    }
+
+    fn pdf(&self, ray: Ray, hit_info: HitInfo, scattered: Vec3) -> f64 {
+        let frame = Onb::from_w(hit_info.get_normal());
+        let wo = frame.to_local(-ray.direction.normalized());
+        let wi = frame.to_local(scattered.normalized());
+
+        let coat_probability = microfacet::fresnel_dielectric(wo.z, self.ior);
+        let coat = match material::roughness_at(self.roughness.as_ref(), &hit_info) {
+            Some(dist) => dist.pdf_reflection(wo, wi),
+            None => 0.,
+        };
+        coat_probability * coat + (1. - coat_probability) * self.base.pdf(ray, hit_info, scattered)
+    }
+
+    fn albedo(&self, hit_info: HitInfo) -> RGBColor {
+        self.base.albedo(hit_info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Conductor, Lambertian};
+    use crate::vec3::Point;
+    use std::f64::consts::PI;
+
+    /// Checks that `scatter` weights its samples with `eval / pdf`, that
+    /// `pdf` integrates to one and that sampling reflects as much light as
+    /// integrating `eval` over the sphere does.
+    fn check_consistency(material: &dyn Material) {
+        let ray = Ray::new(Point::new(0.3, 0.2, 1.), Vec3::new(-0.3, -0.2, -1.));
+        let hit = HitInfo::new(Point::zero(), Vec3::new(0., 0., 1.), 1., (0.5, 0.5), material, ray);
+
+        const SAMPLES: usize = 200_000;
+        let (mut sampled, mut integrated, mut pdf_total) = (RGBColor::zero(), RGBColor::zero(), 0.);
+        for _ in 0..SAMPLES {
+            if let Some(srec) = material.scatter(ray, hit) {
+                assert!(!srec.is_specular);
+                let direction = srec.ray.direction;
+                let pdf = material.pdf(ray, hit, direction);
+                assert!((srec.pdf - pdf).abs() <= 1e-9 * pdf.max(1.));
+                let expected = material.eval(ray, hit, direction) / pdf;
+                assert!((srec.attenuation - expected).length() <= 1e-9 * expected.length().max(1.));
+                sampled += srec.attenuation;
+            }
+
+            let direction = Vec3::rand_on_unit_sphere();
+            integrated += material.eval(ray, hit, direction) * (4. * PI);
+            pdf_total += material.pdf(ray, hit, direction) * (4. * PI);
+        }
+
+        let n = SAMPLES as f64;
+        assert!((pdf_total / n - 1.).abs() < 0.03, "pdf integrates to {}", pdf_total / n);
+        assert!((sampled / n - integrated / n).length() < 0.03, "{:?} vs {:?}", sampled / n, integrated / n);
+    }
+
+    #[test]
+    fn mix_is_consistent() {
+        let diffuse = Box::new(Lambertian::new(RGBColor::new(0.8, 0.4, 0.2)));
+        let metal = Box::new(Conductor::silver(0.5));
+        check_consistency(&MixMaterial::new(diffuse, metal, 0.3));
+    }
+
+    #[test]
+    fn coated_is_consistent() {
+        let base = Box::new(Lambertian::new(RGBColor::new(0.2, 0.5, 0.8)));
+        let coated = CoatedMaterial::new(base, 1.5, 0.5).with_tint(RGBColor::new(0.9, 0.8, 0.7));
+        check_consistency(&coated);
+    }
+}
@@ -4,4 +4,25 @@ pub mod hit_info;
 pub mod hittable;
 pub mod utils;
 pub mod material;
-pub mod sphere;
\ No newline at end of file
+pub mod sphere;
+pub mod onb;
+pub mod medium;
+pub mod aabb;
+pub mod voxel_grid;
+pub mod texture;
+pub mod microfacet;
+pub mod principled;
+pub mod spectrum;
+pub mod thin_film;
+pub mod triangle;
+pub mod subsurface;
+pub mod layered;
+pub mod diffuse;
+pub mod light;
+pub mod image;
+pub mod distribution;
+pub mod environment_map;
+pub mod sky;
+pub mod environment;
+pub mod light_sampler;
+pub mod ies;
@@ -0,0 +1,324 @@
+use std::f64::consts::PI;
+
+use crate::aabb::Aabb;
+use crate::ies::IesProfile;
+use crate::onb::Onb;
+use crate::utils::math_utils::degrees_to_radians;
+use crate::vec3::{Point, RGBColor, Vec3};
+
+/// Luminous efficacy used to turn photometric units into radiometric ones.
+/// Exact for 555nm light, and the usual approximation for white lights.
+const LUMENS_PER_WATT: f64 = 683.;
+
+/// Strength of a point or spot light, in the units other tools expose it in.
+#[derive(Debug, Clone, Copy)]
+pub enum Intensity {
+    /// Radiant intensity, in W/sr.
+    Radiant(f64),
+    /// Radiant power in watts, spread over the whole sphere. This is how
+    /// Blender and most DCCs specify point and spot lights: narrowing a
+    /// spot's cone doesn't make it brighter.
+    Watts(f64),
+    /// Luminous intensity, in candela.
+    Candela(f64),
+    /// Luminous power in lumens, spread over the whole sphere.
+    Lumens(f64),
+}
+
+impl Intensity {
+    /// Radiant intensity in W/sr.
+    pub fn radiant(&self) -> f64 {
+        match *self {
+            Intensity::Radiant(i) => i,
+            Intensity::Watts(power) => power / (4. * PI),
+            Intensity::Candela(cd) => cd / LUMENS_PER_WATT,
+            Intensity::Lumens(lm) => lm / (4. * PI * LUMENS_PER_WATT),
+        }
+    }
+}
+
+/// Strength of a directional light, measured on a surface facing it.
+#[derive(Debug, Clone, Copy)]
+pub enum Irradiance {
+    /// Irradiance in W/m², Blender's sun "strength".
+    Radiant(f64),
+    /// Illuminance in lux, e.g. ~100000 for direct sunlight.
+    Lux(f64),
+}
+
+impl Irradiance {
+    /// Irradiance in W/m².
+    pub fn radiant(&self) -> f64 {
+        match *self {
+            Irradiance::Radiant(e) => e,
+            Irradiance::Lux(lux) => lux / LUMENS_PER_WATT,
+        }
+    }
+}
+
+/// Light reaching a point from a light, before checking for occluders.
+/// `radiance / pdf` is the estimate of the light's contribution.
+#[derive(Debug, Clone, Copy)]
+pub struct IncidentLight {
+    /// Unit direction from the point towards the light.
+    pub direction: Vec3,
+    /// Distance to the sampled point on the light, infinite for directional lights.
+    pub distance: f64,
+    pub radiance: RGBColor,
+    /// Solid-angle density of the sampled direction, or 1 for lights that
+    /// can only be reached along a single direction.
+    pub pdf: f64,
+}
+
+/// Analytic light source, with no geometry: rays never hit it, so it only
+/// contributes through light sampling.
+pub trait Light {
+    /// Samples the light as seen from `point`, or `None` if it sends no light there.
+    fn sample_li(&self, point: Point) -> Option<IncidentLight>;
+
+    /// Total emitted power as luminance, used to pick bright lights more
+    /// often. For lights infinitely far away, the power falling on a unit disc.
+    fn power(&self) -> f64;
+
+    /// Region the light emits from, `None` if it's infinitely far away.
+    fn bounds(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+/// IES profile attached to a light, in the fixture's orientation.
+struct Photometry {
+    profile: IesProfile,
+    frame: Onb,
+    average: f64,
+}
+
+impl Photometry {
+    /// `nadir` is the fixture's main direction of emission, and `length`
+    /// the direction its zero horizontal angle points to.
+    fn new(profile: IesProfile, nadir: Vec3, length: Vec3) -> Photometry {
+        let w = nadir.normalized();
+        let across = length - w * Vec3::dot(length, w);
+        let frame = match across.length_squared() > 1e-12 {
+            true => {
+                let u = across.normalized();
+                Onb { u, v: Vec3::cross(w, u), w }
+            },
+            false => Onb::from_w(w),
+        };
+
+        let average = profile.average_relative();
+        Photometry { profile, frame, average }
+    }
+
+    /// Fraction of the peak intensity sent along `direction`, leaving the light.
+    fn scale(&self, direction: Vec3) -> f64 {
+        self.profile.relative(self.frame.to_local(direction))
+    }
+}
+
+/// Light emitted from a single point, equally in all directions unless it
+/// has a photometric profile.
+pub struct PointLight {
+    position: Point,
+    intensity: RGBColor,
+    photometry: Option<Photometry>,
+}
+
+impl PointLight {
+    pub fn new(position: Point, color: RGBColor, intensity: Intensity) -> PointLight {
+        PointLight { position, intensity: color * intensity.radiant(), photometry: None }
+    }
+
+    /// Shapes the light with an IES `profile`, with the fixture aiming at
+    /// `nadir` and its length along `length`. The light's intensity is then
+    /// the profile's peak: pass `profile.peak_intensity()` to `new` to match
+    /// the photometric data.
+    pub fn with_profile(mut self, profile: IesProfile, nadir: Vec3, length: Vec3) -> PointLight {
+        self.photometry = Some(Photometry::new(profile, nadir, length));
+        self
+    }
+}
+
+impl Light for PointLight {
+    fn sample_li(&self, point: Point) -> Option<IncidentLight> {
+        let to_light = self.position - point;
+        let distance_squared = to_light.length_squared();
+        if distance_squared == 0. {
+            return None;
+        }
+
+        let distance = distance_squared.sqrt();
+        let direction = to_light / distance;
+        let scale = match &self.photometry {
+            Some(photometry) => photometry.scale(-direction),
+            None => 1.,
+        };
+        if scale <= 0. {
+            return None;
+        }
+
+        Some(IncidentLight {
+            direction,
+            distance,
+            radiance: self.intensity * (scale / distance_squared),
+            pdf: 1.,
+        })
+    }
+
+    fn power(&self) -> f64 {
+        let average = self.photometry.as_ref().map_or(1., |p| p.average);
+        4. * PI * average * self.intensity.luminance()
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::new(self.position, self.position))
+    }
+}
+
+/// Point light restricted to a cone. Intensity is full inside `inner_angle`
+/// from the axis and falls off smoothly to zero at `outer_angle`.
+pub struct SpotLight {
+    position: Point,
+    axis: Vec3,
+    intensity: RGBColor,
+    cos_inner: f64,
+    cos_outer: f64,
+    photometry: Option<Photometry>,
+}
+
+impl SpotLight {
+    /// Spot at `position` pointing towards `target`, with cone half-angles in degrees.
+    pub fn new(
+        position: Point,
+        target: Point,
+        color: RGBColor,
+        intensity: Intensity,
+        inner_angle: f64,
+        outer_angle: f64,
+        ) -> SpotLight
+    {
+        let outer_angle = outer_angle.clamp(0., 180.);
+        let inner_angle = inner_angle.clamp(0., outer_angle);
+        SpotLight {
+            position,
+            axis: (target - position).normalized(),
+            intensity: color * intensity.radiant(),
+            cos_inner: degrees_to_radians(inner_angle).cos(),
+            cos_outer: degrees_to_radians(outer_angle).cos(),
+            photometry: None,
+        }
+    }
+
+    /// Shapes the light with an IES `profile` aimed along the spot's axis,
+    /// with the fixture's length along `length`. The cone still applies on
+    /// top of it, so open it fully to keep the profile alone.
+    pub fn with_profile(mut self, profile: IesProfile, length: Vec3) -> SpotLight {
+        self.photometry = Some(Photometry::new(profile, self.axis, length));
+        self
+    }
+
+    /// Fraction of the full intensity sent along `direction`, leaving the light.
+    fn falloff(&self, direction: Vec3) -> f64 {
+        let cos_theta = Vec3::dot(self.axis, direction);
+        if cos_theta >= self.cos_inner {
+            return 1.;
+        }
+        if cos_theta <= self.cos_outer {
+            return 0.;
+        }
+
+        let t = (cos_theta - self.cos_outer) / (self.cos_inner - self.cos_outer);
+        t * t * (3. - 2. * t)
+    }
+}
+
+impl Light for SpotLight {
+    fn sample_li(&self, point: Point) -> Option<IncidentLight> {
+        let to_light = self.position - point;
+        let distance_squared = to_light.length_squared();
+        if distance_squared == 0. {
+            return None;
+        }
+
+        let distance = distance_squared.sqrt();
+        let direction = to_light / distance;
+        let falloff = self.falloff(-direction) * match &self.photometry {
+            Some(photometry) => photometry.scale(-direction),
+            None => 1.,
+        };
+        if falloff <= 0. {
+            return None;
+        }
+
+        Some(IncidentLight {
+            direction,
+            distance,
+            radiance: self.intensity * (falloff / distance_squared),
+            pdf: 1.,
+        })
+    }
+
+    fn power(&self) -> f64 {
+        // Solid angle of the cone, counting the falloff region as half lit
+        let cone = 2. * PI * (1. - 0.5 * (self.cos_inner + self.cos_outer));
+        match &self.photometry {
+            // The profile is usually the narrower of the two
+            Some(photometry) => cone.min(4. * PI * photometry.average) * self.intensity.luminance(),
+            None => cone * self.intensity.luminance(),
+        }
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::new(self.position, self.position))
+    }
+}
+
+/// Light coming from infinitely far away along a single direction, like the
+/// sun. A non-zero angular diameter turns it into a small disc in the sky,
+/// which gives soft shadows; irradiance stays the same either way.
+pub struct DirectionalLight {
+    to_light: Onb,
+    irradiance: RGBColor,
+    cos_theta_max: f64,
+}
+
+impl DirectionalLight {
+    /// Light travelling along `direction`, whose disc spans `angular_diameter`
+    /// degrees (about 0.53 for the sun).
+    pub fn new(direction: Vec3, color: RGBColor, irradiance: Irradiance, angular_diameter: f64) -> DirectionalLight {
+        let half_angle = degrees_to_radians(angular_diameter.clamp(0., 180.)) / 2.;
+        DirectionalLight {
+            to_light: Onb::from_w(-direction),
+            irradiance: color * irradiance.radiant(),
+            cos_theta_max: half_angle.cos(),
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample_li(&self, _point: Point) -> Option<IncidentLight> {
+        if self.cos_theta_max >= 1. {
+            return Some(IncidentLight {
+                direction: self.to_light.w,
+                distance: f64::INFINITY,
+                radiance: self.irradiance,
+                pdf: 1.,
+            });
+        }
+
+        let local = Vec3::rand_in_cone(self.cos_theta_max);
+        let solid_angle = 2. * PI * (1. - self.cos_theta_max);
+
+        Some(IncidentLight {
+            direction: self.to_light.local(local).normalized(),
+            distance: f64::INFINITY,
+            radiance: self.irradiance / solid_angle,
+            pdf: 1. / solid_angle,
+        })
+    }
+
+    fn power(&self) -> f64 {
+        PI * self.irradiance.luminance()
+    }
+}
@@ -0,0 +1,324 @@
+use crate::aabb::Aabb;
+use crate::distribution::Distribution1D;
+use crate::vec3::Point;
+
+/// How direct lighting picks which light to sample from a shading point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LightSelection {
+    /// Every light is equally likely.
+    Uniform,
+    /// Lights are picked in proportion to their emitted power.
+    Power,
+    /// Lights are picked by walking a BVH over them, choosing at each node
+    /// the child with the larger estimated contribution at the shading point.
+    #[default]
+    Bvh,
+}
+
+/// What the sampler knows about a light: where it emits from (`None` for
+/// lights infinitely far away) and its emitted power, as luminance.
+#[derive(Debug, Clone, Copy)]
+pub struct LightInfo {
+    pub bounds: Option<Aabb>,
+    pub power: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum NodeContent {
+    Leaf(usize),
+    Interior(usize, usize),
+}
+
+/// Node of the light BVH, bounding the lights below it and their total power.
+#[derive(Debug, Clone, Copy)]
+struct LightNode {
+    bounds: Aabb,
+    power: f64,
+    content: NodeContent,
+}
+
+impl LightNode {
+    /// Rough estimate of the light the node's emitters send to `point`: their
+    /// power over the squared distance, which is capped by the node's size so
+    /// that points inside or next to it don't blow up.
+    fn importance(&self, point: Point) -> f64 {
+        let center = (self.bounds.min + self.bounds.max) * 0.5;
+        let distance_squared = (point - center).length_squared();
+        let radius_squared = (self.bounds.max - self.bounds.min).length_squared() / 4.;
+        self.power / distance_squared.max(radius_squared).max(1e-8)
+    }
+}
+
+/// Picks one of a scene's lights for next-event estimation, with the
+/// probability of picking each one available to weight the samples.
+pub struct LightSampler {
+    selection: LightSelection,
+    count: usize,
+    power: Option<Distribution1D>,
+    nodes: Vec<LightNode>,
+    /// Branches taken from the root to reach each bounded light, as bits
+    /// (1 for the right child) and their number.
+    trails: Vec<(u64, u32)>,
+    infinite: Vec<usize>,
+}
+
+impl LightSampler {
+    pub fn new(selection: LightSelection, lights: &[LightInfo]) -> LightSampler {
+        let mut sampler = LightSampler {
+            selection,
+            count: lights.len(),
+            power: None,
+            nodes: vec![],
+            trails: vec![(0, 0); lights.len()],
+            infinite: vec![],
+        };
+
+        match selection {
+            LightSelection::Uniform => {},
+            LightSelection::Power if lights.is_empty() => {},
+            LightSelection::Power => {
+                sampler.power = Some(Distribution1D::new(lights.iter().map(|l| l.power.max(0.)).collect()));
+            },
+            LightSelection::Bvh => {
+                let mut bounded: Vec<usize> = vec![];
+                for (i, light) in lights.iter().enumerate() {
+                    match light.bounds {
+                        Some(_) if light.power > 0. => bounded.push(i),
+                        Some(_) => {},
+                        None => sampler.infinite.push(i),
+                    }
+                }
+                if !bounded.is_empty() {
+                    sampler.build(lights, &mut bounded, 0, 0);
+                }
+            },
+        }
+
+        sampler
+    }
+
+    /// Picks a light to sample from `point` using the uniform number `u`,
+    /// returning its index and the probability of picking it.
+    pub fn sample(&self, point: Point, u: f64) -> Option<(usize, f64)> {
+        if self.count == 0 {
+            return None;
+        }
+
+        match self.selection {
+            LightSelection::Uniform => {
+                let idx = ((u * self.count as f64) as usize).min(self.count - 1);
+                Some((idx, 1. / self.count as f64))
+            },
+            LightSelection::Power => {
+                let power = self.power.as_ref()?;
+                let (_, pdf, idx) = power.sample(u);
+                Some((idx, pdf / self.count as f64))
+            },
+            LightSelection::Bvh => self.sample_bvh(point, u),
+        }
+    }
+
+    /// Probability of `sample` picking `light` from `point`.
+    pub fn pmf(&self, point: Point, light: usize) -> f64 {
+        if light >= self.count {
+            return 0.;
+        }
+
+        match self.selection {
+            LightSelection::Uniform => 1. / self.count as f64,
+            LightSelection::Power => match &self.power {
+                Some(power) => power.pdf(light) / self.count as f64,
+                None => 0.,
+            },
+            LightSelection::Bvh => self.pmf_bvh(point, light),
+        }
+    }
+
+    /// Chance of picking an infinite light rather than walking the BVH,
+    /// which counts as a single light.
+    fn infinite_probability(&self) -> f64 {
+        let tree = match self.nodes.is_empty() {
+            true => 0.,
+            false => 1.,
+        };
+        match self.infinite.is_empty() {
+            true => 0.,
+            false => self.infinite.len() as f64 / (self.infinite.len() as f64 + tree),
+        }
+    }
+
+    fn sample_bvh(&self, point: Point, u: f64) -> Option<(usize, f64)> {
+        let p_infinite = self.infinite_probability();
+        if u < p_infinite {
+            let n = self.infinite.len();
+            let idx = ((u / p_infinite * n as f64) as usize).min(n - 1);
+            return Some((self.infinite[idx], p_infinite / n as f64));
+        }
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let mut u = (u - p_infinite) / (1. - p_infinite);
+        let mut pmf = 1. - p_infinite;
+        let mut node = 0;
+        loop {
+            match self.nodes[node].content {
+                NodeContent::Leaf(light) => return Some((light, pmf)),
+                NodeContent::Interior(left, right) => {
+                    let p_left = self.left_probability(point, left, right);
+                    if u < p_left {
+                        u /= p_left;
+                        pmf *= p_left;
+                        node = left;
+                    } else {
+                        u = ((u - p_left) / (1. - p_left)).min(1. - f64::EPSILON);
+                        pmf *= 1. - p_left;
+                        node = right;
+                    }
+                    if pmf <= 0. {
+                        return None;
+                    }
+                },
+            }
+        }
+    }
+
+    fn pmf_bvh(&self, point: Point, light: usize) -> f64 {
+        let p_infinite = self.infinite_probability();
+        if self.infinite.contains(&light) {
+            return p_infinite / self.infinite.len() as f64;
+        }
+
+        if self.nodes.is_empty() {
+            return 0.;
+        }
+
+        let (bits, depth) = self.trails[light];
+        let mut pmf = 1. - p_infinite;
+        let mut node = 0;
+        for level in 0..depth {
+            if let NodeContent::Interior(left, right) = self.nodes[node].content {
+                let p_left = self.left_probability(point, left, right);
+                match bits >> level & 1 {
+                    0 => {
+                        pmf *= p_left;
+                        node = left;
+                    },
+                    _ => {
+                        pmf *= 1. - p_left;
+                        node = right;
+                    },
+                }
+            }
+        }
+
+        match self.nodes[node].content {
+            NodeContent::Leaf(l) if l == light => pmf,
+            _ => 0.,
+        }
+    }
+
+    fn left_probability(&self, point: Point, left: usize, right: usize) -> f64 {
+        let importance_left = self.nodes[left].importance(point);
+        let importance_right = self.nodes[right].importance(point);
+        match importance_left + importance_right > 0. {
+            true => importance_left / (importance_left + importance_right),
+            false => 0.5,
+        }
+    }
+
+    /// Builds the subtree over `items`, splitting them at the median of their
+    /// centers along the axis where those spread the most.
+    fn build(&mut self, lights: &[LightInfo], items: &mut [usize], bits: u64, depth: u32) -> usize {
+        let bounds_of = |i: usize| lights[i].bounds.expect("only bounded lights go in the light BVH");
+        let bounds = items.iter()
+            .skip(1)
+            .fold(bounds_of(items[0]), |b, &i| b.union(&bounds_of(i)));
+        let power = items.iter().map(|&i| lights[i].power).sum();
+
+        let node = self.nodes.len();
+        if items.len() == 1 {
+            self.nodes.push(LightNode { bounds, power, content: NodeContent::Leaf(items[0]) });
+            self.trails[items[0]] = (bits, depth);
+            return node;
+        }
+
+        let center = |i: usize| (bounds_of(i).min + bounds_of(i).max) * 0.5;
+        let first = center(items[0]);
+        let centers = items.iter().fold(Aabb::new(first, first), |b, &i| b.union(&Aabb::new(center(i), center(i))));
+        let extent = centers.max - centers.min;
+        let axis = |p: Point| match (extent.x >= extent.y && extent.x >= extent.z, extent.y >= extent.z) {
+            (true, _) => p.x,
+            (false, true) => p.y,
+            (false, false) => p.z,
+        };
+        items.sort_by(|&a, &b| axis(center(a)).total_cmp(&axis(center(b))));
+
+        // Children are filled in once built, as they come after their parent
+        self.nodes.push(LightNode { bounds, power, content: NodeContent::Leaf(usize::MAX) });
+        let (left_items, right_items) = items.split_at_mut(items.len() / 2);
+        let left = self.build(lights, left_items, bits, depth + 1);
+        let right = self.build(lights, right_items, bits | 1 << depth, depth + 1);
+        self.nodes[node].content = NodeContent::Interior(left, right);
+
+        node
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bounded lights of various power, one of them dark, and two infinite ones.
+    fn lights() -> Vec<LightInfo> {
+        let bounded = |x: f64, power: f64| LightInfo {
+            bounds: Some(Aabb::new(Point::new(x, 0., 0.), Point::new(x + 1., 1., 1.))),
+            power,
+        };
+        vec![
+            bounded(0., 1.),
+            LightInfo { bounds: None, power: 5. },
+            bounded(4., 10.),
+            bounded(-6., 0.),
+            bounded(9., 3.),
+            LightInfo { bounds: None, power: 2. },
+            bounded(-2., 0.5),
+        ]
+    }
+
+    #[test]
+    fn samples_match_their_pmf() {
+        let lights = lights();
+        let points = [Point::new(0.5, 3., 0.5), Point::new(10., -1., 0.), Point::new(-50., 20., 7.)];
+        for selection in [LightSelection::Uniform, LightSelection::Power, LightSelection::Bvh] {
+            let sampler = LightSampler::new(selection, &lights);
+            for point in points {
+                let total: f64 = (0..lights.len()).map(|i| sampler.pmf(point, i)).sum();
+                assert!((total - 1.).abs() < 1e-9, "{:?}: pmfs sum to {}", selection, total);
+
+                // Stratified numbers pick each light about as often as its pmf says
+                const SAMPLES: usize = 100_000;
+                let mut counts = vec![0; lights.len()];
+                for k in 0..SAMPLES {
+                    let u = (k as f64 + 0.5) / SAMPLES as f64;
+                    let (idx, pmf) = sampler.sample(point, u).unwrap();
+                    assert!((pmf - sampler.pmf(point, idx)).abs() < 1e-9, "{:?}: light {}", selection, idx);
+                    counts[idx] += 1;
+                }
+                for (i, &count) in counts.iter().enumerate() {
+                    let frequency = count as f64 / SAMPLES as f64;
+                    assert!((frequency - sampler.pmf(point, i)).abs() < 1e-3, "{:?}: light {}", selection, i);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn nothing_to_sample_without_lights() {
+        for selection in [LightSelection::Uniform, LightSelection::Power, LightSelection::Bvh] {
+            let sampler = LightSampler::new(selection, &[]);
+            assert!(sampler.sample(Point::zero(), 0.5).is_none());
+            assert_eq!(sampler.pmf(Point::zero(), 0), 0.);
+        }
+    }
+}
@@ -1,12 +1,73 @@
+use std::f64::consts::PI;
+
 use crate::hit_info::HitInfo;
 use crate::ray::Ray;
 use crate::vec3::{RGBColor, Vec3};
+use crate::onb::Onb;
+use crate::microfacet::{self, TrowbridgeReitz};
+use crate::spectrum::{self, Ior};
+use crate::texture::{SolidColor, Texture};
+use crate::thin_film::ThinFilm;
 use crate::utils::rand_utils;
 
-type RayColor = (Ray, RGBColor);
+/// Kind of interaction a scattered ray went through, used to budget bounces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lobe {
+    Diffuse,
+    Specular,
+    Transmission,
+}
+
+/// Outcome of sampling a material: the scattered ray, its weight
+/// (`f * cos / pdf`), the solid-angle pdf it was drawn with and whether it
+/// came from a delta lobe (in which case `pdf` is meaningless).
+///
+/// In spectral mode the weight holds one value per wavelength of the path:
+/// materials upsample their RGB parameters themselves, so that spectral
+/// effects like thin films can be computed at the actual wavelengths.
+#[derive(Debug, Clone, Copy)]
+pub struct ScatterRecord {
+    pub ray: Ray,
+    pub attenuation: RGBColor,
+    pub pdf: f64,
+    pub is_specular: bool,
+    pub lobe: Lobe,
+}
+
+impl ScatterRecord {
+    pub fn specular(ray: Ray, attenuation: RGBColor) -> ScatterRecord {
+        ScatterRecord { ray, attenuation, pdf: 0., is_specular: true, lobe: Lobe::Specular }
+    }
+
+    pub fn transmission(ray: Ray, attenuation: RGBColor) -> ScatterRecord {
+        ScatterRecord { ray, attenuation, pdf: 0., is_specular: true, lobe: Lobe::Transmission }
+    }
+}
 
 pub trait Material {
-    fn scatter(&self, ray: Ray,  hit_info: HitInfo) -> Option<RayColor>;
+    fn scatter(&self, ray: Ray, hit_info: HitInfo) -> Option<ScatterRecord>;
+
+    /// Radiance emitted at the hit point towards the origin of `ray`.
+    fn emitted(&self, _ray: Ray, _hit_info: HitInfo) -> RGBColor {
+        RGBColor::zero()
+    }
+
+    /// BSDF times the cosine term for light leaving along `scattered`.
+    /// Delta lobes are not included. Like `ScatterRecord::attenuation`, it
+    /// holds one value per wavelength in spectral mode.
+    fn eval(&self, _ray: Ray, _hit_info: HitInfo, _scattered: Vec3) -> RGBColor {
+        RGBColor::zero()
+    }
+
+    /// Solid-angle density with which `scatter` picks `scattered`.
+    fn pdf(&self, _ray: Ray, _hit_info: HitInfo, _scattered: Vec3) -> f64 {
+        0.
+    }
+
+    /// Base color of the surface, as shown by the albedo debug view.
+    fn albedo(&self, _hit_info: HitInfo) -> RGBColor {
+        RGBColor::ones()
+    }
 }
 
 pub struct Lambertian {
@@ -20,8 +81,9 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _: Ray, hit_info: HitInfo) -> Option<RayColor> {
-        let mut scatter_direction = hit_info.get_normal() + Vec3::rand_unit_vector();
+    fn scatter(&self, _: Ray, hit_info: HitInfo) -> Option<ScatterRecord> {
+        let uvw = Onb::from_w(hit_info.get_normal());
+        let mut scatter_direction = uvw.local(Vec3::rand_cosine_direction());
 
         // Catch degenerate scatter direction
         // (when the direction is very near zero, which will lead to infinity and NaN problems)
@@ -30,8 +92,22 @@ impl Material for Lambertian {
         }
 
         let ray = Ray::new(hit_info.get_point(), scatter_direction);
-        let attenuation = self.albedo;
-        Some((ray, attenuation))
+        let pdf = Vec3::dot(uvw.w, scatter_direction.normalized()).max(0.) / PI;
+        let attenuation = spectrum::upsample(self.albedo);
+        Some(ScatterRecord { ray, attenuation, pdf, is_specular: false, lobe: Lobe::Diffuse })
+    }
+
+    fn eval(&self, _: Ray, hit_info: HitInfo, scattered: Vec3) -> RGBColor {
+        let cosine = Vec3::dot(hit_info.get_normal(), scattered.normalized()).max(0.);
+        spectrum::upsample(self.albedo) * (cosine / PI)
+    }
+
+    fn pdf(&self, _: Ray, hit_info: HitInfo, scattered: Vec3) -> f64 {
+        Vec3::dot(hit_info.get_normal(), scattered.normalized()).max(0.) / PI
+    }
+
+    fn albedo(&self, _: HitInfo) -> RGBColor {
+        self.albedo
     }
 }
 
@@ -48,30 +124,79 @@ impl Metal {
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray: Ray, hit_info: HitInfo) -> Option<RayColor> {
+    fn scatter(&self, ray: Ray, hit_info: HitInfo) -> Option<ScatterRecord> {
         let reflected = ray.direction.normalized().reflect(hit_info.get_normal());
 
         let scattered = Ray::new(
             hit_info.get_point(),
             reflected + Vec3::rand_in_unit_sphere() * self.fuzziness
         );
-        let attenuation = self.albedo;
+        let attenuation = spectrum::upsample(self.albedo);
 
         if Vec3::dot(scattered.direction, hit_info.get_normal()) > 0. {
-            Some((scattered, attenuation))
+            Some(ScatterRecord::specular(scattered, attenuation))
         } else {
             None
         }
     }
+
+    fn albedo(&self, _: HitInfo) -> RGBColor {
+        self.albedo
+    }
 }
 
+/// Smooth glass. An optional absorption coefficient tints light by the
+/// distance it travels inside (Beer-Lambert law), which is applied whenever
+/// a ray leaves through the inside of the surface. Media nested inside the
+/// glass aren't supported: the whole segment ending on the back face is
+/// taken to be inside this glass, even if it crossed another object.
+///
+/// With a wavelength-dependent IOR, spectral renders show dispersion.
 pub struct Dielectric {
-    refraction_index: f64
+    ior: Ior,
+    absorption: RGBColor,
+    thin_film: Option<ThinFilm>,
 }
 
 impl Dielectric {
     pub fn new(refraction_index: f64) -> Dielectric {
-        Dielectric { refraction_index }
+        Dielectric::dispersive(Ior::Constant(refraction_index))
+    }
+
+    pub fn dispersive(ior: Ior) -> Dielectric {
+        Dielectric { ior, absorption: RGBColor::zero(), thin_film: None }
+    }
+
+    /// Glass absorbing `absorption` per unit distance, per channel.
+    pub fn absorbing(refraction_index: f64, absorption: RGBColor) -> Dielectric {
+        Dielectric { ior: Ior::Constant(refraction_index), absorption, thin_film: None }
+    }
+
+    /// Glass letting through `color` after travelling `distance` inside it.
+    /// Distances too small to make sense, NaN included, are clamped.
+    pub fn tinted(refraction_index: f64, color: RGBColor, distance: f64) -> Dielectric {
+        let distance = distance.max(1e-6);
+        let channel = |c: f64| -c.clamp(1e-6, 1.).ln() / distance;
+        let absorption = RGBColor::new(channel(color.x), channel(color.y), channel(color.z));
+        Dielectric::absorbing(refraction_index, absorption)
+    }
+
+    /// Coats the outside of the glass with an interference film.
+    pub fn with_thin_film(mut self, thin_film: ThinFilm) -> Dielectric {
+        self.thin_film = Some(thin_film);
+        self
+    }
+
+    /// Beer-Lambert transmittance over the segment `ray` travelled to reach
+    /// `hit_info`, which lies inside the glass when it hits a back face.
+    fn transmittance(&self, ray: Ray, hit_info: &HitInfo) -> RGBColor {
+        if hit_info.get_front_face() || self.absorption.is_near_zero() {
+            return RGBColor::ones();
+        }
+
+        let distance = hit_info.get_t() * ray.direction.length();
+        let optical_depth = self.absorption * distance;
+        RGBColor::new((-optical_depth.x).exp(), (-optical_depth.y).exp(), (-optical_depth.z).exp())
     }
 
     fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
@@ -81,10 +206,19 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, ray: Ray,  hit_info: HitInfo) -> Option<RayColor> {
+    fn scatter(&self, ray: Ray,  hit_info: HitInfo) -> Option<ScatterRecord> {
+        // Each wavelength refracts its own way, so only the hero one survives
+        let refraction_index = match spectrum::hero_wavelength() {
+            Some(lambda) if self.ior.is_dispersive() => {
+                spectrum::terminate_secondary();
+                self.ior.at(lambda)
+            },
+            _ => self.ior.at(spectrum::REFERENCE_WAVELENGTH),
+        };
+
         let ref_ratio = match hit_info.get_front_face() {
-            true => 1.0 / self.refraction_index,
-            false => self.refraction_index
+            true => 1.0 / refraction_index,
+            false => refraction_index
         };
 
         let unit_direction = ray.direction.normalized();
@@ -93,16 +227,373 @@ impl Material for Dielectric {
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
         let cannot_refract = ref_ratio * sin_theta > 1.0;
-        let p = Dielectric::reflectance(cos_theta, ref_ratio) > rand_utils::rand_f64();
-        let condition = cannot_refract || p;
-        let direction = match condition {
-            true => unit_direction.reflect(hit_info.get_normal()),
-            false => unit_direction.refract(hit_info.get_normal(), ref_ratio),
+        let attenuation = spectrum::upsample(self.transmittance(ray, &hit_info));
+
+        // With a film the reflectance depends on wavelength: pick a side by
+        // its average and reweight each channel accordingly
+        let (reflect, attenuation) = match (&self.thin_film, cannot_refract) {
+            (Some(film), false) => {
+                let (outside, substrate) = match hit_info.get_front_face() {
+                    true => (1., self.ior),
+                    false => (refraction_index, Ior::Constant(1.)),
+                };
+                let r = film.reflectance(&hit_info, cos_theta, outside, |lambda| (substrate.at(lambda), 0.));
+                let p = ((r.x + r.y + r.z) / 3.).clamp(0.001, 0.999);
+                match rand_utils::rand_f64() < p {
+                    true => (true, attenuation * r / p),
+                    false => (false, attenuation * (RGBColor::ones() - r) / (1. - p)),
+                }
+            },
+            _ => {
+                let p = Dielectric::reflectance(cos_theta, ref_ratio) > rand_utils::rand_f64();
+                (cannot_refract || p, attenuation)
+            },
+        };
+
+        match reflect {
+            true => {
+                let direction = unit_direction.reflect(hit_info.get_normal());
+                Some(ScatterRecord::specular(Ray::new(hit_info.get_point(), direction), attenuation))
+            },
+            false => {
+                let direction = unit_direction.refract(hit_info.get_normal(), ref_ratio);
+                Some(ScatterRecord::transmission(Ray::new(hit_info.get_point(), direction), attenuation))
+            },
+        }
+    }
+}
+
+/// Microfacet distribution at the hit point, or `None` where the surface is
+/// smooth enough to be treated as a perfect mirror.
+pub(crate) fn roughness_at(roughness: &dyn Texture, hit_info: &HitInfo) -> Option<TrowbridgeReitz> {
+    let (u, v) = hit_info.get_uv();
+    let r = roughness.scalar(u, v, hit_info.get_point());
+    match r < TrowbridgeReitz::SMOOTH_ROUGHNESS {
+        true => None,
+        false => Some(TrowbridgeReitz::from_roughness(r)),
+    }
+}
+
+/// Metal with a GGX microfacet surface and a complex index of refraction
+/// (`eta + i k`, per RGB channel).
+pub struct Conductor {
+    pub eta: RGBColor,
+    pub k: RGBColor,
+    roughness: Box<dyn Texture>,
+    thin_film: Option<ThinFilm>,
+}
+
+impl Conductor {
+    pub fn new(eta: RGBColor, k: RGBColor, roughness: f64) -> Conductor {
+        Conductor::textured(eta, k, Box::new(SolidColor::scalar(roughness)))
+    }
+
+    pub fn textured(eta: RGBColor, k: RGBColor, roughness: Box<dyn Texture>) -> Conductor {
+        Conductor { eta, k, roughness, thin_film: None }
+    }
+
+    /// Coats the metal with an interference film, like heat-tinted steel.
+    pub fn with_thin_film(mut self, thin_film: ThinFilm) -> Conductor {
+        self.thin_film = Some(thin_film);
+        self
+    }
+
+    fn fresnel(&self, hit_info: &HitInfo, cos_i: f64) -> RGBColor {
+        match &self.thin_film {
+            Some(film) => film.reflectance(hit_info, cos_i, 1., |lambda| {
+                // Complex IOR across the spectrum, from its RGB samples
+                let channel = |c: RGBColor| match lambda < 550. {
+                    true => {
+                        let t = ((lambda - 450.) / 100.).clamp(0., 1.);
+                        c.z * (1. - t) + c.y * t
+                    },
+                    false => {
+                        let t = ((lambda - 550.) / 100.).clamp(0., 1.);
+                        c.y * (1. - t) + c.x * t
+                    },
+                };
+                (channel(self.eta), channel(self.k))
+            }),
+            None => spectrum::upsample(microfacet::fresnel_conductor(cos_i, self.eta, self.k)),
+        }
+    }
+
+    pub fn gold(roughness: f64) -> Conductor {
+        Conductor::new(RGBColor::new(0.143, 0.374, 1.442), RGBColor::new(3.983, 2.385, 1.603), roughness)
+    }
+
+    pub fn silver(roughness: f64) -> Conductor {
+        Conductor::new(RGBColor::new(0.155, 0.117, 0.138), RGBColor::new(4.828, 3.122, 2.147), roughness)
+    }
+
+    pub fn copper(roughness: f64) -> Conductor {
+        Conductor::new(RGBColor::new(0.200, 0.924, 1.102), RGBColor::new(3.912, 2.452, 2.142), roughness)
+    }
+
+    pub fn aluminium(roughness: f64) -> Conductor {
+        Conductor::new(RGBColor::new(1.657, 0.880, 0.521), RGBColor::new(9.224, 6.270, 4.837), roughness)
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(&self, ray: Ray, hit_info: HitInfo) -> Option<ScatterRecord> {
+        let frame = Onb::from_w(hit_info.get_normal());
+        let wo = frame.to_local(-ray.direction.normalized());
+        if wo.z <= 0. {
+            return None;
+        }
+
+        let dist = match roughness_at(self.roughness.as_ref(), &hit_info) {
+            Some(dist) => dist,
+            None => {
+                let direction = frame.local(Vec3::new(-wo.x, -wo.y, wo.z));
+                let attenuation = self.fresnel(&hit_info, wo.z);
+                return Some(ScatterRecord::specular(Ray::new(hit_info.get_point(), direction), attenuation));
+            },
+        };
+
+        let wi = dist.sample_reflection(wo)?;
+        let wm = (wo + wi).normalized();
+        let pdf = dist.pdf_reflection(wo, wi);
+        let fresnel = self.fresnel(&hit_info, Vec3::dot(wo, wm));
+        let attenuation = fresnel * (dist.eval_reflection(wo, wi) / pdf);
+        let ray = Ray::new(hit_info.get_point(), frame.local(wi));
+        Some(ScatterRecord { ray, attenuation, pdf, is_specular: false, lobe: Lobe::Specular })
+    }
+
+    fn eval(&self, ray: Ray, hit_info: HitInfo, scattered: Vec3) -> RGBColor {
+        let dist = match roughness_at(self.roughness.as_ref(), &hit_info) {
+            Some(dist) => dist,
+            None => return RGBColor::zero(),
+        };
+        let frame = Onb::from_w(hit_info.get_normal());
+        let wo = frame.to_local(-ray.direction.normalized());
+        let wi = frame.to_local(scattered.normalized());
+
+        let wm = (wo + wi).normalized();
+        let fresnel = self.fresnel(&hit_info, Vec3::dot(wo, wm));
+        fresnel * dist.eval_reflection(wo, wi)
+    }
+
+    fn pdf(&self, ray: Ray, hit_info: HitInfo, scattered: Vec3) -> f64 {
+        let dist = match roughness_at(self.roughness.as_ref(), &hit_info) {
+            Some(dist) => dist,
+            None => return 0.,
+        };
+        let frame = Onb::from_w(hit_info.get_normal());
+        let wo = frame.to_local(-ray.direction.normalized());
+        let wi = frame.to_local(scattered.normalized());
+        dist.pdf_reflection(wo, wi)
+    }
+
+    fn albedo(&self, _: HitInfo) -> RGBColor {
+        microfacet::fresnel_conductor(1., self.eta, self.k)
+    }
+}
+
+/// Glass with a GGX microfacet surface, reflecting and refracting with the
+/// exact dielectric Fresnel term.
+pub struct RoughDielectric {
+    refraction_index: f64,
+    roughness: Box<dyn Texture>,
+}
+
+impl RoughDielectric {
+    pub fn new(refraction_index: f64, roughness: f64) -> RoughDielectric {
+        RoughDielectric::textured(refraction_index, Box::new(SolidColor::scalar(roughness)))
+    }
+
+    pub fn textured(refraction_index: f64, roughness: Box<dyn Texture>) -> RoughDielectric {
+        RoughDielectric { refraction_index, roughness }
+    }
+
+    /// IOR of the side the ray is going into, relative to the one it comes from.
+    fn relative_eta(&self, hit_info: &HitInfo) -> f64 {
+        match hit_info.get_front_face() {
+            true => self.refraction_index,
+            false => 1. / self.refraction_index,
+        }
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(&self, ray: Ray, hit_info: HitInfo) -> Option<ScatterRecord> {
+        let frame = Onb::from_w(hit_info.get_normal());
+        let wo = frame.to_local(-ray.direction.normalized());
+        if wo.z <= 0. {
+            return None;
+        }
+        let eta = self.relative_eta(&hit_info);
+        let point = hit_info.get_point();
+
+        let dist = match roughness_at(self.roughness.as_ref(), &hit_info) {
+            Some(dist) => dist,
+            None => {
+                let reflectance = microfacet::fresnel_dielectric(wo.z, eta);
+                let normal = Vec3::new(0., 0., 1.);
+                return match rand_utils::rand_f64() < reflectance {
+                    true => {
+                        let direction = frame.local((-wo).reflect(normal));
+                        Some(ScatterRecord::specular(Ray::new(point, direction), RGBColor::ones()))
+                    },
+                    false => {
+                        let direction = frame.local(microfacet::refract(wo, normal, eta)?);
+                        Some(ScatterRecord::transmission(Ray::new(point, direction), RGBColor::ones()))
+                    },
+                };
+            },
+        };
+
+        let wi = dist.sample_dielectric(wo, eta)?;
+        let pdf = dist.pdf_dielectric(wo, wi, eta);
+        if pdf <= 0. {
+            return None;
+        }
+        let attenuation = RGBColor::ones() * (dist.eval_dielectric(wo, wi, eta) / pdf);
+        let lobe = match wi.z > 0. {
+            true => Lobe::Specular,
+            false => Lobe::Transmission,
+        };
+        let ray = Ray::new(point, frame.local(wi));
+        Some(ScatterRecord { ray, attenuation, pdf, is_specular: false, lobe })
+    }
+
+    fn eval(&self, ray: Ray, hit_info: HitInfo, scattered: Vec3) -> RGBColor {
+        let dist = match roughness_at(self.roughness.as_ref(), &hit_info) {
+            Some(dist) => dist,
+            None => return RGBColor::zero(),
+        };
+        let frame = Onb::from_w(hit_info.get_normal());
+        let wo = frame.to_local(-ray.direction.normalized());
+        let wi = frame.to_local(scattered.normalized());
+        RGBColor::ones() * dist.eval_dielectric(wo, wi, self.relative_eta(&hit_info))
+    }
+
+    fn pdf(&self, ray: Ray, hit_info: HitInfo, scattered: Vec3) -> f64 {
+        let dist = match roughness_at(self.roughness.as_ref(), &hit_info) {
+            Some(dist) => dist,
+            None => return 0.,
+        };
+        let frame = Onb::from_w(hit_info.get_normal());
+        let wo = frame.to_local(-ray.direction.normalized());
+        let wi = frame.to_local(scattered.normalized());
+        dist.pdf_dielectric(wo, wi, self.relative_eta(&hit_info))
+    }
+}
+
+/// Emissive material: absorbs everything and emits `emit` from its front face.
+pub struct DiffuseLight {
+    pub emit: RGBColor,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: RGBColor) -> DiffuseLight {
+        DiffuseLight { emit }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _: Ray, _: HitInfo) -> Option<ScatterRecord> {
+        None
+    }
+
+    fn emitted(&self, _: Ray, hit_info: HitInfo) -> RGBColor {
+        match hit_info.get_front_face() {
+            true => self.emit,
+            false => RGBColor::zero(),
+        }
+    }
+
+    fn albedo(&self, _: HitInfo) -> RGBColor {
+        self.emit
+    }
+}
+
+/// Phase function scattering light equally in all directions, used inside
+/// participating media.
+pub struct Isotropic {
+    pub albedo: RGBColor,
+}
+
+impl Isotropic {
+    pub fn new(albedo: RGBColor) -> Isotropic {
+        Isotropic { albedo }
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(&self, _: Ray, hit_info: HitInfo) -> Option<ScatterRecord> {
+        let ray = Ray::new(hit_info.get_point(), Vec3::rand_on_unit_sphere());
+        let pdf = 1. / (4. * PI);
+        let attenuation = spectrum::upsample(self.albedo);
+        Some(ScatterRecord { ray, attenuation, pdf, is_specular: false, lobe: Lobe::Diffuse })
+    }
+
+    fn eval(&self, _: Ray, _: HitInfo, _: Vec3) -> RGBColor {
+        spectrum::upsample(self.albedo) / (4. * PI)
+    }
+
+    fn pdf(&self, _: Ray, _: HitInfo, _: Vec3) -> f64 {
+        1. / (4. * PI)
+    }
+
+    fn albedo(&self, _: HitInfo) -> RGBColor {
+        self.albedo
+    }
+}
+
+/// Henyey-Greenstein phase function for anisotropic media. `g` in (-1, 1)
+/// goes from back scattering through isotropic (0) to forward scattering.
+pub struct HenyeyGreenstein {
+    pub albedo: RGBColor,
+    pub g: f64,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(albedo: RGBColor, g: f64) -> HenyeyGreenstein {
+        HenyeyGreenstein { albedo, g: g.clamp(-0.999, 0.999) }
+    }
+
+    /// Phase function value for a deflection of angle `cos_theta` from the
+    /// incoming direction.
+    fn phase(&self, cos_theta: f64) -> f64 {
+        let denom = 1. + self.g * self.g - 2. * self.g * cos_theta;
+        (1. - self.g * self.g) / (4. * PI * denom * denom.max(1e-12).sqrt())
+    }
+}
+
+impl Material for HenyeyGreenstein {
+    fn scatter(&self, ray: Ray, hit_info: HitInfo) -> Option<ScatterRecord> {
+        let g = self.g;
+        let r1 = rand_utils::rand_f64();
+        let cos_theta = match g.abs() < 1e-3 {
+            true => 1. - 2. * r1,
+            false => {
+                let sq = (1. - g * g) / (1. - g + 2. * g * r1);
+                ((1. + g * g - sq * sq) / (2. * g)).clamp(-1., 1.)
+            },
         };
+        let sin_theta = (1. - cos_theta * cos_theta).max(0.).sqrt();
+        let phi = 2. * PI * rand_utils::rand_f64();
 
-        let attenuation = RGBColor::ones();
-        let scattered = Ray::new(hit_info.get_point(), direction);
+        let frame = Onb::from_w(ray.direction);
+        let direction = frame.local(Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta));
+        let ray = Ray::new(hit_info.get_point(), direction);
+        let pdf = self.phase(cos_theta);
+        let attenuation = spectrum::upsample(self.albedo);
+        Some(ScatterRecord { ray, attenuation, pdf, is_specular: false, lobe: Lobe::Diffuse })
+    }
 
-        Some((scattered, attenuation))
+    fn eval(&self, ray: Ray, hit_info: HitInfo, scattered: Vec3) -> RGBColor {
+        spectrum::upsample(self.albedo) * self.pdf(ray, hit_info, scattered)
     }
-}
\ No newline at end of file
+
+    fn pdf(&self, ray: Ray, _: HitInfo, scattered: Vec3) -> f64 {
+        let cos_theta = Vec3::dot(ray.direction.normalized(), scattered.normalized());
+        self.phase(cos_theta)
+    }
+
+    fn albedo(&self, _: HitInfo) -> RGBColor {
+        self.albedo
+    }
+}
@@ -0,0 +1,310 @@
+use crate::aabb::Aabb;
+use crate::hit_info::HitInfo;
+use crate::hittable::Hittable;
+use crate::material::{Isotropic, Material, ScatterRecord};
+use crate::ray::Ray;
+use crate::utils::rand_utils;
+use crate::vec3::{Point, RGBColor, Vec3};
+use crate::voxel_grid::VoxelGrid;
+
+/// Homogeneous participating medium (smoke, fog, ...) with constant density.
+/// Rays going through it scatter after an exponentially distributed free
+/// flight distance, which `hit` reports as a hit inside the medium.
+///
+/// The medium either fills a closed boundary shape, whose own material is
+/// ignored, or a fixed box of the scene. Negative or NaN densities are
+/// taken as zero, which leaves rays unaffected.
+pub struct ConstantMedium {
+    extent: MediumExtent,
+    density: f64,
+    phase_function: Box<dyn Material>,
+}
+
+enum MediumExtent {
+    Shape(Box<dyn Hittable>),
+    Region(Aabb),
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Box<dyn Hittable>, density: f64, albedo: RGBColor) -> ConstantMedium {
+        ConstantMedium {
+            extent: MediumExtent::Shape(boundary),
+            density: non_negative(density),
+            phase_function: Box::new(Isotropic::new(albedo)),
+        }
+    }
+
+    /// Medium with a custom phase function, e.g. `HenyeyGreenstein`.
+    pub fn with_phase_function(boundary: Box<dyn Hittable>, density: f64, phase_function: Box<dyn Material>) -> ConstantMedium {
+        ConstantMedium {
+            extent: MediumExtent::Shape(boundary),
+            density: non_negative(density),
+            phase_function,
+        }
+    }
+
+    /// Atmospheric fog filling the `extent` box, usually large enough to
+    /// hold the whole scene; see `HittableList::set_fog`. Rays that leave it
+    /// without scattering reach the background.
+    pub fn global(density: f64, albedo: RGBColor, extent: Aabb) -> ConstantMedium {
+        ConstantMedium {
+            extent: MediumExtent::Region(extent),
+            density: non_negative(density),
+            phase_function: Box::new(Isotropic::new(albedo)),
+        }
+    }
+
+    /// Parametric range of `r` that lies inside the medium.
+    fn inside_range(&self, r: Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let (t_enter, t_exit) = match &self.extent {
+            MediumExtent::Shape(boundary) => {
+                let enter = boundary.hit(r, f64::NEG_INFINITY, f64::INFINITY)?;
+                let exit = boundary.hit(r, enter.get_t() + 0.0001, f64::INFINITY)?;
+                (enter.get_t().max(t_min), exit.get_t().min(t_max))
+            },
+            MediumExtent::Region(bounds) => bounds.hit_range(r, t_min, t_max)?,
+        };
+
+        match t_enter < t_exit {
+            true => Some((t_enter, t_exit)),
+            false => None,
+        }
+    }
+}
+
+/// `density`, or zero when it's negative or NaN.
+fn non_negative(density: f64) -> f64 {
+    match density > 0. {
+        true => density,
+        false => 0.,
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitInfo<'_>> {
+        let (t_enter, t_exit) = self.inside_range(r, t_min, t_max)?;
+
+        let ray_length = r.direction.length();
+        let distance_inside = (t_exit - t_enter) * ray_length;
+        let hit_distance = -(1. - rand_utils::rand_f64()).ln() / self.density;
+        if hit_distance > distance_inside {
+            return None;
+        }
+
+        let t = t_enter + hit_distance / ray_length;
+        Some(HitInfo::in_medium(r.at(t), t, &*self.phase_function, r))
+    }
+
+    fn hit_surface(&self, _r: Ray, _t_min: f64, _t_max: f64) -> Option<HitInfo<'_>> {
+        None
+    }
+
+    fn transmittance(&self, r: Ray, t_min: f64, t_max: f64) -> RGBColor {
+        match self.inside_range(r, t_min, t_max) {
+            Some((t_enter, t_exit)) => RGBColor::ones() * (-self.density * (t_exit - t_enter) * r.direction.length()).exp(),
+            None => RGBColor::ones(),
+        }
+    }
+}
+
+/// Spatially varying volume data stored in voxel grids stretched over
+/// `bounds`: extinction density, plus optional emission and temperature
+/// channels for fire and explosions.
+pub struct GridVolume {
+    bounds: Aabb,
+    density: VoxelGrid,
+    density_scale: f64,
+    emission: Option<(VoxelGrid, RGBColor)>,
+    temperature: Option<(VoxelGrid, f64, f64)>,
+}
+
+impl GridVolume {
+    /// Extinction coefficient is `density_scale` times the grid value.
+    pub fn new(bounds: Aabb, density: VoxelGrid, density_scale: f64) -> GridVolume {
+        GridVolume { bounds, density, density_scale, emission: None, temperature: None }
+    }
+
+    /// Emitted radiance is the grid value times `color`.
+    pub fn with_emission(mut self, emission: VoxelGrid, color: RGBColor) -> GridVolume {
+        self.emission = Some((emission, color));
+        self
+    }
+
+    /// Blackbody emission from a temperature grid, whose values become kelvin
+    /// once multiplied by `kelvin_scale`. `intensity` scales the radiance.
+    pub fn with_temperature(mut self, temperature: VoxelGrid, kelvin_scale: f64, intensity: f64) -> GridVolume {
+        self.temperature = Some((temperature, kelvin_scale, intensity));
+        self
+    }
+
+    pub fn get_bounds(&self) -> Aabb {
+        self.bounds
+    }
+
+    /// Largest extinction coefficient in the volume.
+    pub fn max_density(&self) -> f64 {
+        self.density.max_value() * self.density_scale
+    }
+
+    pub fn density_at(&self, p: Point) -> f64 {
+        let o = self.bounds.offset(p);
+        self.density.lookup(o.x, o.y, o.z) * self.density_scale
+    }
+
+    pub fn emission_at(&self, p: Point) -> RGBColor {
+        let o = self.bounds.offset(p);
+        let mut le = RGBColor::zero();
+        if let Some((grid, color)) = &self.emission {
+            le += *color * grid.lookup(o.x, o.y, o.z);
+        }
+        if let Some((grid, kelvin_scale, intensity)) = &self.temperature {
+            le += blackbody(grid.lookup(o.x, o.y, o.z) * kelvin_scale) * *intensity;
+        }
+        le
+    }
+}
+
+/// Approximate blackbody radiance at `kelvin`: Planck's law evaluated at
+/// representative red, green and blue wavelengths, relative to the total
+/// emitted power of a 1000 K body.
+fn blackbody(kelvin: f64) -> RGBColor {
+    if kelvin <= 0. {
+        return RGBColor::zero();
+    }
+
+    // c2 = hc / k in nm * K
+    let c2 = 1.4388e7;
+    let planck = |lambda: f64| 1. / (lambda.powi(5) * ((c2 / (lambda * kelvin)).exp() - 1.));
+    let color = RGBColor::new(planck(610.), planck(550.), planck(465.));
+    let luminance = color.luminance();
+    match luminance > 0. {
+        true => color / luminance * (kelvin / 1000.).powi(4),
+        false => RGBColor::zero(),
+    }
+}
+
+/// Material found at collisions inside a `HeterogeneousMedium`: scattering is
+/// delegated to the phase function, and emission is the local radiance
+/// weighted by the probability of the collision being an absorption.
+struct VolumeMaterial {
+    volume: GridVolume,
+    phase_function: Box<dyn Material>,
+}
+
+impl Material for VolumeMaterial {
+    fn scatter(&self, ray: Ray, hit_info: HitInfo) -> Option<ScatterRecord> {
+        self.phase_function.scatter(ray, hit_info)
+    }
+
+    fn emitted(&self, _: Ray, hit_info: HitInfo) -> RGBColor {
+        let absorption = RGBColor::ones() - self.phase_function.albedo(hit_info);
+        absorption * self.volume.emission_at(hit_info.get_point())
+    }
+
+    fn eval(&self, ray: Ray, hit_info: HitInfo, scattered: Vec3) -> RGBColor {
+        self.phase_function.eval(ray, hit_info, scattered)
+    }
+
+    fn pdf(&self, ray: Ray, hit_info: HitInfo, scattered: Vec3) -> f64 {
+        self.phase_function.pdf(ray, hit_info, scattered)
+    }
+
+    fn albedo(&self, hit_info: HitInfo) -> RGBColor {
+        self.phase_function.albedo(hit_info)
+    }
+}
+
+/// Participating medium whose density varies over a voxel grid. Free flights
+/// are sampled with delta tracking against the grid's maximum density, and
+/// shadow rays get their transmittance from ratio tracking.
+///
+/// The phase function's albedo is the single-scattering albedo: its
+/// complement is the fraction of collisions that absorb, and emit.
+pub struct HeterogeneousMedium {
+    majorant: f64,
+    material: VolumeMaterial,
+}
+
+impl HeterogeneousMedium {
+    pub fn new(volume: GridVolume, phase_function: Box<dyn Material>) -> HeterogeneousMedium {
+        HeterogeneousMedium {
+            majorant: volume.max_density(),
+            material: VolumeMaterial { volume, phase_function },
+        }
+    }
+
+    /// Tentative collisions against the majorant along `r` inside the volume,
+    /// stopping as soon as `visit` returns false.
+    fn track<F>(&self, r: Ray, t_min: f64, t_max: f64, mut visit: F)
+        where
+            F: FnMut(f64, f64) -> bool
+    {
+        if self.majorant <= 0. {
+            return;
+        }
+        let (t_enter, t_exit) = match self.material.volume.get_bounds().hit_range(r, t_min, t_max) {
+            Some(range) => range,
+            None => return,
+        };
+
+        let ray_length = r.direction.length();
+        let mut t = t_enter;
+        loop {
+            t -= (1. - rand_utils::rand_f64()).ln() / (self.majorant * ray_length);
+            if t >= t_exit {
+                return;
+            }
+            let density = self.material.volume.density_at(r.at(t));
+            if !visit(t, density / self.majorant) {
+                return;
+            }
+        }
+    }
+}
+
+impl Hittable for HeterogeneousMedium {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitInfo<'_>> {
+        let mut collision = None;
+        self.track(r, t_min, t_max, |t, real_fraction| {
+            match rand_utils::rand_f64() < real_fraction {
+                true => {
+                    collision = Some(t);
+                    false
+                },
+                false => true,
+            }
+        });
+
+        let t = collision?;
+        Some(HitInfo::in_medium(r.at(t), t, &self.material, r))
+    }
+
+    fn hit_surface(&self, _r: Ray, _t_min: f64, _t_max: f64) -> Option<HitInfo<'_>> {
+        None
+    }
+
+    fn transmittance(&self, r: Ray, t_min: f64, t_max: f64) -> RGBColor {
+        let mut transmittance = 1.;
+        self.track(r, t_min, t_max, |_, real_fraction| {
+            transmittance *= 1. - real_fraction.min(1.);
+            transmittance > 0.
+        });
+        RGBColor::ones() * transmittance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_densities_leave_rays_unaffected() {
+        let extent = Aabb::new(Point::new(-1., -1., -1.), Point::new(1., 1., 1.));
+        let r = Ray::new(Point::new(0., 0., -5.), Vec3::new(0., 0., 1.));
+        for density in [-2., f64::NAN] {
+            let fog = ConstantMedium::global(density, RGBColor::ones(), extent);
+            assert!(fog.hit(r, 0.001, f64::INFINITY).is_none());
+            assert_eq!(fog.transmittance(r, 0.001, f64::INFINITY), RGBColor::ones());
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use std::f64::consts::PI;
+
+use crate::utils::rand_utils;
+use crate::vec3::{RGBColor, Vec3};
+
+/// GGX / Trowbridge-Reitz microfacet distribution with Smith
+/// height-correlated masking-shadowing. All directions are in the local
+/// shading frame, where the macro normal is +z and the tangent is +x.
+#[derive(Debug, Clone, Copy)]
+pub struct TrowbridgeReitz {
+    alpha_x: f64,
+    alpha_y: f64,
+}
+
+impl TrowbridgeReitz {
+    /// Below this roughness surfaces are treated as perfectly smooth.
+    pub const SMOOTH_ROUGHNESS: f64 = 1e-3;
+
+    /// Distribution for a perceptual `roughness` in [0, 1] (alpha = roughness^2).
+    pub fn from_roughness(roughness: f64) -> TrowbridgeReitz {
+        TrowbridgeReitz::anisotropic(roughness, 0.)
+    }
+
+    /// Distribution stretched along the tangent by `anisotropy` in [0, 1],
+    /// with the remapping used by the Disney and Blender principled BSDFs.
+    pub fn anisotropic(roughness: f64, anisotropy: f64) -> TrowbridgeReitz {
+        let r = roughness.clamp(TrowbridgeReitz::SMOOTH_ROUGHNESS, 1.);
+        let aspect = (1. - 0.9 * anisotropy.clamp(0., 1.)).sqrt();
+        let alpha = r * r;
+        TrowbridgeReitz { alpha_x: (alpha / aspect).max(1e-6), alpha_y: (alpha * aspect).max(1e-6) }
+    }
+
+    /// Density of microfacet normals `wm`.
+    pub fn d(&self, wm: Vec3) -> f64 {
+        if wm.z <= 0. {
+            return 0.;
+        }
+        let x = wm.x / self.alpha_x;
+        let y = wm.y / self.alpha_y;
+        let denom = x * x + y * y + wm.z * wm.z;
+        1. / (PI * self.alpha_x * self.alpha_y * denom * denom)
+    }
+
+    fn lambda(&self, w: Vec3) -> f64 {
+        let cos2 = w.z * w.z;
+        if cos2 == 0. {
+            return f64::INFINITY;
+        }
+        let alpha2_tan2 = (w.x * w.x * self.alpha_x * self.alpha_x + w.y * w.y * self.alpha_y * self.alpha_y) / cos2;
+        ((1. + alpha2_tan2).sqrt() - 1.) / 2.
+    }
+
+    /// Masking of `w` alone.
+    pub fn g1(&self, w: Vec3) -> f64 {
+        1. / (1. + self.lambda(w))
+    }
+
+    /// Joint masking-shadowing of `wo` and `wi`.
+    pub fn g(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1. / (1. + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Density of `sample_visible` picking `wm` as seen from `wo`.
+    pub fn pdf_visible(&self, wo: Vec3, wm: Vec3) -> f64 {
+        if wo.z == 0. {
+            return 0.;
+        }
+        self.g1(wo) / wo.z.abs() * self.d(wm) * Vec3::dot(wo, wm).abs()
+    }
+
+    /// Samples a microfacet normal from the distribution of normals visible
+    /// from `wo` (Heitz 2018).
+    pub fn sample_visible(&self, wo: Vec3) -> Vec3 {
+        let wo = match wo.z < 0. {
+            true => -wo,
+            false => wo,
+        };
+        let vh = Vec3::new(self.alpha_x * wo.x, self.alpha_y * wo.y, wo.z).normalized();
+
+        let lensq = vh.x * vh.x + vh.y * vh.y;
+        let t1 = match lensq > 0. {
+            true => Vec3::new(-vh.y, vh.x, 0.) / lensq.sqrt(),
+            false => Vec3::new(1., 0., 0.),
+        };
+        let t2 = Vec3::cross(vh, t1);
+
+        let r = rand_utils::rand_f64().sqrt();
+        let phi = 2. * PI * rand_utils::rand_f64();
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1. + vh.z);
+        let p2 = (1. - s) * (1. - p1 * p1).max(0.).sqrt() + s * r * phi.sin();
+        let nh = t1 * p1 + t2 * p2 + vh * (1. - p1 * p1 - p2 * p2).max(0.).sqrt();
+
+        Vec3::new(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(1e-6)).normalized()
+    }
+
+    /// Glossy reflection lobe without its Fresnel factor, as f * cos:
+    /// D * G / (4 cos_o). Fresnel is evaluated on `(wo + wi).normalized()`.
+    pub fn eval_reflection(&self, wo: Vec3, wi: Vec3) -> f64 {
+        if wo.z <= 0. || wi.z <= 0. {
+            return 0.;
+        }
+        let wm = (wo + wi).normalized();
+        self.d(wm) * self.g(wo, wi) / (4. * wo.z)
+    }
+
+    pub fn pdf_reflection(&self, wo: Vec3, wi: Vec3) -> f64 {
+        if wo.z <= 0. || wi.z <= 0. {
+            return 0.;
+        }
+        let wm = (wo + wi).normalized();
+        self.pdf_visible(wo, wm) / (4. * Vec3::dot(wo, wm))
+    }
+
+    pub fn sample_reflection(&self, wo: Vec3) -> Option<Vec3> {
+        let wm = self.sample_visible(wo);
+        let wi = (-wo).reflect(wm);
+        match wi.z > 0. {
+            true => Some(wi),
+            false => None,
+        }
+    }
+
+    /// Rough dielectric interface into a medium of relative IOR `eta`,
+    /// reflecting and refracting according to the exact Fresnel term, as f * |cos|.
+    pub fn eval_dielectric(&self, wo: Vec3, wi: Vec3, eta: f64) -> f64 {
+        let wm = match dielectric_half_vector(wo, wi, eta) {
+            Some(wm) => wm,
+            None => return 0.,
+        };
+
+        let reflectance = fresnel_dielectric(Vec3::dot(wo, wm), eta);
+        match wi.z > 0. {
+            true => self.d(wm) * self.g(wo, wi) * reflectance / (4. * wo.z),
+            false => {
+                let denom = (Vec3::dot(wi, wm) + Vec3::dot(wo, wm) / eta).powi(2);
+                self.d(wm) * self.g(wo, wi) * (1. - reflectance)
+                    * (Vec3::dot(wi, wm) * Vec3::dot(wo, wm) / (wo.z * denom)).abs()
+            },
+        }
+    }
+
+    pub fn pdf_dielectric(&self, wo: Vec3, wi: Vec3, eta: f64) -> f64 {
+        let wm = match dielectric_half_vector(wo, wi, eta) {
+            Some(wm) => wm,
+            None => return 0.,
+        };
+
+        let reflectance = fresnel_dielectric(Vec3::dot(wo, wm), eta);
+        match wi.z > 0. {
+            true => self.pdf_visible(wo, wm) / (4. * Vec3::dot(wo, wm)) * reflectance,
+            false => {
+                let denom = (Vec3::dot(wi, wm) + Vec3::dot(wo, wm) / eta).powi(2);
+                self.pdf_visible(wo, wm) * Vec3::dot(wi, wm).abs() / denom * (1. - reflectance)
+            },
+        }
+    }
+
+    /// Picks reflection or refraction with probability given by the Fresnel term.
+    pub fn sample_dielectric(&self, wo: Vec3, eta: f64) -> Option<Vec3> {
+        let wm = self.sample_visible(wo);
+        let reflectance = fresnel_dielectric(Vec3::dot(wo, wm), eta);
+        match rand_utils::rand_f64() < reflectance {
+            true => Some((-wo).reflect(wm)).filter(|wi| wi.z > 0.),
+            false => refract(wo, wm, eta).filter(|wi| wi.z < 0.),
+        }
+    }
+}
+
+/// Microfacet normal (facing +z) that turns `wo` into `wi` through an
+/// interface of relative IOR `eta`, `None` for back-facing configurations.
+fn dielectric_half_vector(wo: Vec3, wi: Vec3, eta: f64) -> Option<Vec3> {
+    let etap = match wi.z > 0. {
+        true => 1.,
+        false => eta,
+    };
+    let wm = wi * etap + wo;
+    if wi.z == 0. || wo.z <= 0. || wm.length_squared() == 0. {
+        return None;
+    }
+    let wm = match wm.z < 0. {
+        true => -wm.normalized(),
+        false => wm.normalized(),
+    };
+
+    match Vec3::dot(wm, wi) * wi.z < 0. || Vec3::dot(wm, wo) <= 0. {
+        true => None,
+        false => Some(wm),
+    }
+}
+
+/// Refracts `wo` through a facet with normal `wm` into a medium of relative
+/// IOR `eta`, `None` on total internal reflection.
+pub fn refract(wo: Vec3, wm: Vec3, eta: f64) -> Option<Vec3> {
+    let cos_i = Vec3::dot(wo, wm);
+    let sin2_t = (1. - cos_i * cos_i).max(0.) / (eta * eta);
+    if sin2_t >= 1. {
+        return None;
+    }
+    let cos_t = (1. - sin2_t).sqrt();
+    Some(-wo / eta + wm * (cos_i / eta - cos_t))
+}
+
+/// Unpolarized Fresnel reflectance of a dielectric interface, for light
+/// arriving at `cos_i` from the side where the relative IOR is `eta`.
+pub fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let cos_i = cos_i.clamp(-1., 1.);
+    let (cos_i, eta) = match cos_i < 0. {
+        true => (-cos_i, 1. / eta),
+        false => (cos_i, eta),
+    };
+
+    let sin2_t = (1. - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1. {
+        return 1.;
+    }
+    let cos_t = (1. - sin2_t).max(0.).sqrt();
+
+    let r_parl = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perp = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (r_parl * r_parl + r_perp * r_perp) / 2.
+}
+
+/// Fresnel reflectance of a conductor with complex IOR `eta + i k`, per channel.
+pub fn fresnel_conductor(cos_i: f64, eta: RGBColor, k: RGBColor) -> RGBColor {
+    let channel = |eta: f64, k: f64| {
+        let cos2 = cos_i.clamp(0., 1.).powi(2);
+        let sin2 = 1. - cos2;
+
+        let t0 = eta * eta - k * k - sin2;
+        let a2_plus_b2 = (t0 * t0 + 4. * eta * eta * k * k).sqrt();
+        let a = (0.5 * (a2_plus_b2 + t0)).max(0.).sqrt();
+
+        let t1 = a2_plus_b2 + cos2;
+        let t2 = 2. * cos2.sqrt() * a;
+        let rs = (t1 - t2) / (t1 + t2);
+
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+
+        (rs + rp) / 2.
+    };
+
+    RGBColor::new(channel(eta.x, k.x), channel(eta.y, k.y), channel(eta.z, k.z))
+}
+
+/// Schlick's `(1 - cos)^5` Fresnel weight.
+pub fn schlick_weight(cos: f64) -> f64 {
+    (1. - cos.clamp(0., 1.)).powi(5)
+}
@@ -0,0 +1,321 @@
+use std::f64::consts::PI;
+
+use crate::hit_info::HitInfo;
+use crate::material::{Lobe, Material, ScatterRecord};
+use crate::microfacet::{self, TrowbridgeReitz};
+use crate::ray::Ray;
+use crate::spectrum;
+use crate::texture::Texture;
+use crate::utils::rand_utils;
+use crate::vec3::{RGBColor, Vec3};
+
+/// Disney-style "uber" material following the parameter semantics of
+/// Blender's (v1) Principled BSDF, so glTF / OBJ materials can be mapped
+/// onto it directly. Every input is a texture; plain numbers and colors
+/// convert into constant textures.
+///
+/// It mixes a retro-reflective diffuse lobe with sheen, an anisotropic
+/// GGX specular lobe, a rough glass lobe and a GGX clearcoat. Anisotropy
+/// stretches highlights along the first tangent of the shading frame.
+pub struct PrincipledBsdf {
+    base_color: Box<dyn Texture>,
+    metallic: Box<dyn Texture>,
+    roughness: Box<dyn Texture>,
+    specular: Box<dyn Texture>,
+    specular_tint: Box<dyn Texture>,
+    anisotropic: Box<dyn Texture>,
+    sheen: Box<dyn Texture>,
+    sheen_tint: Box<dyn Texture>,
+    clearcoat: Box<dyn Texture>,
+    clearcoat_roughness: Box<dyn Texture>,
+    transmission: Box<dyn Texture>,
+    ior: Box<dyn Texture>,
+}
+
+/// Index of refraction of the clearcoat layer.
+const CLEARCOAT_IOR: f64 = 1.5;
+
+/// Inputs evaluated at a hit point, with the lobe weights derived from them.
+struct Params {
+    base_color: RGBColor,
+    roughness: f64,
+    specular_color: RGBColor,
+    sheen_color: RGBColor,
+    clearcoat: f64,
+    ior: f64,
+    diffuse_weight: f64,
+    specular_weight: f64,
+    glass_weight: f64,
+    specular_dist: TrowbridgeReitz,
+    clearcoat_dist: TrowbridgeReitz,
+}
+
+impl PrincipledBsdf {
+    /// Dielectric with Blender's default inputs and the given base color.
+    pub fn new(base_color: impl Into<Box<dyn Texture>>) -> PrincipledBsdf {
+        PrincipledBsdf {
+            base_color: base_color.into(),
+            metallic: 0.0.into(),
+            roughness: 0.5.into(),
+            specular: 0.5.into(),
+            specular_tint: 0.0.into(),
+            anisotropic: 0.0.into(),
+            sheen: 0.0.into(),
+            sheen_tint: 0.5.into(),
+            clearcoat: 0.0.into(),
+            clearcoat_roughness: 0.03.into(),
+            transmission: 0.0.into(),
+            ior: 1.45.into(),
+        }
+    }
+
+    pub fn metallic(mut self, metallic: impl Into<Box<dyn Texture>>) -> PrincipledBsdf {
+        self.metallic = metallic.into();
+        self
+    }
+
+    pub fn roughness(mut self, roughness: impl Into<Box<dyn Texture>>) -> PrincipledBsdf {
+        self.roughness = roughness.into();
+        self
+    }
+
+    /// Amount of dielectric specular reflection, 0.5 being 4% at normal incidence.
+    pub fn specular(mut self, specular: impl Into<Box<dyn Texture>>) -> PrincipledBsdf {
+        self.specular = specular.into();
+        self
+    }
+
+    pub fn specular_tint(mut self, specular_tint: impl Into<Box<dyn Texture>>) -> PrincipledBsdf {
+        self.specular_tint = specular_tint.into();
+        self
+    }
+
+    pub fn anisotropic(mut self, anisotropic: impl Into<Box<dyn Texture>>) -> PrincipledBsdf {
+        self.anisotropic = anisotropic.into();
+        self
+    }
+
+    pub fn sheen(mut self, sheen: impl Into<Box<dyn Texture>>) -> PrincipledBsdf {
+        self.sheen = sheen.into();
+        self
+    }
+
+    pub fn sheen_tint(mut self, sheen_tint: impl Into<Box<dyn Texture>>) -> PrincipledBsdf {
+        self.sheen_tint = sheen_tint.into();
+        self
+    }
+
+    pub fn clearcoat(mut self, clearcoat: impl Into<Box<dyn Texture>>) -> PrincipledBsdf {
+        self.clearcoat = clearcoat.into();
+        self
+    }
+
+    pub fn clearcoat_roughness(mut self, clearcoat_roughness: impl Into<Box<dyn Texture>>) -> PrincipledBsdf {
+        self.clearcoat_roughness = clearcoat_roughness.into();
+        self
+    }
+
+    pub fn transmission(mut self, transmission: impl Into<Box<dyn Texture>>) -> PrincipledBsdf {
+        self.transmission = transmission.into();
+        self
+    }
+
+    pub fn ior(mut self, ior: impl Into<Box<dyn Texture>>) -> PrincipledBsdf {
+        self.ior = ior.into();
+        self
+    }
+
+    fn params(&self, hit_info: &HitInfo) -> Params {
+        let (u, v) = hit_info.get_uv();
+        let p = hit_info.get_point();
+        let scalar = |t: &dyn Texture| t.scalar(u, v, p).clamp(0., 1.);
+
+        let base_color = self.base_color.value(u, v, p);
+        let metallic = scalar(self.metallic.as_ref());
+        let roughness = scalar(self.roughness.as_ref());
+        let transmission = scalar(self.transmission.as_ref());
+
+        let luminance = base_color.luminance();
+        let tint = match luminance > 0. {
+            true => base_color / luminance,
+            false => RGBColor::ones(),
+        };
+        let lerp = |a: RGBColor, b: RGBColor, t: f64| a * (1. - t) + b * t;
+
+        let dielectric_specular = lerp(RGBColor::ones(), tint, scalar(self.specular_tint.as_ref()))
+            * (0.08 * self.specular.scalar(u, v, p).max(0.));
+        let sheen_color = lerp(RGBColor::ones(), tint, scalar(self.sheen_tint.as_ref()))
+            * self.sheen.scalar(u, v, p).max(0.);
+
+        let glass_weight = (1. - metallic) * transmission;
+        let clearcoat_roughness = scalar(self.clearcoat_roughness.as_ref());
+
+        Params {
+            base_color,
+            roughness,
+            specular_color: lerp(dielectric_specular, base_color, metallic),
+            sheen_color,
+            clearcoat: 0.25 * self.clearcoat.scalar(u, v, p).max(0.),
+            ior: self.ior.scalar(u, v, p).max(1.0001),
+            diffuse_weight: (1. - metallic) * (1. - transmission),
+            specular_weight: 1. - glass_weight,
+            glass_weight,
+            specular_dist: TrowbridgeReitz::anisotropic(roughness, scalar(self.anisotropic.as_ref())),
+            clearcoat_dist: TrowbridgeReitz::from_roughness(clearcoat_roughness),
+        }
+    }
+
+    /// Relative IOR of the glass lobe for a ray arriving at `hit_info`.
+    fn relative_eta(params: &Params, hit_info: &HitInfo) -> f64 {
+        match hit_info.get_front_face() {
+            true => params.ior,
+            false => 1. / params.ior,
+        }
+    }
+
+    /// Probabilities of sampling the diffuse, specular, glass and clearcoat
+    /// lobes, roughly proportional to their reflectance seen from `wo`.
+    fn lobe_probabilities(params: &Params, wo: Vec3) -> [f64; 4] {
+        let fresnel_weight = microfacet::schlick_weight(wo.z);
+        let specular = params.specular_color * (1. - fresnel_weight) + RGBColor::ones() * fresnel_weight;
+        let weights = [
+            params.diffuse_weight * (params.base_color.luminance() + params.sheen_color.luminance()),
+            params.specular_weight * specular.luminance(),
+            params.glass_weight,
+            params.clearcoat * microfacet::fresnel_dielectric(wo.z, CLEARCOAT_IOR),
+        ];
+
+        let total: f64 = weights.iter().sum();
+        match total > 0. {
+            true => weights.map(|w| w / total),
+            false => [0.; 4],
+        }
+    }
+
+    /// Sum of all lobes as f * |cos|, in the local shading frame.
+    fn eval_local(params: &Params, wo: Vec3, wi: Vec3, eta: f64) -> RGBColor {
+        let mut f = RGBColor::zero();
+
+        if wo.z > 0. && wi.z > 0. {
+            let wh = (wo + wi).normalized();
+            let cos_d = Vec3::dot(wi, wh);
+
+            if params.diffuse_weight > 0. {
+                let fd90 = 0.5 + 2. * params.roughness * cos_d * cos_d;
+                let fl = microfacet::schlick_weight(wi.z);
+                let fv = microfacet::schlick_weight(wo.z);
+                let retro = (1. + (fd90 - 1.) * fl) * (1. + (fd90 - 1.) * fv);
+                let diffuse = params.base_color * (retro / PI);
+                let sheen = params.sheen_color * microfacet::schlick_weight(cos_d);
+                f += (diffuse + sheen) * (params.diffuse_weight * wi.z);
+            }
+
+            if params.specular_weight > 0. {
+                let fresnel_weight = microfacet::schlick_weight(Vec3::dot(wo, wh));
+                let fresnel = params.specular_color * (1. - fresnel_weight) + RGBColor::ones() * fresnel_weight;
+                f += fresnel * (params.specular_weight * params.specular_dist.eval_reflection(wo, wi));
+            }
+
+            if params.clearcoat > 0. {
+                let fresnel = microfacet::fresnel_dielectric(Vec3::dot(wo, wh), CLEARCOAT_IOR);
+                f += RGBColor::ones() * (params.clearcoat * fresnel * params.clearcoat_dist.eval_reflection(wo, wi));
+            }
+        }
+
+        if params.glass_weight > 0. {
+            let color = match wi.z > 0. {
+                true => RGBColor::ones(),
+                false => params.base_color,
+            };
+            f += color * (params.glass_weight * params.specular_dist.eval_dielectric(wo, wi, eta));
+        }
+
+        f
+    }
+
+    /// Combined density of picking a lobe and then `wi` from it.
+    fn pdf_local(params: &Params, probabilities: &[f64; 4], wo: Vec3, wi: Vec3, eta: f64) -> f64 {
+        let [p_diffuse, p_specular, p_glass, p_clearcoat] = *probabilities;
+        let mut pdf = 0.;
+
+        if p_diffuse > 0. && wi.z > 0. {
+            pdf += p_diffuse * wi.z / PI;
+        }
+        if p_specular > 0. {
+            pdf += p_specular * params.specular_dist.pdf_reflection(wo, wi);
+        }
+        if p_glass > 0. {
+            pdf += p_glass * params.specular_dist.pdf_dielectric(wo, wi, eta);
+        }
+        if p_clearcoat > 0. {
+            pdf += p_clearcoat * params.clearcoat_dist.pdf_reflection(wo, wi);
+        }
+
+        pdf
+    }
+}
+
+impl Material for PrincipledBsdf {
+    fn scatter(&self, ray: Ray, hit_info: HitInfo) -> Option<ScatterRecord> {
+        let params = self.params(&hit_info);
+        let frame = hit_info.shading_frame();
+        let wo = frame.to_local(-ray.direction.normalized());
+        if wo.z <= 0. {
+            return None;
+        }
+        let eta = PrincipledBsdf::relative_eta(&params, &hit_info);
+        let probabilities = PrincipledBsdf::lobe_probabilities(&params, wo);
+
+        let r = rand_utils::rand_f64();
+        let [p_diffuse, p_specular, p_glass, _] = probabilities;
+        let wi = if r < p_diffuse {
+            Vec3::rand_cosine_direction()
+        } else if r < p_diffuse + p_specular {
+            params.specular_dist.sample_reflection(wo)?
+        } else if r < p_diffuse + p_specular + p_glass {
+            params.specular_dist.sample_dielectric(wo, eta)?
+        } else {
+            params.clearcoat_dist.sample_reflection(wo)?
+        };
+
+        let pdf = PrincipledBsdf::pdf_local(&params, &probabilities, wo, wi, eta);
+        if pdf <= 0. {
+            return None;
+        }
+        let attenuation = spectrum::upsample(PrincipledBsdf::eval_local(&params, wo, wi, eta)) / pdf;
+        let lobe = if wi.z < 0. {
+            Lobe::Transmission
+        } else if r < p_diffuse {
+            Lobe::Diffuse
+        } else {
+            Lobe::Specular
+        };
+
+        let ray = Ray::new(hit_info.get_point(), frame.local(wi));
+        Some(ScatterRecord { ray, attenuation, pdf, is_specular: false, lobe })
+    }
+
+    fn eval(&self, ray: Ray, hit_info: HitInfo, scattered: Vec3) -> RGBColor {
+        let params = self.params(&hit_info);
+        let frame = hit_info.shading_frame();
+        let wo = frame.to_local(-ray.direction.normalized());
+        let wi = frame.to_local(scattered.normalized());
+        let eta = PrincipledBsdf::relative_eta(&params, &hit_info);
+        spectrum::upsample(PrincipledBsdf::eval_local(&params, wo, wi, eta))
+    }
+
+    fn pdf(&self, ray: Ray, hit_info: HitInfo, scattered: Vec3) -> f64 {
+        let params = self.params(&hit_info);
+        let frame = hit_info.shading_frame();
+        let wo = frame.to_local(-ray.direction.normalized());
+        let wi = frame.to_local(scattered.normalized());
+        let eta = PrincipledBsdf::relative_eta(&params, &hit_info);
+        let probabilities = PrincipledBsdf::lobe_probabilities(&params, wo);
+        PrincipledBsdf::pdf_local(&params, &probabilities, wo, wi, eta)
+    }
+
+    fn albedo(&self, hit_info: HitInfo) -> RGBColor {
+        let (u, v) = hit_info.get_uv();
+        self.base_color.value(u, v, hit_info.get_point())
+    }
+}
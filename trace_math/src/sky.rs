@@ -0,0 +1,368 @@
+use std::f64::consts::PI;
+
+use crate::environment_map::equirect_direction;
+use crate::image::Image;
+use crate::light::{IncidentLight, Light};
+use crate::onb::Onb;
+use crate::spectrum::xyz_to_linear_srgb;
+use crate::utils::math_utils::degrees_to_radians;
+use crate::vec3::{Point, RGBColor, Vec3};
+
+/// Illuminance of the sun at the top of the atmosphere, in lux.
+const SUN_ILLUMINANCE: f64 = 128_000.;
+
+/// Angular diameter of the sun seen from the ground, in degrees.
+const SUN_ANGULAR_DIAMETER: f64 = 0.53;
+
+/// Wavelengths standing for the red, green and blue channels, in micrometers.
+const CHANNEL_WAVELENGTHS: [f64; 3] = [0.680, 0.550, 0.440];
+
+/// Position of the sun in the sky. The azimuth is measured clockwise from
+/// north, which is -z in world space, with east along +x and up along +y.
+#[derive(Debug, Clone, Copy)]
+pub struct SunPosition {
+    /// Angle above the horizon, in degrees.
+    pub elevation: f64,
+    /// Compass direction, in degrees.
+    pub azimuth: f64,
+}
+
+impl SunPosition {
+    pub fn new(elevation: f64, azimuth: f64) -> SunPosition {
+        SunPosition { elevation, azimuth }
+    }
+
+    /// Approximate position of the sun seen from `latitude` and `longitude`
+    /// (degrees, north and east positive) on the `day_of_year` (1 to 365),
+    /// at `utc_hours` past midnight UTC. Accurate to about a degree.
+    pub fn from_location(latitude: f64, longitude: f64, day_of_year: u32, utc_hours: f64) -> SunPosition {
+        let day = day_of_year as f64;
+
+        // Earth's tilt and orbit eccentricity, as declination and equation of time
+        let declination = degrees_to_radians(-23.44 * (2. * PI * (day + 10.) / 365.).cos());
+        let b = 2. * PI * (day - 81.) / 364.;
+        let equation_of_time = 9.87 * (2. * b).sin() - 7.53 * b.cos() - 1.5 * b.sin();
+
+        let solar_time = utc_hours + longitude / 15. + equation_of_time / 60.;
+        let hour_angle = degrees_to_radians(15. * (solar_time - 12.));
+        let phi = degrees_to_radians(latitude);
+
+        let sin_elevation = phi.sin() * declination.sin() + phi.cos() * declination.cos() * hour_angle.cos();
+        let azimuth = f64::atan2(
+            -declination.cos() * hour_angle.sin(),
+            declination.sin() * phi.cos() - declination.cos() * phi.sin() * hour_angle.cos(),
+        );
+
+        SunPosition {
+            elevation: sin_elevation.clamp(-1., 1.).asin().to_degrees(),
+            azimuth: azimuth.to_degrees().rem_euclid(360.),
+        }
+    }
+
+    /// Unit vector pointing towards the sun.
+    pub fn direction(&self) -> Vec3 {
+        let elevation = degrees_to_radians(self.elevation);
+        let azimuth = degrees_to_radians(self.azimuth);
+        Vec3::new(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            -elevation.cos() * azimuth.cos(),
+        )
+    }
+}
+
+/// Coefficients of the Perez sky luminance distribution.
+#[derive(Debug, Clone, Copy)]
+struct Perez {
+    a: f64,
+    b: f64,
+    c: f64,
+    d: f64,
+    e: f64,
+}
+
+impl Perez {
+    /// Relative value at the zenith angle whose cosine is `cos_theta`,
+    /// `gamma` radians away from the sun.
+    fn eval(&self, cos_theta: f64, gamma: f64) -> f64 {
+        let cos_gamma = gamma.cos();
+        (1. + self.a * (self.b / cos_theta).exp())
+            * (1. + self.c * (self.d * gamma).exp() + self.e * cos_gamma * cos_gamma)
+    }
+}
+
+/// Analytic daylight model of Preetham, Shirley and Smits (1999): clear sky
+/// radiance from the sun position and the atmosphere's turbidity (2 for a
+/// very clear sky, around 10 for haze). Below the horizon is a diffuse
+/// ground of `ground_albedo` lit by the sky and the sun.
+///
+/// The sun is a disc of uniform radiance in `radiance`, which is also
+/// sampled directly as a light, so that BSDF sampling and light sampling
+/// can both find it.
+///
+/// An `intensity` of one gives luminances in the white-light equivalent of
+/// W/m²/sr, where a clear noon sky is around ten and the sun brings over a
+/// hundred W/m²; scale it down to match scenes lit around one.
+pub struct PhysicalSky {
+    sun_direction: Vec3,
+    sun_zenith: f64,
+    /// Cosine of the sun disc's angular radius.
+    sun_cos_theta_max: f64,
+    /// Radiance of the sun disc, zero once it has set.
+    sun_radiance: RGBColor,
+    turbidity: f64,
+    intensity: f64,
+    perez: [Perez; 3],
+    zenith: [f64; 3],
+    ground: RGBColor,
+}
+
+impl PhysicalSky {
+    pub fn new(sun: SunPosition, turbidity: f64, ground_albedo: RGBColor, intensity: f64) -> PhysicalSky {
+        let t = turbidity.clamp(1.7, 10.);
+        let sun_direction = sun.direction();
+
+        // The model breaks down with the sun under the horizon
+        let sun_zenith = sun_direction.y.clamp(0., 1.).acos();
+
+        // Chromaticity x, y and luminance Y
+        let perez = [
+            Perez {
+                a: -0.0193 * t - 0.2592,
+                b: -0.0665 * t + 0.0008,
+                c: -0.0004 * t + 0.2125,
+                d: -0.0641 * t - 0.8989,
+                e: -0.0033 * t + 0.0452,
+            },
+            Perez {
+                a: -0.0167 * t - 0.2608,
+                b: -0.0950 * t + 0.0092,
+                c: -0.0079 * t + 0.2102,
+                d: -0.0441 * t - 1.6537,
+                e: -0.0109 * t + 0.0529,
+            },
+            Perez {
+                a: 0.1787 * t - 1.4630,
+                b: -0.3554 * t + 0.4275,
+                c: -0.0227 * t + 5.3251,
+                d: 0.1206 * t - 2.5771,
+                e: -0.0670 * t + 0.3703,
+            },
+        ];
+
+        let mut sky = PhysicalSky {
+            sun_direction,
+            sun_zenith,
+            sun_cos_theta_max: (degrees_to_radians(SUN_ANGULAR_DIAMETER) / 2.).cos(),
+            sun_radiance: RGBColor::zero(),
+            turbidity: t,
+            intensity,
+            perez,
+            zenith: PhysicalSky::zenith_values(t, sun_zenith),
+            ground: RGBColor::zero(),
+        };
+        if sun_direction.y > 0. {
+            sky.sun_radiance = sky.sun_irradiance() / sky.sun_solid_angle();
+        }
+        sky.ground = ground_albedo * sky.ground_irradiance() / PI;
+        sky
+    }
+
+    /// Sky radiance arriving from infinitely far away along `-direction`,
+    /// including the sun's disc.
+    pub fn radiance(&self, direction: Vec3) -> RGBColor {
+        let d = direction.normalized();
+        if d.y < 0. {
+            return self.ground;
+        }
+
+        match self.in_sun(d) {
+            true => self.sky_radiance(d) + self.sun_radiance,
+            false => self.sky_radiance(d),
+        }
+    }
+
+    /// Solid-angle density of `sample_li` picking `direction`: uniform over
+    /// the sun's disc, zero elsewhere.
+    pub fn pdf(&self, direction: Vec3) -> f64 {
+        let d = direction.normalized();
+        match self.sun_direction.y > 0. && d.y >= 0. && self.in_sun(d) {
+            true => 1. / self.sun_solid_angle(),
+            false => 0.,
+        }
+    }
+
+    fn in_sun(&self, d: Vec3) -> bool {
+        Vec3::dot(d, self.sun_direction) >= self.sun_cos_theta_max
+    }
+
+    fn sun_solid_angle(&self) -> f64 {
+        2. * PI * (1. - self.sun_cos_theta_max)
+    }
+
+    /// Irradiance of the sun on a surface facing it, with the color left
+    /// after crossing the atmosphere.
+    fn sun_irradiance(&self) -> RGBColor {
+        self.sun_transmittance() * (SUN_ILLUMINANCE / 683. * self.intensity)
+    }
+
+    /// Bakes the sky into an equirectangular image, e.g. for an `EnvironmentMap`.
+    pub fn to_image(&self, width: usize, height: usize) -> Image {
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let u = (x as f64 + 0.5) / width as f64;
+                let v = (y as f64 + 0.5) / height as f64;
+                pixels.push(self.radiance(equirect_direction(u, v)));
+            }
+        }
+
+        Image::new(width, height, pixels)
+    }
+
+    fn sky_radiance(&self, d: Vec3) -> RGBColor {
+        // Keep the Perez formula finite right at the horizon
+        let cos_theta = d.y.max(0.01);
+        let gamma = Vec3::dot(d, self.sun_direction).clamp(-1., 1.).acos();
+
+        let value = |i: usize| {
+            self.zenith[i] * self.perez[i].eval(cos_theta, gamma) / self.perez[i].eval(1., self.sun_zenith)
+        };
+        let (x, y, luminance) = (value(0), value(1), value(2));
+        if y <= 0. {
+            return RGBColor::zero();
+        }
+
+        // kcd/m² to the white-light equivalent of W/m²/sr
+        let big_y = luminance * 1000. / 683.;
+        let xyz = Vec3::new(x / y * big_y, big_y, (1. - x - y) / y * big_y);
+        let rgb = xyz_to_linear_srgb(xyz) * self.intensity;
+        RGBColor::new(rgb.x.max(0.), rgb.y.max(0.), rgb.z.max(0.))
+    }
+
+    /// Zenith chromaticity x, y and luminance (kcd/m²) for turbidity `t`.
+    fn zenith_values(t: f64, theta: f64) -> [f64; 3] {
+        let chi = (4. / 9. - t / 120.) * (PI - 2. * theta);
+        let luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+
+        let (t2, th2, th3) = (t * t, theta * theta, theta * theta * theta);
+        let x = t2 * (0.00166 * th3 - 0.00375 * th2 + 0.00209 * theta)
+            + t * (-0.02903 * th3 + 0.06377 * th2 - 0.03202 * theta + 0.00394)
+            + (0.11693 * th3 - 0.21196 * th2 + 0.06052 * theta + 0.25886);
+        let y = t2 * (0.00275 * th3 - 0.00610 * th2 + 0.00317 * theta)
+            + t * (-0.04214 * th3 + 0.08970 * th2 - 0.04153 * theta + 0.00516)
+            + (0.15346 * th3 - 0.26756 * th2 + 0.06670 * theta + 0.26688);
+
+        [x, y, luminance.max(0.)]
+    }
+
+    /// Fraction of sunlight per channel that makes it through the atmosphere,
+    /// from Rayleigh and aerosol (Angstrom) extinction along the sun's path.
+    fn sun_transmittance(&self) -> RGBColor {
+        // Kasten's relative air mass, which stays finite at the horizon
+        let zenith_degrees = self.sun_zenith.to_degrees();
+        let air_mass = 1. / (self.sun_zenith.cos() + 0.15 * (93.885 - zenith_degrees).powf(-1.253));
+        let beta = 0.04608 * self.turbidity - 0.04586;
+
+        let channel = |lambda: f64| {
+            let rayleigh = 0.008735 * lambda.powf(-4.08);
+            let aerosol = beta * lambda.powf(-1.3);
+            (-(rayleigh + aerosol) * air_mass).exp()
+        };
+        let [r, g, b] = CHANNEL_WAVELENGTHS;
+        RGBColor::new(channel(r), channel(g), channel(b))
+    }
+
+    /// Irradiance on the ground from the sky dome and the sun, integrated
+    /// numerically over the upper hemisphere.
+    fn ground_irradiance(&self) -> RGBColor {
+        const THETA_STEPS: usize = 32;
+        const PHI_STEPS: usize = 64;
+        let d_theta = PI / 2. / THETA_STEPS as f64;
+        let d_phi = 2. * PI / PHI_STEPS as f64;
+
+        let mut irradiance = RGBColor::zero();
+        for i in 0..THETA_STEPS {
+            let theta = (i as f64 + 0.5) * d_theta;
+            for j in 0..PHI_STEPS {
+                let phi = (j as f64 + 0.5) * d_phi;
+                let d = Vec3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin());
+                irradiance += self.sky_radiance(d) * (theta.cos() * theta.sin() * d_theta * d_phi);
+            }
+        }
+
+        if self.sun_direction.y > 0. {
+            irradiance += self.sun_irradiance() * self.sun_direction.y;
+        }
+        irradiance
+    }
+}
+
+/// Samples the sun's disc, the only part of the sky too small for BSDF
+/// sampling to find.
+impl Light for PhysicalSky {
+    fn sample_li(&self, _point: Point) -> Option<IncidentLight> {
+        if self.sun_direction.y <= 0. {
+            return None;
+        }
+
+        let local = Vec3::rand_in_cone(self.sun_cos_theta_max);
+        let direction = Onb::from_w(self.sun_direction).local(local).normalized();
+
+        Some(IncidentLight {
+            direction,
+            distance: f64::INFINITY,
+            radiance: self.radiance(direction),
+            pdf: 1. / self.sun_solid_angle(),
+        })
+    }
+
+    fn power(&self) -> f64 {
+        PI * self.sun_irradiance().luminance()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f64, b: f64, tolerance: f64) {
+        assert!((a - b).abs() < tolerance, "{} vs {}", a, b);
+    }
+
+    #[test]
+    fn sun_is_highest_at_solar_noon() {
+        // Summer solstice at 40 degrees north: due south, 90 - 40 + 23.44 degrees up
+        let summer = SunPosition::from_location(40., 0., 172, 12.025);
+        assert_close(summer.elevation, 73.44, 1.);
+        assert_close(summer.azimuth, 180., 1.);
+
+        // Winter solstice in Sydney, local noon: north, near the zenith
+        let sydney = SunPosition::from_location(-33.87, 151.21, 355, 12. - 151.21 / 15.);
+        assert_close(sydney.elevation, 79.57, 1.);
+        assert!(sydney.direction().z < 0.);
+    }
+
+    #[test]
+    fn sun_rises_in_the_east_at_the_equinox() {
+        let sunrise = SunPosition::from_location(40., 0., 80, 6.1);
+        assert_close(sunrise.elevation, 0., 2.);
+        assert_close(sunrise.azimuth, 90., 2.);
+
+        let midnight = SunPosition::from_location(40., 0., 80, 0.);
+        assert_close(midnight.elevation, -50., 1.);
+    }
+
+    #[test]
+    fn direction_follows_elevation_and_azimuth() {
+        let east = SunPosition::new(0., 90.).direction();
+        assert!((east - Vec3::new(1., 0., 0.)).length() < 1e-12);
+
+        let zenith = SunPosition::new(90., 123.).direction();
+        assert!((zenith - Vec3::new(0., 1., 0.)).length() < 1e-12);
+
+        let south = SunPosition::new(30., 180.).direction();
+        assert!((south - Vec3::new(0., 0.5, 0.75f64.sqrt())).length() < 1e-12);
+        assert_close(south.length(), 1., 1e-12);
+    }
+}
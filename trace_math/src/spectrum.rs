@@ -0,0 +1,299 @@
+use std::cell::RefCell;
+use std::sync::OnceLock;
+
+use crate::vec3::{RGBColor, Vec3};
+
+/// Range of visible wavelengths sampled in spectral mode, in nanometers.
+pub const LAMBDA_MIN: f64 = 380.;
+pub const LAMBDA_MAX: f64 = 720.;
+
+/// Wavelength at which non-spectral renders evaluate dispersive IORs
+/// (the Fraunhofer d line, where IORs are usually quoted).
+pub const REFERENCE_WAVELENGTH: f64 = 587.6;
+
+/// Wavelengths carried by a path in spectral mode: a uniformly sampled
+/// hero wavelength and two more evenly rotated across the visible range.
+/// While they are active, the x, y and z channels of every color hold the
+/// spectrum at each of them.
+#[derive(Debug, Clone, Copy)]
+pub struct SampledWavelengths {
+    lambda: [f64; 3],
+    secondary_terminated: bool,
+}
+
+impl SampledWavelengths {
+    /// Wavelengths for the uniform number `u`.
+    pub fn sample(u: f64) -> SampledWavelengths {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let hero = LAMBDA_MIN + u * range;
+        let rotate = |k: f64| {
+            let lambda = hero + k * range / 3.;
+            match lambda > LAMBDA_MAX {
+                true => lambda - range,
+                false => lambda,
+            }
+        };
+
+        SampledWavelengths { lambda: [hero, rotate(1.), rotate(2.)], secondary_terminated: false }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    /// Linear sRGB color of a path carrying `values` at these wavelengths,
+    /// averaging the estimates of all three (or only the hero's).
+    pub fn to_rgb(&self, values: RGBColor) -> RGBColor {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let values = [values.x, values.y, values.z];
+        let mut xyz = Vec3::zero();
+        for (&lambda, &value) in self.lambda.iter().zip(values.iter()) {
+            xyz += cie_xyz(lambda) * value;
+        }
+        let xyz = xyz * (range / (3. * cie_y_integral()));
+
+        let white = equal_energy_white();
+        let rgb = xyz_to_linear_srgb(xyz);
+        RGBColor::new(rgb.x / white.x, rgb.y / white.y, rgb.z / white.z)
+    }
+}
+
+thread_local! {
+    static ACTIVE_WAVELENGTHS: RefCell<Option<SampledWavelengths>> = const { RefCell::new(None) };
+}
+
+/// Runs `f` in spectral mode, with `wavelengths` as the path's wavelengths.
+pub fn with_wavelengths<R, F: FnOnce() -> R>(wavelengths: SampledWavelengths, f: F) -> R {
+    let previous = ACTIVE_WAVELENGTHS.with(|active| active.replace(Some(wavelengths)));
+    let _restore = RestoreWavelengths(previous);
+    f()
+}
+
+/// Puts back the wavelengths that were active before `with_wavelengths`,
+/// even if its closure panics.
+struct RestoreWavelengths(Option<SampledWavelengths>);
+
+impl Drop for RestoreWavelengths {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        ACTIVE_WAVELENGTHS.with(|active| active.replace(previous));
+    }
+}
+
+/// Hero wavelength of the current path, `None` outside spectral mode.
+pub fn hero_wavelength() -> Option<f64> {
+    ACTIVE_WAVELENGTHS.with(|active| active.borrow().map(|wl| wl.hero()))
+}
+
+/// All the wavelengths of the current path, `None` outside spectral mode.
+pub fn active_wavelengths() -> Option<[f64; 3]> {
+    ACTIVE_WAVELENGTHS.with(|active| active.borrow().map(|wl| wl.lambda))
+}
+
+/// Drops the secondary wavelengths of the current path, after an event
+/// (such as dispersion) that only makes sense for the hero one.
+pub fn terminate_secondary() {
+    ACTIVE_WAVELENGTHS.with(|active| {
+        if let Some(wl) = active.borrow_mut().as_mut() {
+            wl.secondary_terminated = true;
+        }
+    });
+}
+
+/// Whether only the hero wavelength of the current path is left, in which
+/// case integrators carry it alone in the x channel, with three times the weight.
+pub fn secondary_terminated() -> bool {
+    ACTIVE_WAVELENGTHS.with(|active| active.borrow().is_some_and(|wl| wl.secondary_terminated))
+}
+
+/// Spectral counterpart of an RGB reflectance or emission: the color itself
+/// outside spectral mode, otherwise its smooth upsampled spectrum (Smits 1999)
+/// at the path's wavelengths.
+pub fn upsample(rgb: RGBColor) -> RGBColor {
+    match ACTIVE_WAVELENGTHS.with(|active| *active.borrow()) {
+        Some(wl) => RGBColor::new(
+            rgb_spectrum(rgb, wl.lambda[0]),
+            rgb_spectrum(rgb, wl.lambda[1]),
+            rgb_spectrum(rgb, wl.lambda[2]),
+        ),
+        None => rgb,
+    }
+}
+
+// Smits' basis spectra, over 10 equal bins spanning LAMBDA_MIN..LAMBDA_MAX
+const SMITS_WHITE: [f64; 10] = [1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000];
+const SMITS_CYAN: [f64; 10] = [0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000];
+const SMITS_MAGENTA: [f64; 10] = [1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959];
+const SMITS_YELLOW: [f64; 10] = [0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840];
+const SMITS_RED: [f64; 10] = [0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149];
+const SMITS_GREEN: [f64; 10] = [0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025];
+const SMITS_BLUE: [f64; 10] = [1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496];
+
+/// Basis spectrum value at `lambda`, interpolated between bin centers.
+fn basis(table: &[f64; 10], lambda: f64) -> f64 {
+    let width = (LAMBDA_MAX - LAMBDA_MIN) / 10.;
+    let x = ((lambda - LAMBDA_MIN) / width - 0.5).clamp(0., 9.);
+    let i = (x as usize).min(8);
+    let f = x - i as f64;
+    table[i] * (1. - f) + table[i + 1] * f
+}
+
+fn rgb_spectrum(rgb: RGBColor, lambda: f64) -> f64 {
+    let (r, g, b) = (rgb.x, rgb.y, rgb.z);
+    let at = |table: &[f64; 10]| basis(table, lambda);
+
+    if r <= g && r <= b {
+        let base = r * at(&SMITS_WHITE);
+        match g <= b {
+            true => base + (g - r) * at(&SMITS_CYAN) + (b - g) * at(&SMITS_BLUE),
+            false => base + (b - r) * at(&SMITS_CYAN) + (g - b) * at(&SMITS_GREEN),
+        }
+    } else if g <= r && g <= b {
+        let base = g * at(&SMITS_WHITE);
+        match r <= b {
+            true => base + (r - g) * at(&SMITS_MAGENTA) + (b - r) * at(&SMITS_BLUE),
+            false => base + (b - g) * at(&SMITS_MAGENTA) + (r - b) * at(&SMITS_RED),
+        }
+    } else {
+        let base = b * at(&SMITS_WHITE);
+        match r <= g {
+            true => base + (r - b) * at(&SMITS_YELLOW) + (g - r) * at(&SMITS_GREEN),
+            false => base + (g - b) * at(&SMITS_YELLOW) + (r - g) * at(&SMITS_RED),
+        }
+    }
+}
+
+/// CIE 1931 2-degree color matching functions, using the multi-lobe
+/// gaussian fit of Wyman, Sloan and Shirley (2013).
+pub fn cie_xyz(lambda: f64) -> Vec3 {
+    let g = |mu: f64, sigma_low: f64, sigma_high: f64| {
+        let sigma = match lambda < mu {
+            true => sigma_low,
+            false => sigma_high,
+        };
+        (-0.5 * ((lambda - mu) / sigma).powi(2)).exp()
+    };
+
+    let x = 1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2);
+    let y = 0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1);
+    let z = 1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8);
+    Vec3::new(x, y, z)
+}
+
+pub fn xyz_to_linear_srgb(xyz: Vec3) -> RGBColor {
+    RGBColor::new(
+        3.2406 * xyz.x - 1.5372 * xyz.y - 0.4986 * xyz.z,
+        -0.9689 * xyz.x + 1.8758 * xyz.y + 0.0415 * xyz.z,
+        0.0557 * xyz.x - 0.2040 * xyz.y + 1.0570 * xyz.z,
+    )
+}
+
+const INTEGRATION_STEPS: usize = 3400;
+
+/// Integral of the y matching function over the sampled range.
+fn cie_y_integral() -> f64 {
+    static INTEGRAL: OnceLock<f64> = OnceLock::new();
+    *INTEGRAL.get_or_init(|| {
+        let step = (LAMBDA_MAX - LAMBDA_MIN) / INTEGRATION_STEPS as f64;
+        (0..INTEGRATION_STEPS)
+            .map(|i| cie_xyz(LAMBDA_MIN + (i as f64 + 0.5) * step).y * step)
+            .sum()
+    })
+}
+
+/// Linear sRGB color of a constant spectrum of one, used to white balance
+/// so that white reflectances stay white.
+fn equal_energy_white() -> RGBColor {
+    static WHITE: OnceLock<(f64, f64, f64)> = OnceLock::new();
+    let (r, g, b) = *WHITE.get_or_init(|| {
+        let step = (LAMBDA_MAX - LAMBDA_MIN) / INTEGRATION_STEPS as f64;
+        let mut xyz = Vec3::zero();
+        for i in 0..INTEGRATION_STEPS {
+            xyz += cie_xyz(LAMBDA_MIN + (i as f64 + 0.5) * step) * step;
+        }
+        let rgb = xyz_to_linear_srgb(xyz / cie_y_integral());
+        (rgb.x, rgb.y, rgb.z)
+    });
+    RGBColor::new(r, g, b)
+}
+
+/// Linear sRGB color of a reflectance spectrum, integrated over `samples`
+/// evenly spaced wavelengths. A reflectance of one everywhere gives white.
+pub fn reflectance_to_rgb<F: Fn(f64) -> f64>(samples: usize, reflectance: F) -> RGBColor {
+    let step = (LAMBDA_MAX - LAMBDA_MIN) / samples as f64;
+    let mut xyz = Vec3::zero();
+    for i in 0..samples {
+        let lambda = LAMBDA_MIN + (i as f64 + 0.5) * step;
+        xyz += cie_xyz(lambda) * (reflectance(lambda) * step);
+    }
+
+    let white = equal_energy_white();
+    let rgb = xyz_to_linear_srgb(xyz / cie_y_integral());
+    RGBColor::new(rgb.x / white.x, rgb.y / white.y, rgb.z / white.z)
+}
+
+/// Index of refraction, possibly varying with wavelength. Wavelengths are
+/// given in nanometers, while the dispersion formulas use micrometers.
+#[derive(Debug, Clone, Copy)]
+pub enum Ior {
+    Constant(f64),
+    /// n = a + b / lambda^2
+    Cauchy { a: f64, b: f64 },
+    /// n^2 = 1 + sum(b_i lambda^2 / (lambda^2 - c_i))
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Ior {
+    /// Schott BK7 crown glass.
+    pub fn bk7() -> Ior {
+        Ior::Sellmeier {
+            b: [1.03961212, 0.231792344, 1.01046945],
+            c: [0.00600069867, 0.0200179144, 103.560653],
+        }
+    }
+
+    /// Dense flint glass (Schott SF11), strongly dispersive.
+    pub fn sf11() -> Ior {
+        Ior::Sellmeier {
+            b: [1.73759695, 0.313747346, 1.89878101],
+            c: [0.013188707, 0.0623068142, 155.23629],
+        }
+    }
+
+    pub fn diamond() -> Ior {
+        Ior::Sellmeier {
+            b: [4.3356, 0.3306, 0.],
+            c: [0.1060 * 0.1060, 0.1750 * 0.1750, 0.],
+        }
+    }
+
+    pub fn at(&self, lambda: f64) -> f64 {
+        let um = lambda / 1000.;
+        match *self {
+            Ior::Constant(n) => n,
+            Ior::Cauchy { a, b } => a + b / (um * um),
+            Ior::Sellmeier { b, c } => {
+                let l2 = um * um;
+                let n2 = 1. + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f64>();
+                n2.max(1.).sqrt()
+            },
+        }
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, Ior::Constant(_))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wavelengths_are_restored_after_a_panic() {
+        let wavelengths = SampledWavelengths::sample(0.3);
+        let result = std::panic::catch_unwind(|| with_wavelengths(wavelengths, || panic!("lost path")));
+        assert!(result.is_err());
+        assert!(hero_wavelength().is_none());
+    }
+}
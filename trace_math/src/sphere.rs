@@ -1,8 +1,12 @@
+use std::f64::consts::PI;
+
+use crate::aabb::Aabb;
 use crate::hittable::Hittable;
 use crate::hit_info::HitInfo;
 use crate::vec3::{Vec3, Point};
 use crate::ray::Ray;
 use crate::material::Material;
+use crate::onb::Onb;
 
 pub struct Sphere {
     pub center: Point,
@@ -14,10 +18,30 @@ impl Sphere {
     pub fn new(center: Point, radius: f64, material: Box<dyn Material + 'static>) -> Sphere {
         Sphere{ center, radius, material }
     }
+
+    /// Surface area of the sphere.
+    fn area(&self) -> f64 {
+        4. * PI * self.radius * self.radius
+    }
+
+    /// Direction in which u grows at a point of the unit sphere, zero at the poles.
+    fn tangent(p: Point) -> Vec3 {
+        Vec3::new(p.z, 0., -p.x)
+    }
+
+    /// Spherical coordinates of a point on the unit sphere, mapped to [0, 1]:
+    /// u is the angle around the Y axis starting from X = -1,
+    /// v is the angle from Y = -1 to Y = +1.
+    fn get_uv(p: Point) -> (f64, f64) {
+        let theta = (-p.y).clamp(-1., 1.).acos();
+        let phi = f64::atan2(-p.z, p.x) + PI;
+
+        (phi / (2. * PI), theta / PI)
+    }
 }
 
 impl Hittable for Sphere {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitInfo> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitInfo<'_>> {
         let oc = r.origin - self.center;
         let a = r.direction.length_squared();
         let half_b = Vec3::dot(r.direction, oc);
@@ -25,19 +49,70 @@ impl Hittable for Sphere {
         let discriminant = half_b*half_b - a*c;
 
         if discriminant < 0. {
-            None
-        } else {
-            let sqrt_disc = discriminant.sqrt();
-            let root = ( -half_b - sqrt_disc ) / a;
+            return None;
+        }
+
+        // Take the nearest root in range, falling back to the far one
+        // so that rays starting inside the sphere find their way out
+        let sqrt_disc = discriminant.sqrt();
+        let mut root = ( -half_b - sqrt_disc ) / a;
+        if (root < t_min) || (t_max < root) {
+            root = ( -half_b + sqrt_disc ) / a;
             if (root < t_min) || (t_max < root) {
-                None
-            } else {
-                let t = root;
-                let point = r.at(t);
-                let out_normal = ( point - self.center ) / self.radius;
-                let material = &*self.material;
-                Some(HitInfo::new(point, out_normal, t, material, r))
+                return None;
             }
         }
+
+        let t = root;
+        let point = r.at(t);
+        let out_normal = ( point - self.center ) / self.radius;
+        let material = &*self.material;
+        let uv = Sphere::get_uv(out_normal);
+        Some(HitInfo::new(point, out_normal, t, uv, material, r).with_tangent(Sphere::tangent(out_normal)))
     }
-}
\ No newline at end of file
+
+    fn pdf_value(&self, origin: Point, direction: Vec3) -> f64 {
+        let distance_squared = (self.center - origin).length_squared();
+        if distance_squared <= self.radius * self.radius {
+            return 0.;
+        }
+
+        match self.hit(Ray::new(origin, direction), 0.001, f64::INFINITY) {
+            Some(_) => {
+                let cos_theta_max = (1. - self.radius * self.radius / distance_squared).sqrt();
+                let solid_angle = 2. * PI * (1. - cos_theta_max);
+                1. / solid_angle
+            },
+            None => 0.,
+        }
+    }
+
+    fn random(&self, origin: Point) -> Vec3 {
+        let direction = self.center - origin;
+        let uvw = Onb::from_w(direction);
+        uvw.local(Vec3::rand_to_sphere(self.radius, direction.length_squared()))
+    }
+
+    fn sample_surface(&self) -> Option<(HitInfo<'_>, f64)> {
+        let out_normal = Vec3::rand_on_unit_sphere();
+        let point = self.center + out_normal * self.radius;
+        let seen_from = Ray::new(point + out_normal, -out_normal);
+        let uv = Sphere::get_uv(out_normal);
+        let hit = HitInfo::new(point, out_normal, 1., uv, &*self.material, seen_from);
+
+        Some((hit, 1. / self.area()))
+    }
+
+    fn surface_pdf(&self, point: Point) -> f64 {
+        let eps = 1e-6 * self.radius.max(1.);
+        match ((point - self.center).length() - self.radius).abs() < eps {
+            true => 1. / self.area(),
+            false => 0.,
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let extent = Vec3::ones() * self.radius;
+        Some(Aabb::new(self.center - extent, self.center + extent))
+    }
+}
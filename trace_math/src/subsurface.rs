@@ -0,0 +1,206 @@
+use std::f64::consts::PI;
+
+use crate::hit_info::HitInfo;
+use crate::hittable::Hittable;
+use crate::material::{HenyeyGreenstein, Lobe, Material, ScatterRecord};
+use crate::microfacet;
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::spectrum;
+use crate::utils::rand_utils;
+use crate::vec3::{RGBColor, Vec3};
+
+/// Translucent object (skin, wax, marble, milk) rendered with volumetric
+/// random-walk subsurface scattering inside a closed shape, which may be
+/// a `Sphere` or a `TriangleMesh`. The shape's own material is ignored.
+///
+/// Light crossing the surface goes through a rough, diffusely transmitting
+/// boundary with a Fresnel reflection on top, then scatters through a dense
+/// medium until it finds its way out. The walk is carried out by the
+/// integrator: inside the shape `hit` reports collisions in the medium.
+///
+/// Distances are sampled with a randomly picked color channel, and each
+/// channel is reweighted by the distance travelled since the ray's origin.
+pub struct SubsurfaceObject {
+    shape: Box<dyn Hittable>,
+    sigma_t: RGBColor,
+    boundary: SubsurfaceBoundary,
+    interior: SubsurfaceInterior,
+}
+
+impl SubsurfaceObject {
+    /// `albedo` is the overall color the object takes under diffuse light
+    /// and `mean_free_path` how far light travels inside it, per channel.
+    /// `g` is the anisotropy of the interior's phase function.
+    pub fn new(shape: Box<dyn Hittable>, albedo: RGBColor, mean_free_path: RGBColor, ior: f64, g: f64) -> SubsurfaceObject {
+        // Albedo inversion from Chiang et al. 2016, "Practical and
+        // Controllable Subsurface Scattering for Production Path Tracing"
+        let remap = |a: f64, d: f64| {
+            let a = a.clamp(0., 0.999);
+            let alpha = 1. - (-5.09406 * a + 2.61188 * a * a - 4.31805 * a * a * a).exp();
+            let s = 1.9 - a + 3.5 * (a - 0.8) * (a - 0.8);
+            (1. / (d * s).max(1e-9), alpha)
+        };
+        let (tr, ar) = remap(albedo.x, mean_free_path.x);
+        let (tg, ag) = remap(albedo.y, mean_free_path.y);
+        let (tb, ab) = remap(albedo.z, mean_free_path.z);
+        let sigma_t = RGBColor::new(tr, tg, tb);
+
+        SubsurfaceObject {
+            shape,
+            sigma_t,
+            boundary: SubsurfaceBoundary { ior, sigma_t },
+            interior: SubsurfaceInterior {
+                sigma_t,
+                phase: HenyeyGreenstein::new(RGBColor::new(ar, ag, ab), g),
+            },
+        }
+    }
+}
+
+impl Hittable for SubsurfaceObject {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitInfo<'_>> {
+        let surface = self.shape.hit(r, t_min, t_max)?;
+        if surface.get_front_face() {
+            return Some(surface.with_material(&self.boundary));
+        }
+
+        // Inside the shape: sample a collision before reaching the surface
+        let sigma = match (rand_utils::rand_f64() * 3.) as usize {
+            0 => self.sigma_t.x,
+            1 => self.sigma_t.y,
+            _ => self.sigma_t.z,
+        };
+        let distance = -(1. - rand_utils::rand_f64()).ln() / sigma;
+        let t = (distance / r.direction.length()).max(t_min);
+        match t < surface.get_t() {
+            true => Some(HitInfo::in_medium(r.at(t), t, &self.interior, r)),
+            false => Some(surface.with_material(&self.boundary)),
+        }
+    }
+
+    fn hit_surface(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitInfo<'_>> {
+        let surface = self.shape.hit(r, t_min, t_max)?;
+        Some(surface.with_material(&self.boundary))
+    }
+
+    /// Segments reaching here don't cross the surface, so they are either
+    /// fully inside the shape or fully outside.
+    fn transmittance(&self, r: Ray, t_min: f64, t_max: f64) -> RGBColor {
+        match self.shape.hit(r, t_min, f64::INFINITY) {
+            Some(exit) if !exit.get_front_face() => {
+                let d = (t_max - t_min) * r.direction.length();
+                let s = self.sigma_t;
+                RGBColor::new((-s.x * d).exp(), (-s.y * d).exp(), (-s.z * d).exp())
+            },
+            _ => RGBColor::ones(),
+        }
+    }
+}
+
+/// Per channel transmittance over `distance`, divided by the chance of the
+/// channel-averaged free flight sampling going that far.
+fn pass_weight(sigma_t: RGBColor, distance: f64) -> RGBColor {
+    let tr = RGBColor::new((-sigma_t.x * distance).exp(), (-sigma_t.y * distance).exp(), (-sigma_t.z * distance).exp());
+    let pdf = (tr.x + tr.y + tr.z) / 3.;
+    match pdf > 0. {
+        true => tr / pdf,
+        false => RGBColor::zero(),
+    }
+}
+
+/// Distance from the previous vertex of the path to `hit_info`.
+fn travelled(ray: Ray, hit_info: &HitInfo) -> f64 {
+    (hit_info.get_point() - ray.origin).length()
+}
+
+/// Surface of a subsurface object: Fresnel reflection, otherwise diffuse
+/// transmission in either direction.
+struct SubsurfaceBoundary {
+    ior: f64,
+    sigma_t: RGBColor,
+}
+
+impl SubsurfaceBoundary {
+    /// Weight of the lobe transmitting through the surface.
+    fn transmission(&self, ray: Ray, hit_info: &HitInfo) -> (f64, RGBColor) {
+        match hit_info.get_front_face() {
+            true => {
+                let cos_i = Vec3::dot(-ray.direction.normalized(), hit_info.get_normal());
+                let reflectance = microfacet::fresnel_dielectric(cos_i, self.ior);
+                (1. - reflectance, RGBColor::ones())
+            },
+            false => (1., pass_weight(self.sigma_t, travelled(ray, hit_info))),
+        }
+    }
+}
+
+impl Material for SubsurfaceBoundary {
+    fn scatter(&self, ray: Ray, hit_info: HitInfo) -> Option<ScatterRecord> {
+        let (probability, weight) = self.transmission(ray, &hit_info);
+        if rand_utils::rand_f64() >= probability {
+            let direction = ray.direction.normalized().reflect(hit_info.get_normal());
+            return Some(ScatterRecord::specular(Ray::new(hit_info.get_point(), direction), RGBColor::ones()));
+        }
+
+        // The normal faces the incoming ray, so transmission goes against it
+        let uvw = Onb::from_w(-hit_info.get_normal());
+        let direction = uvw.local(Vec3::rand_cosine_direction());
+        let pdf = probability * Vec3::dot(uvw.w, direction).max(0.) / PI;
+        let ray = Ray::new(hit_info.get_point(), direction);
+        let attenuation = spectrum::upsample(weight);
+        Some(ScatterRecord { ray, attenuation, pdf, is_specular: false, lobe: Lobe::Transmission })
+    }
+
+    fn eval(&self, ray: Ray, hit_info: HitInfo, scattered: Vec3) -> RGBColor {
+        let (probability, weight) = self.transmission(ray, &hit_info);
+        let cosine = Vec3::dot(-hit_info.get_normal(), scattered.normalized()).max(0.);
+        spectrum::upsample(weight) * (probability * cosine / PI)
+    }
+
+    fn pdf(&self, ray: Ray, hit_info: HitInfo, scattered: Vec3) -> f64 {
+        let (probability, _) = self.transmission(ray, &hit_info);
+        probability * Vec3::dot(-hit_info.get_normal(), scattered.normalized()).max(0.) / PI
+    }
+}
+
+/// Scattering event inside a subsurface object.
+struct SubsurfaceInterior {
+    sigma_t: RGBColor,
+    phase: HenyeyGreenstein,
+}
+
+impl SubsurfaceInterior {
+    /// Single-scattering albedo times the transmittance to the collision,
+    /// over the density of the collision having been sampled there.
+    fn collision_weight(&self, ray: Ray, hit_info: &HitInfo) -> RGBColor {
+        let d = travelled(ray, hit_info);
+        let s = self.sigma_t;
+        let density = RGBColor::new(s.x * (-s.x * d).exp(), s.y * (-s.y * d).exp(), s.z * (-s.z * d).exp());
+        let pdf = (density.x + density.y + density.z) / 3.;
+        match pdf > 0. {
+            true => self.phase.albedo * density / pdf,
+            false => RGBColor::zero(),
+        }
+    }
+}
+
+impl Material for SubsurfaceInterior {
+    fn scatter(&self, ray: Ray, hit_info: HitInfo) -> Option<ScatterRecord> {
+        let srec = self.phase.scatter(ray, hit_info)?;
+        let attenuation = spectrum::upsample(self.collision_weight(ray, &hit_info));
+        Some(ScatterRecord { attenuation, ..srec })
+    }
+
+    fn eval(&self, ray: Ray, hit_info: HitInfo, scattered: Vec3) -> RGBColor {
+        spectrum::upsample(self.collision_weight(ray, &hit_info)) * self.phase.pdf(ray, hit_info, scattered)
+    }
+
+    fn pdf(&self, ray: Ray, hit_info: HitInfo, scattered: Vec3) -> f64 {
+        self.phase.pdf(ray, hit_info, scattered)
+    }
+
+    fn albedo(&self, _: HitInfo) -> RGBColor {
+        self.phase.albedo
+    }
+}
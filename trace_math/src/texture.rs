@@ -0,0 +1,72 @@
+use crate::vec3::{Point, RGBColor};
+
+/// Spatially varying material input, looked up from surface coordinates
+/// and the hit point.
+pub trait Texture {
+    fn value(&self, u: f64, v: f64, p: Point) -> RGBColor;
+
+    /// Single-channel lookup, for inputs such as roughness.
+    fn scalar(&self, u: f64, v: f64, p: Point) -> f64 {
+        self.value(u, v, p).luminance()
+    }
+}
+
+pub struct SolidColor {
+    color: RGBColor,
+}
+
+impl SolidColor {
+    pub fn new(color: RGBColor) -> SolidColor {
+        SolidColor { color }
+    }
+
+    /// Grey texture whose `scalar` lookup is `value`.
+    pub fn scalar(value: f64) -> SolidColor {
+        SolidColor { color: RGBColor::ones() * value }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _: f64, _: f64, _: Point) -> RGBColor {
+        self.color
+    }
+}
+
+impl From<f64> for Box<dyn Texture> {
+    fn from(value: f64) -> Box<dyn Texture> {
+        Box::new(SolidColor::scalar(value))
+    }
+}
+
+impl From<RGBColor> for Box<dyn Texture> {
+    fn from(color: RGBColor) -> Box<dyn Texture> {
+        Box::new(SolidColor::new(color))
+    }
+}
+
+/// 3D checkerboard alternating between two textures every `1 / frequency` units.
+pub struct CheckerTexture {
+    even: Box<dyn Texture>,
+    odd: Box<dyn Texture>,
+    frequency: f64,
+}
+
+impl CheckerTexture {
+    pub fn new(even: Box<dyn Texture>, odd: Box<dyn Texture>, frequency: f64) -> CheckerTexture {
+        CheckerTexture { even, odd, frequency }
+    }
+
+    pub fn from_colors(even: RGBColor, odd: RGBColor, frequency: f64) -> CheckerTexture {
+        CheckerTexture::new(Box::new(SolidColor::new(even)), Box::new(SolidColor::new(odd)), frequency)
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, p: Point) -> RGBColor {
+        let cell = (self.frequency * p.x).floor() + (self.frequency * p.y).floor() + (self.frequency * p.z).floor();
+        match cell as i64 % 2 == 0 {
+            true => self.even.value(u, v, p),
+            false => self.odd.value(u, v, p),
+        }
+    }
+}
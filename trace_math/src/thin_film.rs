@@ -0,0 +1,149 @@
+use std::f64::consts::PI;
+use std::ops;
+
+use crate::hit_info::HitInfo;
+use crate::spectrum;
+use crate::texture::Texture;
+use crate::vec3::RGBColor;
+
+/// Number of wavelengths the interference pattern is integrated over.
+const WAVELENGTH_SAMPLES: usize = 32;
+
+/// Thin transparent layer coating a surface, whose reflections interfere
+/// with the ones of the surface beneath and produce iridescent colors.
+/// Thickness is in nanometers; a texture can make it swirl.
+pub struct ThinFilm {
+    ior: f64,
+    thickness: Box<dyn Texture>,
+}
+
+impl ThinFilm {
+    pub fn new(ior: f64, thickness: impl Into<Box<dyn Texture>>) -> ThinFilm {
+        ThinFilm { ior, thickness: thickness.into() }
+    }
+
+    /// Reflectance at `hit_info` for light arriving at `cos_i` from a medium
+    /// of IOR `outside`, with `substrate(lambda)` the complex IOR (eta, k)
+    /// of what lies under the film. In spectral mode each channel holds the
+    /// reflectance at one of the path's wavelengths, otherwise the RGB color
+    /// of the whole spectrum, clamped to [0, 1].
+    pub fn reflectance<F>(&self, hit_info: &HitInfo, cos_i: f64, outside: f64, substrate: F) -> RGBColor
+        where
+            F: Fn(f64) -> (f64, f64)
+    {
+        let (u, v) = hit_info.get_uv();
+        let thickness = self.thickness.scalar(u, v, hit_info.get_point()).max(0.);
+        let at = |lambda: f64| {
+            let (eta, k) = substrate(lambda);
+            airy_reflectance(cos_i, outside, self.ior, Complex::new(eta, k), thickness, lambda)
+        };
+
+        match spectrum::active_wavelengths() {
+            Some(lambda) => RGBColor::new(at(lambda[0]), at(lambda[1]), at(lambda[2])),
+            None => {
+                // The spectrum of a strong interference peak can land outside the sRGB gamut
+                let r = spectrum::reflectance_to_rgb(WAVELENGTH_SAMPLES, at);
+                RGBColor::new(r.x.clamp(0., 1.), r.y.clamp(0., 1.), r.z.clamp(0., 1.))
+            },
+        }
+    }
+}
+
+/// Reflectance of a film of IOR `n2` and `thickness` nm between a
+/// dielectric of IOR `n1` and a (possibly absorbing) substrate `n3`,
+/// summing all the inter-reflections inside the film (Airy formula),
+/// averaged over both polarizations.
+fn airy_reflectance(cos_i: f64, n1: f64, n2: f64, n3: Complex, thickness: f64, lambda: f64) -> f64 {
+    let cos1 = Complex::real(cos_i.clamp(0., 1.));
+    let sin2_1 = 1. - cos_i * cos_i;
+    let n1c = Complex::real(n1);
+    let n2c = Complex::real(n2);
+
+    // Snell's law with complex angles covers absorption and total internal reflection
+    let cos2 = (Complex::real(1.) - Complex::real(n1 * n1 * sin2_1 / (n2 * n2))).sqrt();
+    let cos3 = (Complex::real(1.) - Complex::real(n1 * n1 * sin2_1) / (n3 * n3)).sqrt();
+
+    let phase = Complex::real(4. * PI * n2 * thickness / lambda) * cos2;
+    let shift = (Complex::new(0., 1.) * phase).exp();
+
+    let airy = |r12: Complex, r23: Complex| {
+        let r = (r12 + r23 * shift) / (Complex::real(1.) + r12 * r23 * shift);
+        r.norm_sqr().min(1.)
+    };
+
+    let rs12 = (n1c * cos1 - n2c * cos2) / (n1c * cos1 + n2c * cos2);
+    let rs23 = (n2c * cos2 - n3 * cos3) / (n2c * cos2 + n3 * cos3);
+    let rp12 = (n2c * cos1 - n1c * cos2) / (n2c * cos1 + n1c * cos2);
+    let rp23 = (n3 * cos2 - n2c * cos3) / (n3 * cos2 + n2c * cos3);
+
+    (airy(rs12, rs23) + airy(rp12, rp23)) / 2.
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    fn new(re: f64, im: f64) -> Complex {
+        Complex { re, im }
+    }
+
+    fn real(re: f64) -> Complex {
+        Complex { re, im: 0. }
+    }
+
+    fn norm_sqr(&self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    /// Principal square root.
+    fn sqrt(&self) -> Complex {
+        let norm = self.norm_sqr().sqrt();
+        let re = ((norm + self.re) / 2.).max(0.).sqrt();
+        let im = ((norm - self.re) / 2.).max(0.).sqrt();
+        Complex::new(re, if self.im < 0. { -im } else { im })
+    }
+
+    fn exp(&self) -> Complex {
+        let scale = self.re.exp();
+        Complex::new(scale * self.im.cos(), scale * self.im.sin())
+    }
+}
+
+impl ops::Add for Complex {
+    type Output = Complex;
+
+    fn add(self, rhs: Complex) -> Complex {
+        Complex::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl ops::Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, rhs: Complex) -> Complex {
+        Complex::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl ops::Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, rhs: Complex) -> Complex {
+        Complex::new(self.re * rhs.re - self.im * rhs.im, self.re * rhs.im + self.im * rhs.re)
+    }
+}
+
+impl ops::Div for Complex {
+    type Output = Complex;
+
+    fn div(self, rhs: Complex) -> Complex {
+        let denom = rhs.norm_sqr();
+        Complex::new(
+            (self.re * rhs.re + self.im * rhs.im) / denom,
+            (self.im * rhs.re - self.re * rhs.im) / denom,
+        )
+    }
+}
@@ -0,0 +1,509 @@
+use std::io::{Error, ErrorKind, Result};
+use std::ops::Range;
+
+use crate::aabb::Aabb;
+use crate::distribution::Distribution1D;
+use crate::hit_info::HitInfo;
+use crate::hittable::Hittable;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::utils::rand_utils::rand_f64;
+use crate::vec3::{Point, Vec3};
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Intersection of `r` with the triangle (a, b, c) in `[t_min, t_max]`
+/// (Moller-Trumbore), as the ray parameter and the barycentric weights of b and c.
+fn intersect(r: Ray, [a, b, c]: [Point; 3], t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+    let edge1 = b - a;
+    let edge2 = c - a;
+    let p = Vec3::cross(r.direction, edge2);
+    let det = Vec3::dot(edge1, p);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+
+    let inv_det = 1. / det;
+    let s = r.origin - a;
+    let beta = Vec3::dot(s, p) * inv_det;
+    if !(0. ..=1.).contains(&beta) {
+        return None;
+    }
+
+    let q = Vec3::cross(s, edge1);
+    let gamma = Vec3::dot(r.direction, q) * inv_det;
+    if gamma < 0. || beta + gamma > 1. {
+        return None;
+    }
+
+    let t = Vec3::dot(edge2, q) * inv_det;
+    match t >= t_min && t <= t_max {
+        true => Some((t, beta, gamma)),
+        false => None,
+    }
+}
+
+/// Direction in which the texture's u coordinate grows across a triangle,
+/// or its first edge when the uvs are degenerate.
+fn dp_du([a, b, c]: [Point; 3], [ua, ub, uc]: [(f64, f64); 3]) -> Vec3 {
+    let (du1, dv1) = (ub.0 - ua.0, ub.1 - ua.1);
+    let (du2, dv2) = (uc.0 - ua.0, uc.1 - ua.1);
+    let det = du1 * dv2 - dv1 * du2;
+    match det.abs() > 1e-12 {
+        true => ((b - a) * dv2 - (c - a) * dv1) / det,
+        false => b - a,
+    }
+}
+
+/// Uniformly distributed point, with the barycentric weights of b and c.
+fn random_point([a, b, c]: [Point; 3]) -> (Point, f64, f64) {
+    let su = rand_f64().sqrt();
+    let beta = rand_f64() * su;
+    let gamma = 1. - su;
+    (a * (1. - beta - gamma) + b * beta + c * gamma, beta, gamma)
+}
+
+/// Whether `point` is on the triangle: close to its plane and projecting
+/// inside it.
+fn on_triangle([a, b, c]: [Point; 3], point: Point) -> bool {
+    let normal = Vec3::cross(b - a, c - a).normalized();
+    let eps = 1e-6 * (b - a).length().max((c - a).length()).max(1.);
+    if Vec3::dot(point - a, normal).abs() > eps {
+        return false;
+    }
+
+    let probe = Ray::new(point + normal, -normal);
+    intersect(probe, [a, b, c], 0., f64::INFINITY).is_some()
+}
+
+/// Largest number of faces in a leaf of a mesh's hierarchy.
+const LEAF_FACES: usize = 4;
+
+/// Bounds of the triangle (a, b, c), padded a little so that flat boxes
+/// still get hit.
+fn face_bounds([a, b, c]: [Point; 3]) -> Aabb {
+    let pad = Vec3::ones() * 1e-6;
+    let bounds = Aabb::new(a, b).union(&Aabb::new(c, c));
+    Aabb::new(bounds.min - pad, bounds.max + pad)
+}
+
+/// Node of the bounding volume hierarchy over a mesh's faces, stored
+/// depth-first: an inner node's first child comes right after it.
+enum FaceNode {
+    Leaf { bounds: Aabb, faces: Range<usize> },
+    Inner { bounds: Aabb, second_child: usize },
+}
+
+/// Appends the hierarchy over `faces` to `nodes`, reordering the faces so
+/// that each leaf covers a contiguous range of them, starting at `offset`.
+fn build_hierarchy(positions: &[Point], faces: &mut [[usize; 3]], offset: usize, nodes: &mut Vec<FaceNode>) {
+    let corners = |face: [usize; 3]| face.map(|i| positions[i]);
+    let bounds = faces.iter()
+        .map(|&face| face_bounds(corners(face)))
+        .reduce(|a, b| a.union(&b))
+        .unwrap_or_else(|| Aabb::new(Point::zero(), Point::zero()));
+
+    if faces.len() <= LEAF_FACES {
+        nodes.push(FaceNode::Leaf { bounds, faces: offset..offset + faces.len() });
+        return;
+    }
+
+    // Median split of the centroids along their widest axis
+    let centroid = |face: [usize; 3]| {
+        let [a, b, c] = corners(face);
+        (a + b + c) / 3.
+    };
+    let first = centroid(faces[0]);
+    let spread = faces.iter().fold(Aabb::new(first, first), |spread, &face| {
+        let p = centroid(face);
+        spread.union(&Aabb::new(p, p))
+    });
+    let extent = spread.max - spread.min;
+    let axis = match (extent.x >= extent.y, extent.x >= extent.z, extent.y >= extent.z) {
+        (true, true, _) => 0,
+        (false, _, true) => 1,
+        _ => 2,
+    };
+    let key = |face: [usize; 3]| {
+        let p = centroid(face);
+        [p.x, p.y, p.z][axis]
+    };
+
+    let middle = faces.len() / 2;
+    faces.select_nth_unstable_by(middle, |&f, &g| key(f).total_cmp(&key(g)));
+
+    let index = nodes.len();
+    nodes.push(FaceNode::Inner { bounds, second_child: 0 });
+    let (left, right) = faces.split_at_mut(middle);
+    build_hierarchy(positions, left, offset, nodes);
+    let second = nodes.len();
+    build_hierarchy(positions, right, offset + middle, nodes);
+    nodes[index] = FaceNode::Inner { bounds, second_child: second };
+}
+
+pub struct Triangle {
+    pub vertices: [Point; 3],
+    pub material: Box<dyn Material>,
+}
+
+impl Triangle {
+    pub fn new(a: Point, b: Point, c: Point, material: Box<dyn Material>) -> Triangle {
+        Triangle { vertices: [a, b, c], material }
+    }
+
+    fn area(&self) -> f64 {
+        let [a, b, c] = self.vertices;
+        Vec3::cross(b - a, c - a).length() / 2.
+    }
+
+    fn out_normal(&self) -> Vec3 {
+        let [a, b, c] = self.vertices;
+        Vec3::cross(b - a, c - a).normalized()
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitInfo<'_>> {
+        let (t, beta, gamma) = intersect(r, self.vertices, t_min, t_max)?;
+        let [a, b, _] = self.vertices;
+        Some(HitInfo::new(r.at(t), self.out_normal(), t, (beta, gamma), &*self.material, r).with_tangent(b - a))
+    }
+
+    fn pdf_value(&self, origin: Point, direction: Vec3) -> f64 {
+        let (t, ..) = match intersect(Ray::new(origin, direction), self.vertices, 0.001, f64::INFINITY) {
+            Some(hit) => hit,
+            None => return 0.,
+        };
+
+        let distance_squared = t * t * direction.length_squared();
+        let cosine = Vec3::dot(self.out_normal(), direction.normalized()).abs();
+        match cosine > 0. {
+            true => distance_squared / (cosine * self.area()),
+            false => 0.,
+        }
+    }
+
+    fn random(&self, origin: Point) -> Vec3 {
+        let (point, ..) = random_point(self.vertices);
+        point - origin
+    }
+
+    fn sample_surface(&self) -> Option<(HitInfo<'_>, f64)> {
+        let (point, beta, gamma) = random_point(self.vertices);
+        let out_normal = self.out_normal();
+        let seen_from = Ray::new(point + out_normal, -out_normal);
+        let hit = HitInfo::new(point, out_normal, 1., (beta, gamma), &*self.material, seen_from);
+
+        Some((hit, 1. / self.area()))
+    }
+
+    fn surface_pdf(&self, point: Point) -> f64 {
+        match on_triangle(self.vertices, point) {
+            true => 1. / self.area(),
+            false => 0.,
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let [a, b, c] = self.vertices;
+        Some(Aabb::new(a, b).union(&Aabb::new(c, c)))
+    }
+}
+
+/// Indexed triangle mesh sharing one material, with optional per-vertex
+/// normals and texture coordinates. Faces wind counter-clockwise seen from
+/// the outside.
+pub struct TriangleMesh {
+    positions: Vec<Point>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+    faces: Vec<[usize; 3]>,
+    hierarchy: Vec<FaceNode>,
+    bounds: Aabb,
+    // Picks faces in proportion to their area, `None` without faces
+    face_areas: Option<Distribution1D>,
+    area: f64,
+    material: Box<dyn Material>,
+}
+
+impl TriangleMesh {
+    /// `normals` and `uvs` are either empty or have one entry per position,
+    /// and faces index into the positions, otherwise the data is rejected.
+    pub fn new(
+        positions: Vec<Point>,
+        normals: Vec<Vec3>,
+        uvs: Vec<(f64, f64)>,
+        faces: Vec<[usize; 3]>,
+        material: Box<dyn Material>,
+        ) -> Result<TriangleMesh>
+    {
+        if !normals.is_empty() && normals.len() != positions.len() {
+            return Err(invalid("mesh normals don't match its positions"));
+        }
+        if !uvs.is_empty() && uvs.len() != positions.len() {
+            return Err(invalid("mesh uvs don't match its positions"));
+        }
+        if faces.iter().flatten().any(|&i| i >= positions.len()) {
+            return Err(invalid("mesh face index out of range"));
+        }
+
+        let first = positions.first().cloned().unwrap_or_else(Point::zero);
+        let bounds = positions.iter()
+            .fold(Aabb::new(first, first), |bounds, &p| bounds.union(&Aabb::new(p, p)));
+
+        let mut faces = faces;
+        let mut hierarchy = vec![];
+        build_hierarchy(&positions, &mut faces, 0, &mut hierarchy);
+
+        let areas: Vec<f64> = faces.iter()
+            .map(|&face| {
+                let [a, b, c] = face.map(|i| positions[i]);
+                Vec3::cross(b - a, c - a).length() / 2.
+            })
+            .collect();
+        let area = areas.iter().sum();
+        let face_areas = match areas.is_empty() {
+            true => None,
+            false => Some(Distribution1D::new(areas)),
+        };
+
+        Ok(TriangleMesh { positions, normals, uvs, faces, hierarchy, bounds, face_areas, area, material })
+    }
+
+    pub fn face_count(&self) -> usize {
+        self.faces.len()
+    }
+
+    pub fn get_bounds(&self) -> Aabb {
+        self.bounds
+    }
+
+    /// Corners of each face.
+    pub fn triangles(&self) -> impl Iterator<Item = [Point; 3]> + '_ {
+        self.faces.iter().map(|&face| self.face_vertices(face))
+    }
+
+    fn face_vertices(&self, face: [usize; 3]) -> [Point; 3] {
+        face.map(|i| self.positions[i])
+    }
+
+    /// Closest face `r` hits, with the distance and barycentric weights.
+    fn closest_face(&self, r: Ray, t_min: f64, t_max: f64) -> Option<(f64, f64, f64, [usize; 3])> {
+        // Median splits keep the hierarchy far shallower than this
+        let mut stack = [0usize; 64];
+        let mut depth = 1;
+
+        let mut closest: Option<(f64, f64, f64, [usize; 3])> = None;
+        while depth > 0 {
+            depth -= 1;
+            let index = stack[depth];
+            let t_far = closest.map_or(t_max, |(t, ..)| t);
+            match &self.hierarchy[index] {
+                FaceNode::Leaf { bounds, faces } => {
+                    if bounds.hit_range(r, t_min, t_far).is_none() {
+                        continue;
+                    }
+                    for &face in self.faces[faces.clone()].iter() {
+                        let t_far = closest.map_or(t_max, |(t, ..)| t);
+                        if let Some((t, beta, gamma)) = intersect(r, self.face_vertices(face), t_min, t_far) {
+                            closest = Some((t, beta, gamma, face));
+                        }
+                    }
+                },
+                FaceNode::Inner { bounds, second_child } => {
+                    if bounds.hit_range(r, t_min, t_far).is_some() {
+                        stack[depth] = *second_child;
+                        stack[depth + 1] = index + 1;
+                        depth += 2;
+                    }
+                },
+            }
+        }
+        closest
+    }
+
+    /// Hit of `r` at distance `t` on `face`, at the barycentric weights
+    /// `beta` and `gamma` of its second and third corners.
+    fn face_hit(&self, face: [usize; 3], r: Ray, t: f64, beta: f64, gamma: f64) -> HitInfo<'_> {
+        let alpha = 1. - beta - gamma;
+        let [a, b, c] = self.face_vertices(face);
+        let geometric_normal = Vec3::cross(b - a, c - a).normalized();
+
+        // Interpolated normals are kept on the same side as the geometric one,
+        // which alone decides whether the hit is on the front face
+        let shading_normal = match self.normals.is_empty() {
+            true => geometric_normal,
+            false => {
+                let [na, nb, nc] = face.map(|i| self.normals[i]);
+                let n = na * alpha + nb * beta + nc * gamma;
+                match (n.length_squared() > 1e-12, Vec3::dot(n, geometric_normal) < 0.) {
+                    (false, _) => geometric_normal,
+                    (true, true) => -n.normalized(),
+                    (true, false) => n.normalized(),
+                }
+            },
+        };
+
+        let (uv, tangent) = match self.uvs.is_empty() {
+            true => ((beta, gamma), b - a),
+            false => {
+                let [ua, ub, uc] = face.map(|i| self.uvs[i]);
+                let uv = (ua.0 * alpha + ub.0 * beta + uc.0 * gamma, ua.1 * alpha + ub.1 * beta + uc.1 * gamma);
+                (uv, dp_du([a, b, c], [ua, ub, uc]))
+            },
+        };
+
+        let hit = HitInfo::new(r.at(t), geometric_normal, t, uv, &*self.material, r);
+        hit.with_shading_normal(shading_normal).with_tangent(tangent)
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitInfo<'_>> {
+        let (t, beta, gamma, face) = self.closest_face(r, t_min, t_max)?;
+        Some(self.face_hit(face, r, t, beta, gamma))
+    }
+
+    fn pdf_value(&self, origin: Point, direction: Vec3) -> f64 {
+        let (t, _, _, face) = match self.closest_face(Ray::new(origin, direction), 0.001, f64::INFINITY) {
+            Some(hit) => hit,
+            None => return 0.,
+        };
+
+        let [a, b, c] = self.face_vertices(face);
+        let distance_squared = t * t * direction.length_squared();
+        let cosine = Vec3::dot(Vec3::cross(b - a, c - a).normalized(), direction.normalized()).abs();
+        match cosine > 0. {
+            true => distance_squared / (cosine * self.area),
+            false => 0.,
+        }
+    }
+
+    fn random(&self, origin: Point) -> Vec3 {
+        match self.sample_surface() {
+            Some((hit, _)) => hit.get_point() - origin,
+            None => Vec3::new(1., 0., 0.),
+        }
+    }
+
+    fn sample_surface(&self) -> Option<(HitInfo<'_>, f64)> {
+        let (_, _, index) = self.face_areas.as_ref()?.sample(rand_f64());
+        let face = self.faces[index];
+        let [a, b, c] = self.face_vertices(face);
+        let (point, beta, gamma) = random_point([a, b, c]);
+
+        let out_normal = Vec3::cross(b - a, c - a).normalized();
+        let seen_from = Ray::new(point + out_normal, -out_normal);
+        Some((self.face_hit(face, seen_from, 1., beta, gamma), 1. / self.area))
+    }
+
+    fn surface_pdf(&self, point: Point) -> f64 {
+        match self.triangles().any(|vertices| on_triangle(vertices, point)) {
+            true => 1. / self.area,
+            false => 0.,
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::vec3::RGBColor;
+
+    fn material() -> Box<dyn Material> {
+        Box::new(Lambertian::new(RGBColor::ones()))
+    }
+
+    /// Latitude-longitude tessellation of the unit sphere.
+    fn sphere_mesh(rings: usize, segments: usize) -> TriangleMesh {
+        let mut positions = vec![];
+        for i in 0..=rings {
+            let theta = std::f64::consts::PI * i as f64 / rings as f64;
+            for j in 0..segments {
+                let phi = 2. * std::f64::consts::PI * j as f64 / segments as f64;
+                positions.push(Point::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin()));
+            }
+        }
+
+        let index = |i: usize, j: usize| i * segments + j % segments;
+        let mut faces = vec![];
+        for i in 0..rings {
+            for j in 0..segments {
+                faces.push([index(i, j), index(i + 1, j), index(i + 1, j + 1)]);
+                faces.push([index(i, j), index(i + 1, j + 1), index(i, j + 1)]);
+            }
+        }
+        TriangleMesh::new(positions, vec![], vec![], faces, material()).unwrap()
+    }
+
+    #[test]
+    fn hierarchy_finds_the_closest_face() {
+        let mesh = sphere_mesh(12, 24);
+        for k in 0..500 {
+            let origin = Point::new((k as f64 * 0.37).sin() * 3., (k as f64 * 0.91).cos() * 3., 2.5);
+            let target = Point::new((k as f64 * 1.3).cos() * 0.8, (k as f64 * 0.7).sin() * 0.8, 0.);
+            let r = Ray::new(origin, target - origin);
+
+            let brute_force = mesh.triangles()
+                .filter_map(|vertices| intersect(r, vertices, 0.001, f64::INFINITY))
+                .map(|(t, ..)| t)
+                .reduce(f64::min);
+            let hit = mesh.hit(r, 0.001, f64::INFINITY).map(|hit| hit.get_t());
+            assert_eq!(hit, brute_force);
+        }
+    }
+
+    #[test]
+    fn front_face_follows_the_geometric_normal() {
+        let positions = vec![Point::new(-1., -1., 0.), Point::new(1., -1., 0.), Point::new(0., 1., 0.)];
+        let tilted = Vec3::new(1., 0., 0.1).normalized();
+        let mesh = TriangleMesh::new(positions, vec![tilted; 3], vec![], vec![[0, 1, 2]], material()).unwrap();
+
+        // Grazing ray hitting the front, although it leaves the shading normal behind
+        let r = Ray::new(Point::new(-5., 0., 1.), Vec3::new(5., 0., -1.));
+        let hit = mesh.hit(r, 0.001, f64::INFINITY).unwrap();
+        assert!(hit.get_front_face());
+        assert!((hit.get_normal() - tilted).length() < 1e-9);
+    }
+
+    #[test]
+    fn surface_samples_follow_the_face_areas() {
+        let positions = vec![Point::new(0., 0., 0.), Point::new(2., 0., 0.), Point::new(0., 2., 0.), Point::new(3., 0., 0.), Point::new(3., 1., 0.)];
+        let mesh = TriangleMesh::new(positions, vec![], vec![], vec![[0, 1, 2], [1, 3, 4]], material()).unwrap();
+        let area = 2.5;
+
+        const SAMPLES: usize = 10_000;
+        let mut on_large_face = 0;
+        for _ in 0..SAMPLES {
+            let (hit, pdf) = mesh.sample_surface().unwrap();
+            assert_eq!(pdf, 1. / area);
+            assert_eq!(mesh.surface_pdf(hit.get_point()), 1. / area);
+            if hit.get_point().x + hit.get_point().y <= 2. {
+                on_large_face += 1;
+            }
+
+            // Directions towards the samples are those `pdf_value` accounts for
+            let origin = Point::new(1., 0.5, 1.);
+            assert!(mesh.pdf_value(origin, hit.get_point() - origin) > 0.);
+        }
+
+        let fraction = on_large_face as f64 / SAMPLES as f64;
+        assert!((fraction - 0.8).abs() < 0.02, "{}", fraction);
+        assert_eq!(mesh.surface_pdf(Point::new(2.5, 0.9, 0.)), 0.);
+    }
+
+    #[test]
+    fn inconsistent_data_is_rejected() {
+        let positions = || vec![Point::new(0., 0., 0.), Point::new(1., 0., 0.), Point::new(0., 1., 0.)];
+        assert!(TriangleMesh::new(positions(), vec![Vec3::new(0., 0., 1.)], vec![], vec![[0, 1, 2]], material()).is_err());
+        assert!(TriangleMesh::new(positions(), vec![], vec![(0., 0.); 2], vec![[0, 1, 2]], material()).is_err());
+        assert!(TriangleMesh::new(positions(), vec![], vec![], vec![[0, 1, 3]], material()).is_err());
+    }
+}
@@ -1,3 +1,15 @@
 pub fn degrees_to_radians(degrees: f64) -> f64 {
     degrees * std::f64::consts::PI / 180.
-}
\ No newline at end of file
+}
+
+/// Power heuristic (beta = 2) weight for a sample drawn from the strategy
+/// with density `pdf_f`, when `pdf_g` could have produced it as well.
+pub fn power_heuristic(pdf_f: f64, pdf_g: f64) -> f64 {
+    let f2 = pdf_f * pdf_f;
+    let g2 = pdf_g * pdf_g;
+    if f2 + g2 == 0. {
+        0.
+    } else {
+        f2 / (f2 + g2)
+    }
+}
@@ -1,2 +1,3 @@
 pub mod rand_utils;
-pub mod math_utils;
\ No newline at end of file
+pub mod math_utils;
+pub mod sampler;
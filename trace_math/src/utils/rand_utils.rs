@@ -1,16 +1,21 @@
 use rand::{self, Rng};
 
+use crate::utils::sampler;
+
 pub fn rand_f64_range(min: f64, max: f64) -> f64 {
-    let mut rng = rand::thread_rng();
-    rng.gen::<f64>() * (max - min) + min
+    rand_f64() * (max - min) + min
 }
 
 pub fn rand_f64() -> f64 {
-    let mut rng = rand::thread_rng();
-    rng.gen::<f64>()
+    match sampler::next_from_active() {
+        Some(value) => value,
+        None => {
+            let mut rng = rand::thread_rng();
+            rng.gen::<f64>()
+        },
+    }
 }
 
 pub fn rand_bool() -> bool {
-    let mut rng = rand::thread_rng();
-    rng.gen::<bool>()
-}
\ No newline at end of file
+    rand_f64() < 0.5
+}
@@ -0,0 +1,145 @@
+use std::cell::RefCell;
+use std::f64::consts::PI;
+use std::rc::Rc;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Source of the uniform numbers consumed by `rand_utils`.
+pub trait Sampler {
+    fn next_f64(&mut self) -> f64;
+}
+
+thread_local! {
+    static ACTIVE_SAMPLER: RefCell<Option<Rc<RefCell<dyn Sampler>>>> = RefCell::new(None);
+}
+
+/// Runs `f` with every random number drawn through `rand_utils` on this
+/// thread coming from `sampler`, instead of the thread's own generator.
+pub fn with_sampler<R, F: FnOnce() -> R>(sampler: Rc<RefCell<dyn Sampler>>, f: F) -> R {
+    let previous = ACTIVE_SAMPLER.with(|active| active.replace(Some(sampler)));
+    let _restore = RestoreSampler(previous);
+    f()
+}
+
+/// Puts back the sampler that was active before `with_sampler`, even if
+/// its closure panics.
+struct RestoreSampler(Option<Rc<RefCell<dyn Sampler>>>);
+
+impl Drop for RestoreSampler {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        ACTIVE_SAMPLER.with(|active| active.replace(previous));
+    }
+}
+
+/// Next number from the active sampler, `None` when there is none. The
+/// sampler is used in place, so the common case costs a single check.
+pub(crate) fn next_from_active() -> Option<f64> {
+    ACTIVE_SAMPLER.with(|active| active.borrow().as_ref().map(|sampler| sampler.borrow_mut().next_f64()))
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PrimarySample {
+    value: f64,
+    last_modification: u64,
+    value_backup: f64,
+    modification_backup: u64,
+}
+
+/// Primary sample space sampler for Metropolis light transport: the random
+/// numbers a path consumes form a point in the unit hypercube, which is
+/// mutated either by small gaussian perturbations or by drawing it anew
+/// (large steps). Values are created lazily as the path asks for them.
+pub struct PssSampler {
+    rng: StdRng,
+    samples: Vec<PrimarySample>,
+    sigma: f64,
+    large_step_probability: f64,
+    current_iteration: u64,
+    large_step: bool,
+    last_large_step_iteration: u64,
+    sample_index: usize,
+}
+
+impl PssSampler {
+    /// Samplers created with the same seed produce the same initial point.
+    pub fn new(seed: u64, sigma: f64, large_step_probability: f64) -> PssSampler {
+        PssSampler {
+            rng: StdRng::seed_from_u64(seed),
+            samples: vec![],
+            sigma,
+            large_step_probability,
+            current_iteration: 0,
+            large_step: true,
+            last_large_step_iteration: 0,
+            sample_index: 0,
+        }
+    }
+
+    /// Begins a new mutation of the current point.
+    pub fn start_iteration(&mut self) {
+        self.current_iteration += 1;
+        self.large_step = self.rng.gen::<f64>() < self.large_step_probability;
+        self.sample_index = 0;
+    }
+
+    pub fn accept(&mut self) {
+        if self.large_step {
+            self.last_large_step_iteration = self.current_iteration;
+        }
+    }
+
+    pub fn reject(&mut self) {
+        for sample in self.samples.iter_mut() {
+            if sample.last_modification == self.current_iteration {
+                sample.value = sample.value_backup;
+                sample.last_modification = sample.modification_backup;
+            }
+        }
+        self.current_iteration -= 1;
+    }
+
+    pub fn is_large_step(&self) -> bool {
+        self.large_step
+    }
+
+    fn ensure_ready(&mut self, index: usize) {
+        while self.samples.len() <= index {
+            self.samples.push(PrimarySample { value: 0., last_modification: 0, value_backup: 0., modification_backup: 0 });
+        }
+
+        let sample = &mut self.samples[index];
+
+        // Catch up with a large step the value missed since it was last used
+        if sample.last_modification < self.last_large_step_iteration {
+            sample.value = self.rng.gen::<f64>();
+            sample.last_modification = self.last_large_step_iteration;
+        }
+
+        sample.value_backup = sample.value;
+        sample.modification_backup = sample.last_modification;
+
+        if self.large_step {
+            sample.value = self.rng.gen::<f64>();
+        } else {
+            // Small steps skipped while the value was unused add up to a
+            // single perturbation with a wider gaussian
+            let n_small = (self.current_iteration - sample.last_modification) as f64;
+            let sigma = self.sigma * n_small.sqrt();
+            let (r1, r2): (f64, f64) = (self.rng.gen(), self.rng.gen());
+            let normal = (-2. * (1. - r1).ln()).sqrt() * (2. * PI * r2).cos();
+            sample.value = (sample.value + normal * sigma).rem_euclid(1.);
+        }
+        sample.last_modification = self.current_iteration;
+    }
+}
+
+impl Sampler for PssSampler {
+    fn next_f64(&mut self) -> f64 {
+        let index = self.sample_index;
+        self.ensure_ready(index);
+        self.sample_index += 1;
+        self.samples[index].value
+    }
+}
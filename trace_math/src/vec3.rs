@@ -129,6 +129,58 @@ impl Vec3 {
         }
     }
 
+    /// Uniformly distributed point on the unit sphere (pdf = 1 / 4pi).
+    pub fn rand_on_unit_sphere() -> Vec3 {
+        let z = 1. - 2. * rand_utils::rand_f64();
+        let r = (1. - z * z).max(0.).sqrt();
+        let phi = 2. * std::f64::consts::PI * rand_utils::rand_f64();
+
+        Vec3 { x: r * phi.cos(), y: r * phi.sin(), z }
+    }
+
+    /// Cosine-weighted direction around the local +z axis (pdf = cos / pi).
+    pub fn rand_cosine_direction() -> Vec3 {
+        let r1 = rand_utils::rand_f64();
+        let r2 = rand_utils::rand_f64();
+        let phi = 2. * std::f64::consts::PI * r1;
+
+        let x = phi.cos() * r2.sqrt();
+        let y = phi.sin() * r2.sqrt();
+        let z = (1. - r2).sqrt();
+
+        Vec3 { x, y, z }
+    }
+
+    /// Uniform direction inside the cone subtended by a sphere of `radius`
+    /// seen from `distance_squared` away, around the local +z axis.
+    pub fn rand_to_sphere(radius: f64, distance_squared: f64) -> Vec3 {
+        let cos_theta_max = (1. - radius * radius / distance_squared).max(0.).sqrt();
+        Vec3::rand_in_cone(cos_theta_max)
+    }
+
+    /// Uniform direction within the angle of cosine `cos_theta_max` from the
+    /// local +z axis.
+    pub fn rand_in_cone(cos_theta_max: f64) -> Vec3 {
+        let r1 = rand_utils::rand_f64();
+        let r2 = rand_utils::rand_f64();
+        let z = 1. + r2 * (cos_theta_max - 1.);
+
+        let phi = 2. * std::f64::consts::PI * r1;
+        let x = phi.cos() * (1. - z * z).max(0.).sqrt();
+        let y = phi.sin() * (1. - z * z).max(0.).sqrt();
+
+        Vec3 { x, y, z }
+    }
+
+    /// Relative luminance of a linear RGB color (Rec. 709 primaries).
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.x + 0.7152 * self.y + 0.0722 * self.z
+    }
+
+    pub fn max_component(&self) -> f64 {
+        self.x.max(self.y).max(self.z)
+    }
+
     pub fn is_near_zero(&self) -> bool {
         let eps = 1e-8;
         self.x.abs() < eps && self.y.abs() < eps && self.z.abs() < eps
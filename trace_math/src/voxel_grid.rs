@@ -0,0 +1,89 @@
+use std::io::{Error, ErrorKind, Result};
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Dense 3D grid of scalar values, sampled at voxel centers and stored with
+/// x varying fastest, then y, then z.
+pub struct VoxelGrid {
+    nx: usize,
+    ny: usize,
+    nz: usize,
+    data: Vec<f64>,
+}
+
+impl VoxelGrid {
+    /// Grid of `nx * ny * nz` values, rejected unless they are all there,
+    /// finite and non-negative.
+    pub fn new(nx: usize, ny: usize, nz: usize, data: Vec<f64>) -> Result<VoxelGrid> {
+        if nx == 0 || ny == 0 || nz == 0 {
+            return Err(invalid("voxel grid has an empty resolution"));
+        }
+        if nx.checked_mul(ny).and_then(|n| n.checked_mul(nz)) != Some(data.len()) {
+            return Err(invalid("voxel grid data doesn't match its resolution"));
+        }
+
+        // Negative or infinite densities would make tracking estimate
+        // transmittances above one
+        if data.iter().any(|v| !v.is_finite() || *v < 0.) {
+            return Err(invalid("voxel grid values must be finite and non-negative"));
+        }
+
+        Ok(VoxelGrid { nx, ny, nz, data })
+    }
+
+    pub fn resolution(&self) -> (usize, usize, usize) {
+        (self.nx, self.ny, self.nz)
+    }
+
+    pub fn max_value(&self) -> f64 {
+        self.data.iter().cloned().fold(0., f64::max)
+    }
+
+    fn voxel(&self, x: usize, y: usize, z: usize) -> f64 {
+        self.data[(z * self.ny + y) * self.nx + x]
+    }
+
+    /// Trilinearly interpolated value at `(u, v, w)` in [0, 1]^3; zero outside.
+    pub fn lookup(&self, u: f64, v: f64, w: f64) -> f64 {
+        if !(0. ..=1.).contains(&u) || !(0. ..=1.).contains(&v) || !(0. ..=1.).contains(&w) {
+            return 0.;
+        }
+
+        let (x0, x1, fx) = VoxelGrid::cell(u, self.nx);
+        let (y0, y1, fy) = VoxelGrid::cell(v, self.ny);
+        let (z0, z1, fz) = VoxelGrid::cell(w, self.nz);
+
+        let lerp = |a: f64, b: f64, t: f64| a * (1. - t) + b * t;
+        let c00 = lerp(self.voxel(x0, y0, z0), self.voxel(x1, y0, z0), fx);
+        let c10 = lerp(self.voxel(x0, y1, z0), self.voxel(x1, y1, z0), fx);
+        let c01 = lerp(self.voxel(x0, y0, z1), self.voxel(x1, y0, z1), fx);
+        let c11 = lerp(self.voxel(x0, y1, z1), self.voxel(x1, y1, z1), fx);
+
+        lerp(lerp(c00, c10, fy), lerp(c01, c11, fy), fz)
+    }
+
+    /// Neighbouring voxel indices along one axis and the blend between them.
+    fn cell(u: f64, n: usize) -> (usize, usize, f64) {
+        let x = (u * n as f64 - 0.5).clamp(0., (n - 1) as f64);
+        let x0 = x.floor() as usize;
+        let x1 = (x0 + 1).min(n - 1);
+        (x0, x1, x - x0 as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_grids_are_rejected() {
+        assert!(VoxelGrid::new(2, 1, 1, vec![0., 1.]).is_ok());
+        assert!(VoxelGrid::new(0, 1, 1, vec![]).is_err());
+        assert!(VoxelGrid::new(2, 2, 1, vec![0., 1.]).is_err());
+        assert!(VoxelGrid::new(usize::MAX, 2, 1, vec![0., 1.]).is_err());
+        assert!(VoxelGrid::new(2, 1, 1, vec![0., -1.]).is_err());
+        assert!(VoxelGrid::new(2, 1, 1, vec![f64::NAN, 1.]).is_err());
+    }
+}
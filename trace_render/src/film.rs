@@ -0,0 +1,46 @@
+use trace_math::vec3::RGBColor;
+
+/// Accumulation buffer for the rendered image. Pixels are addressed from the
+/// top-left corner, in the same order they are written out.
+pub struct Film {
+    width: u32,
+    height: u32,
+    pixels: Vec<RGBColor>,
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32) -> Film {
+        Film { width, height, pixels: vec![RGBColor::zero(); (width * height) as usize] }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn add_sample(&mut self, x: u32, y: u32, color: RGBColor) {
+        let idx = (y * self.width + x) as usize;
+        self.pixels[idx] += color;
+    }
+
+    /// Adds a contribution at film coordinates `(s, t)`, measured from the
+    /// bottom-left corner as in `CameraModel::get_ray`.
+    pub fn splat(&mut self, s: f64, t: f64, color: RGBColor) {
+        let x = (s * self.width as f64) as u32;
+        let row = (t * self.height as f64) as u32;
+        if x < self.width && row < self.height {
+            self.add_sample(x, self.height - 1 - row, color);
+        }
+    }
+
+    pub fn to_ppm(&self, samples_per_pixel: u32) -> String {
+        let mut img = trace_utils::initialize_ppm(self.width, self.height);
+        for pixel in self.pixels.iter() {
+            trace_utils::add_pixel_to_ppm(&mut img, *pixel, samples_per_pixel);
+        }
+        img
+    }
+}
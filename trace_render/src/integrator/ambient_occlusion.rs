@@ -0,0 +1,43 @@
+use trace_camera::camera::CameraModel;
+use trace_math::hittable::{Hittable, HittableList};
+use trace_math::onb::Onb;
+use trace_math::ray::Ray;
+use trace_math::vec3::{RGBColor, Vec3};
+
+use crate::film::Film;
+
+use super::{Integrator, T_MIN};
+
+/// Fraction of the cosine-weighted hemisphere around the first hit that is
+/// not blocked within `max_distance`.
+pub struct AmbientOcclusionIntegrator {
+    samples: u32,
+    max_distance: f64,
+}
+
+impl AmbientOcclusionIntegrator {
+    pub fn new(samples: u32, max_distance: f64) -> AmbientOcclusionIntegrator {
+        AmbientOcclusionIntegrator { samples: samples.max(1), max_distance }
+    }
+}
+
+impl Integrator for AmbientOcclusionIntegrator {
+    fn li(&self, ray: Ray, scene: &HittableList, _: &dyn CameraModel, _: &mut Film) -> RGBColor {
+        let hit = match scene.hit(ray, T_MIN, f64::INFINITY) {
+            Some(hit) => hit,
+            None => return RGBColor::ones(),
+        };
+
+        let uvw = Onb::from_w(hit.get_normal());
+        let unoccluded = (0..self.samples)
+            .filter(|_| {
+                let direction = uvw.local(Vec3::rand_cosine_direction());
+                let occlusion_ray = Ray::new(hit.get_point(), direction);
+                let distance = self.max_distance / direction.length();
+                scene.hit(occlusion_ray, T_MIN, distance).is_none()
+            })
+            .count();
+
+        RGBColor::ones() * (unoccluded as f64 / self.samples as f64)
+    }
+}
@@ -0,0 +1,492 @@
+use std::f64::consts::PI;
+
+use trace_camera::camera::CameraModel;
+use trace_math::hit_info::HitInfo;
+use trace_math::hittable::{Hittable, HittableList};
+use trace_math::onb::Onb;
+use trace_math::ray::Ray;
+use trace_math::vec3::{Point, RGBColor, Vec3};
+
+use crate::film::Film;
+
+use super::{background, camera_background, sample_light, Integrator, T_MIN};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VertexKind {
+    Camera,
+    Light,
+    Surface,
+}
+
+/// Vertex of a camera or light subpath. `pdf_fwd` is the area density of
+/// the vertex as generated by its subpath, `pdf_rev` the one it would have if
+/// the path had been generated from the other end.
+#[derive(Clone, Copy)]
+struct Vertex<'a> {
+    kind: VertexKind,
+    point: Point,
+    normal: Vec3,
+    hit: Option<HitInfo<'a>>,
+    ray_in: Ray,
+    beta: RGBColor,
+    delta: bool,
+    pdf_fwd: f64,
+    pdf_rev: f64,
+}
+
+impl<'a> Vertex<'a> {
+    fn camera(point: Point, forward: Vec3, beta: RGBColor) -> Vertex<'a> {
+        Vertex {
+            kind: VertexKind::Camera,
+            point,
+            normal: forward,
+            hit: None,
+            ray_in: Ray::new(point, forward),
+            beta,
+            delta: false,
+            pdf_fwd: 0.,
+            pdf_rev: 0.,
+        }
+    }
+
+    /// Vertex on an emitter; `hit` must be oriented with the outward normal.
+    fn light(hit: HitInfo<'a>, beta: RGBColor, pdf_fwd: f64) -> Vertex<'a> {
+        let point = hit.get_point();
+        let normal = hit.get_normal();
+        Vertex {
+            kind: VertexKind::Light,
+            point,
+            normal,
+            hit: Some(hit),
+            ray_in: Ray::new(point + normal, -normal),
+            beta,
+            delta: false,
+            pdf_fwd,
+            pdf_rev: 0.,
+        }
+    }
+
+    fn surface(hit: HitInfo<'a>, ray_in: Ray, beta: RGBColor) -> Vertex<'a> {
+        Vertex {
+            kind: VertexKind::Surface,
+            point: hit.get_point(),
+            normal: hit.get_normal(),
+            hit: Some(hit),
+            ray_in,
+            beta,
+            delta: false,
+            pdf_fwd: 0.,
+            pdf_rev: 0.,
+        }
+    }
+
+    fn is_connectible(&self) -> bool {
+        !self.delta
+    }
+
+    /// Normal on the emitting side of the surface.
+    fn outward_normal(&self) -> Vec3 {
+        match self.hit {
+            Some(hit) if !hit.get_front_face() => -self.normal,
+            _ => self.normal,
+        }
+    }
+
+    /// BSDF times the cosine towards `next`, for a surface vertex.
+    fn f(&self, next: &Vertex) -> RGBColor {
+        match self.hit {
+            Some(hit) => hit.get_material().eval(self.ray_in, hit, next.point - self.point),
+            None => RGBColor::zero(),
+        }
+    }
+
+    /// Radiance emitted from this vertex towards `target`.
+    fn le(&self, target: Point) -> RGBColor {
+        match self.hit {
+            Some(hit) => {
+                let ray = Ray::new(target, self.point - target);
+                hit.get_material().emitted(ray, hit.facing(ray))
+            },
+            None => RGBColor::zero(),
+        }
+    }
+
+    /// Turns a solid-angle density at this vertex into an area density at `next`.
+    fn convert_density(&self, pdf_dir: f64, next: &Vertex) -> f64 {
+        let w = next.point - self.point;
+        let dist_squared = w.length_squared();
+        if dist_squared == 0. {
+            return 0.;
+        }
+
+        let pdf = pdf_dir / dist_squared;
+        match next.is_on_surface() {
+            true => pdf * Vec3::dot(next.normal, w).abs() / dist_squared.sqrt(),
+            false => pdf,
+        }
+    }
+
+    /// Whether the vertex lies on a surface, as opposed to the camera or a
+    /// scattering event inside a medium.
+    fn is_on_surface(&self) -> bool {
+        match self.hit {
+            Some(hit) => hit.is_on_surface(),
+            None => false,
+        }
+    }
+
+    /// Area density at `next` of this vertex sampling it, having been reached from `prev`.
+    fn pdf(&self, camera: &dyn CameraModel, prev: Option<&Vertex>, next: &Vertex) -> f64 {
+        match self.kind {
+            VertexKind::Light => self.pdf_light(next),
+            VertexKind::Camera => {
+                let (_, pdf_dir) = camera.pdf_we(Ray::new(self.point, next.point - self.point));
+                self.convert_density(pdf_dir, next)
+            },
+            VertexKind::Surface => match (self.hit, prev) {
+                (Some(hit), Some(prev)) => {
+                    let ray = Ray::new(prev.point, self.point - prev.point);
+                    let hit = hit.facing(ray);
+                    let pdf_dir = hit.get_material().pdf(ray, hit, next.point - self.point);
+                    self.convert_density(pdf_dir, next)
+                },
+                _ => 0.,
+            },
+        }
+    }
+
+    /// Area density at `next` of this vertex emitting towards it, with the
+    /// cosine-weighted distribution used for light subpaths.
+    fn pdf_light(&self, next: &Vertex) -> f64 {
+        let w = (next.point - self.point).normalized();
+        let pdf_dir = Vec3::dot(self.outward_normal(), w).max(0.) / PI;
+        self.convert_density(pdf_dir, next)
+    }
+}
+
+/// Bidirectional path tracer: every camera subpath is connected to a light
+/// subpath at all vertex pairs, and the strategies are combined with the
+/// balance heuristic. Connections to the camera are splatted onto the film.
+pub struct BdptIntegrator {
+    max_depth: u32,
+}
+
+impl BdptIntegrator {
+    pub fn new(max_depth: u32) -> BdptIntegrator {
+        BdptIntegrator { max_depth }
+    }
+
+    /// Extends `path` by tracing `ray`, returning the radiance of the
+    /// background if a camera subpath escapes the scene.
+    fn random_walk<'a>(
+        &self,
+        scene: &'a HittableList,
+        mut ray: Ray,
+        mut beta: RGBColor,
+        mut pdf_dir: f64,
+        path: &mut Vec<Vertex<'a>>,
+        is_camera_path: bool,
+        ) -> RGBColor
+    {
+        // Camera subpaths get one more vertex, since the first one on a
+        // light subpath already sits on a surface
+        let max_vertices = match is_camera_path {
+            true => self.max_depth as usize + 2,
+            false => self.max_depth as usize + 1,
+        };
+
+        while path.len() < max_vertices {
+            let hit = match scene.hit(ray, T_MIN, f64::INFINITY) {
+                Some(hit) => hit,
+                None => {
+                    return match is_camera_path {
+                        true if path.len() == 1 => beta * camera_background(scene, ray),
+                        true => beta * background(scene, ray),
+                        false => RGBColor::zero(),
+                    };
+                },
+            };
+
+            let mut vertex = Vertex::surface(hit, ray, beta);
+            vertex.pdf_fwd = path[path.len() - 1].convert_density(pdf_dir, &vertex);
+            path.push(vertex);
+            if path.len() >= max_vertices {
+                break;
+            }
+
+            let material = hit.get_material();
+            let srec = match material.scatter(ray, hit) {
+                Some(srec) => srec,
+                None => break,
+            };
+
+            let n = path.len();
+            let pdf_rev_dir = match srec.is_specular {
+                true => {
+                    path[n - 1].delta = true;
+                    pdf_dir = 0.;
+                    0.
+                },
+                false => {
+                    let wi = srec.ray.direction;
+                    let rev_ray = Ray::new(hit.get_point() + wi, -wi);
+                    pdf_dir = srec.pdf;
+                    material.pdf(rev_ray, hit.facing(rev_ray), -ray.direction)
+                },
+            };
+            path[n - 2].pdf_rev = path[n - 1].convert_density(pdf_rev_dir, &path[n - 2]);
+
+            beta = beta * srec.attenuation;
+            ray = srec.ray;
+        }
+
+        RGBColor::zero()
+    }
+
+    fn generate_light_subpath<'a>(&self, scene: &'a HittableList) -> Vec<Vertex<'a>> {
+        let mut path = vec![];
+        let (hit, pdf_pos) = match scene.sample_light_surface() {
+            Some(sample) => sample,
+            None => return path,
+        };
+
+        let normal = hit.get_normal();
+        let direction = Onb::from_w(normal).local(Vec3::rand_cosine_direction());
+        let pdf_dir = Vec3::dot(normal, direction.normalized()) / PI;
+
+        let light = Vertex::light(hit, RGBColor::zero(), pdf_pos);
+        let le = light.le(hit.get_point() + direction);
+        path.push(Vertex { beta: le, ..light });
+        if pdf_dir <= 0. || le == RGBColor::zero() {
+            return path;
+        }
+
+        let beta = le * (Vec3::dot(normal, direction.normalized()) / (pdf_pos * pdf_dir));
+        let ray = Ray::new(hit.get_point(), direction);
+        self.random_walk(scene, ray, beta, pdf_dir, &mut path, false);
+
+        path
+    }
+
+    /// Contribution of the path made of the first `s` light and `t` camera
+    /// vertices, already weighted. For `t == 1` it is splatted on the film.
+    fn connect<'a>(
+        &self,
+        scene: &'a HittableList,
+        camera: &dyn CameraModel,
+        light_path: &[Vertex<'a>],
+        camera_path: &[Vertex<'a>],
+        (s, t): (usize, usize),
+        film: &mut Film,
+        ) -> RGBColor
+    {
+        let mut sampled = None;
+        let mut film_coords = None;
+
+        let l = if s == 0 {
+            let pt = &camera_path[t - 1];
+            match pt.kind {
+                VertexKind::Surface => pt.beta * pt.le(camera_path[t - 2].point),
+                _ => RGBColor::zero(),
+            }
+        } else if t == 1 {
+            let qs = &light_path[s - 1];
+            if !qs.is_connectible() {
+                return RGBColor::zero();
+            }
+            let imp = match camera.sample_importance(qs.point) {
+                Some(imp) if imp.pdf > 0. => imp,
+                _ => return RGBColor::zero(),
+            };
+            let cam_vertex = Vertex::camera(imp.lens_point, camera.forward(), RGBColor::ones() * (imp.we / imp.pdf));
+            let l = qs.beta * qs.f(&cam_vertex) * cam_vertex.beta;
+            if l == RGBColor::zero() {
+                return l;
+            }
+            let l = l * transmittance(scene, qs.point, cam_vertex.point);
+            sampled = Some(cam_vertex);
+            film_coords = Some((imp.s, imp.t));
+            l
+        } else if s == 1 {
+            let pt = &camera_path[t - 1];
+            if !pt.is_connectible() {
+                return RGBColor::zero();
+            }
+            let (hit, pdf_pos) = match scene.sample_light_surface() {
+                Some(sample) => sample,
+                None => return RGBColor::zero(),
+            };
+            let light = Vertex::light(hit, RGBColor::zero(), pdf_pos);
+            let to_light = light.point - pt.point;
+            let cos_light = Vec3::dot(light.normal, -to_light.normalized()).abs();
+            let le = light.le(pt.point);
+            let l = pt.beta * pt.f(&light) * le * (cos_light / (to_light.length_squared() * pdf_pos));
+            if l == RGBColor::zero() {
+                return l;
+            }
+            let l = l * transmittance(scene, pt.point, light.point);
+            sampled = Some(Vertex { beta: le / pdf_pos, ..light });
+            l
+        } else {
+            let qs = &light_path[s - 1];
+            let pt = &camera_path[t - 1];
+            if !qs.is_connectible() || !pt.is_connectible() {
+                return RGBColor::zero();
+            }
+            let dist_squared = (qs.point - pt.point).length_squared();
+            let l = qs.beta * qs.f(pt) * pt.f(qs) * pt.beta / dist_squared;
+            if l == RGBColor::zero() {
+                return l;
+            }
+            l * transmittance(scene, qs.point, pt.point)
+        };
+
+        if l == RGBColor::zero() {
+            return l;
+        }
+
+        let weighted = l * mis_weight(scene, camera, light_path, camera_path, sampled, s, t);
+        match film_coords {
+            Some((fs, ft)) => {
+                film.splat(fs, ft, weighted);
+                RGBColor::zero()
+            },
+            None => weighted,
+        }
+    }
+}
+
+impl Integrator for BdptIntegrator {
+    fn li(&self, ray: Ray, scene: &HittableList, camera: &dyn CameraModel, film: &mut Film) -> RGBColor {
+        // Cameras that light paths can't connect to act like a delta
+        // vertex, leaving out the strategies that would need them
+        let mut camera_vertex = Vertex::camera(ray.origin, camera.forward(), RGBColor::ones());
+        camera_vertex.delta = !camera.is_connectible();
+        let mut camera_path = vec![camera_vertex];
+        let (_, pdf_dir) = camera.pdf_we(ray);
+        let mut l = self.random_walk(scene, ray, RGBColor::ones(), pdf_dir, &mut camera_path, true);
+
+        let light_path = self.generate_light_subpath(scene);
+
+        for t in 1..=camera_path.len() {
+            for s in 0..=light_path.len() {
+                let depth = (s + t) as i64 - 2;
+                if (s == 1 && t == 1) || depth < 0 || depth > self.max_depth as i64 {
+                    continue;
+                }
+                l += self.connect(scene, camera, &light_path, &camera_path, (s, t), film);
+            }
+        }
+
+        // Analytic lights can't start a light subpath, so they are only
+        // reached by connecting camera vertices to them directly
+        for vertex in camera_path.iter().skip(1).take(self.max_depth as usize) {
+            l += analytic_direct(scene, vertex);
+        }
+
+        l
+    }
+}
+
+/// Light from one of the scene's analytic lights reflected at `vertex`.
+/// Samples that land on emissive shapes are dropped, as the other
+/// strategies already account for those.
+fn analytic_direct(scene: &HittableList, vertex: &Vertex) -> RGBColor {
+    let hit = match vertex.hit {
+        Some(hit) if vertex.is_connectible() => hit,
+        _ => return RGBColor::zero(),
+    };
+
+    match sample_light(scene, &hit) {
+        Some(ls) if !ls.mis => vertex.beta * hit.get_material().eval(vertex.ray_in, hit, ls.direction) * ls.radiance / ls.pdf,
+        _ => RGBColor::zero(),
+    }
+}
+
+/// Visibility between two vertices, attenuated by the media in between.
+fn transmittance(scene: &HittableList, from: Point, to: Point) -> RGBColor {
+    let w = to - from;
+    let dist = w.length();
+    let ray = Ray::new(from, w / dist);
+    match scene.hit_surface(ray, T_MIN, dist - T_MIN) {
+        Some(_) => RGBColor::zero(),
+        None => scene.transmittance(ray, T_MIN, dist - T_MIN),
+    }
+}
+
+/// Balance heuristic weight of the (s, t) strategy, computed by walking the
+/// path in both directions and comparing each vertex's reverse density with
+/// its forward one.
+fn mis_weight<'a>(
+    scene: &HittableList,
+    camera: &dyn CameraModel,
+    light_path: &[Vertex<'a>],
+    camera_path: &[Vertex<'a>],
+    sampled: Option<Vertex<'a>>,
+    s: usize,
+    t: usize,
+    ) -> f64
+{
+    if s + t == 2 {
+        return 1.;
+    }
+    // Emitters that aren't registered lights (e.g. glowing media) can only be
+    // reached by the camera subpath
+    if s == 0 && scene.light_surface_pdf(camera_path[t - 1].point) == 0. {
+        return 1.;
+    }
+
+    let mut light: Vec<Vertex> = light_path[..s].to_vec();
+    let mut cam: Vec<Vertex> = camera_path[..t].to_vec();
+    match sampled {
+        Some(v) if s == 1 => light[0] = v,
+        Some(v) if t == 1 => cam[0] = v,
+        _ => {},
+    }
+
+    // Connection endpoints are never delta, and their reverse densities
+    // (and those of their predecessors) depend on the other subpath
+    cam[t - 1].delta = false;
+    if s > 0 {
+        light[s - 1].delta = false;
+    }
+
+    cam[t - 1].pdf_rev = match s {
+        0 => scene.light_surface_pdf(cam[t - 1].point),
+        _ => light[s - 1].pdf(camera, s.checked_sub(2).map(|i| &light[i]), &cam[t - 1]),
+    };
+    if t > 1 {
+        cam[t - 2].pdf_rev = match s {
+            0 => cam[t - 1].pdf_light(&cam[t - 2]),
+            _ => cam[t - 1].pdf(camera, Some(&light[s - 1]), &cam[t - 2]),
+        };
+    }
+    if s > 0 {
+        light[s - 1].pdf_rev = cam[t - 1].pdf(camera, t.checked_sub(2).map(|i| &cam[i]), &light[s - 1]);
+    }
+    if s > 1 {
+        light[s - 2].pdf_rev = light[s - 1].pdf(camera, Some(&cam[t - 1]), &light[s - 2]);
+    }
+
+    let remap0 = |f: f64| if f != 0. { f } else { 1. };
+    let mut sum_ri = 0.;
+
+    let mut ri = 1.;
+    for i in (1..t).rev() {
+        ri *= remap0(cam[i].pdf_rev) / remap0(cam[i].pdf_fwd);
+        if !cam[i].delta && !cam[i - 1].delta {
+            sum_ri += ri;
+        }
+    }
+
+    let mut ri = 1.;
+    for i in (0..s).rev() {
+        ri *= remap0(light[i].pdf_rev) / remap0(light[i].pdf_fwd);
+        let delta_light_vertex = i > 0 && light[i - 1].delta;
+        if !light[i].delta && !delta_light_vertex {
+            sum_ri += ri;
+        }
+    }
+
+    1. / (1. + sum_ri)
+}
@@ -0,0 +1,70 @@
+use trace_camera::camera::CameraModel;
+use trace_math::hittable::{Hittable, HittableList};
+use trace_math::ray::Ray;
+use trace_math::vec3::RGBColor;
+
+use crate::film::Film;
+
+use super::{Integrator, T_MIN};
+
+/// Shading normal of the first hit, remapped from [-1, 1] to [0, 1].
+pub struct NormalIntegrator;
+
+impl Integrator for NormalIntegrator {
+    fn li(&self, ray: Ray, scene: &HittableList, _: &dyn CameraModel, _: &mut Film) -> RGBColor {
+        match scene.hit(ray, T_MIN, f64::INFINITY) {
+            Some(hit) => (hit.get_normal() + RGBColor::ones()) * 0.5,
+            None => RGBColor::zero(),
+        }
+    }
+}
+
+/// Distance to the first hit, white at the camera and black from `max_distance` on.
+pub struct DepthIntegrator {
+    max_distance: f64,
+}
+
+impl DepthIntegrator {
+    pub fn new(max_distance: f64) -> DepthIntegrator {
+        DepthIntegrator { max_distance }
+    }
+}
+
+impl Integrator for DepthIntegrator {
+    fn li(&self, ray: Ray, scene: &HittableList, _: &dyn CameraModel, _: &mut Film) -> RGBColor {
+        match scene.hit(ray, T_MIN, f64::INFINITY) {
+            Some(hit) => {
+                let distance = hit.get_t() * ray.direction.length();
+                RGBColor::ones() * (1. - distance / self.max_distance).clamp(0., 1.)
+            },
+            None => RGBColor::zero(),
+        }
+    }
+}
+
+/// Surface parametrization of the first hit, u in red and v in green.
+pub struct UvIntegrator;
+
+impl Integrator for UvIntegrator {
+    fn li(&self, ray: Ray, scene: &HittableList, _: &dyn CameraModel, _: &mut Film) -> RGBColor {
+        match scene.hit(ray, T_MIN, f64::INFINITY) {
+            Some(hit) => {
+                let (u, v) = hit.get_uv();
+                RGBColor::new(u, v, 0.)
+            },
+            None => RGBColor::zero(),
+        }
+    }
+}
+
+/// Unlit base color of the first hit.
+pub struct AlbedoIntegrator;
+
+impl Integrator for AlbedoIntegrator {
+    fn li(&self, ray: Ray, scene: &HittableList, _: &dyn CameraModel, _: &mut Film) -> RGBColor {
+        match scene.hit(ray, T_MIN, f64::INFINITY) {
+            Some(hit) => hit.get_material().albedo(hit),
+            None => RGBColor::zero(),
+        }
+    }
+}
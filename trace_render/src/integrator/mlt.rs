@@ -0,0 +1,171 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use trace_camera::camera::CameraModel;
+use trace_math::hittable::HittableList;
+use trace_math::ray::Ray;
+use trace_math::utils::rand_utils;
+use trace_math::utils::sampler::{self, PssSampler};
+use trace_math::vec3::RGBColor;
+
+use crate::film::Film;
+
+use super::path::{BounceLimits, PathIntegrator};
+use super::Integrator;
+
+/// A path as seen by Metropolis: where it lands on the film, the radiance it
+/// carries and its scalar contribution, which the chain samples proportionally to.
+#[derive(Debug, Clone, Copy)]
+struct PathSample {
+    s: f64,
+    t: f64,
+    radiance: RGBColor,
+    contribution: f64,
+}
+
+struct Chain {
+    sampler: Rc<RefCell<PssSampler>>,
+    current: PathSample,
+}
+
+struct MltState {
+    normalization: f64,
+    chains: Vec<Chain>,
+    next_chain: usize,
+}
+
+/// Primary sample space Metropolis light transport on top of the path
+/// tracer. A bootstrap pass estimates the image normalization and seeds the
+/// Markov chains, which then explore paths through small and large step
+/// mutations and splat every state onto the film.
+///
+/// Each call to `li` performs a single mutation of one of the chains and
+/// ignores the camera ray, so a full render makes as many mutations per
+/// pixel as it has samples per pixel.
+pub struct MltIntegrator {
+    path: PathIntegrator,
+    bootstrap_samples: u32,
+    chain_count: u32,
+    sigma: f64,
+    large_step_probability: f64,
+    state: RefCell<Option<MltState>>,
+}
+
+impl MltIntegrator {
+    pub fn new(
+        limits: BounceLimits,
+        bootstrap_samples: u32,
+        chain_count: u32,
+        sigma: f64,
+        large_step_probability: f64,
+        ) -> MltIntegrator
+    {
+        MltIntegrator {
+            path: PathIntegrator::new(limits),
+            bootstrap_samples: bootstrap_samples.max(1),
+            chain_count: chain_count.max(1),
+            sigma,
+            large_step_probability,
+            state: RefCell::new(None),
+        }
+    }
+
+    /// Runs the path tracer with every random decision (film position
+    /// included) taken from `pss`.
+    fn evaluate(&self, pss: &Rc<RefCell<PssSampler>>, scene: &HittableList, camera: &dyn CameraModel, film: &mut Film) -> PathSample {
+        sampler::with_sampler(pss.clone(), || {
+            let s = rand_utils::rand_f64();
+            let t = rand_utils::rand_f64();
+            let radiance = match camera.generate_ray(s, t) {
+                Some((ray, weight)) => self.path.li(ray, scene, camera, film) * weight,
+                None => RGBColor::zero(),
+            };
+            let contribution = match radiance.luminance() {
+                c if c.is_finite() => c.max(0.),
+                _ => 0.,
+            };
+            PathSample { s, t, radiance, contribution }
+        })
+    }
+
+    fn new_sampler(&self, seed: u64) -> Rc<RefCell<PssSampler>> {
+        Rc::new(RefCell::new(PssSampler::new(seed, self.sigma, self.large_step_probability)))
+    }
+
+    fn bootstrap(&self, scene: &HittableList, camera: &dyn CameraModel, film: &mut Film) -> MltState {
+        // Building the light sampler inside a seed's path would change it
+        scene.prepare();
+        let contributions: Vec<f64> = (0..self.bootstrap_samples as u64)
+            .map(|seed| self.evaluate(&self.new_sampler(seed), scene, camera, film).contribution)
+            .collect();
+        let total: f64 = contributions.iter().sum();
+        let normalization = total / self.bootstrap_samples as f64;
+
+        let mut chains = vec![];
+        if total > 0. {
+            let mut cdf = Vec::with_capacity(contributions.len());
+            let mut running = 0.;
+            for c in contributions.iter() {
+                running += c / total;
+                cdf.push(running);
+            }
+
+            // Seeds are picked proportionally to their contribution, and
+            // replaying them gives back the very same starting path
+            for _ in 0..self.chain_count {
+                let u = rand_utils::rand_f64();
+                let seed = cdf.partition_point(|&x| x < u).min(cdf.len() - 1);
+                let sampler = self.new_sampler(seed as u64);
+                let current = self.evaluate(&sampler, scene, camera, film);
+                chains.push(Chain { sampler, current });
+            }
+        }
+
+        MltState { normalization, chains, next_chain: 0 }
+    }
+}
+
+impl Integrator for MltIntegrator {
+    fn li(&self, _: Ray, scene: &HittableList, camera: &dyn CameraModel, film: &mut Film) -> RGBColor {
+        let mut state = self.state.borrow_mut();
+        if state.is_none() {
+            *state = Some(self.bootstrap(scene, camera, film));
+        }
+        let state = state.as_mut().unwrap();
+        if state.chains.is_empty() {
+            return RGBColor::zero();
+        }
+
+        let b = state.normalization;
+        let idx = state.next_chain;
+        state.next_chain = (state.next_chain + 1) % state.chains.len();
+        let chain = &mut state.chains[idx];
+
+        chain.sampler.borrow_mut().start_iteration();
+        let proposed = self.evaluate(&chain.sampler, scene, camera, film);
+        let current = chain.current;
+
+        let accept = match current.contribution > 0. {
+            true => (proposed.contribution / current.contribution).min(1.),
+            false => 1.,
+        };
+
+        // Splat both states weighted by their acceptance probability, which
+        // averages out the randomness of the accept/reject decision
+        if accept > 0. {
+            film.splat(proposed.s, proposed.t, proposed.radiance * (b * accept / proposed.contribution));
+        }
+        if current.contribution > 0. {
+            film.splat(current.s, current.t, current.radiance * (b * (1. - accept) / current.contribution));
+        }
+
+        if rand_utils::rand_f64() < accept {
+            chain.sampler.borrow_mut().accept();
+            chain.current = proposed;
+        } else {
+            chain.sampler.borrow_mut().reject();
+        }
+
+        RGBColor::zero()
+    }
+}
@@ -0,0 +1,121 @@
+use trace_camera::camera::CameraModel;
+use trace_math::hit_info::HitInfo;
+use trace_math::hittable::{Hittable, HittableList, SceneLight};
+use trace_math::light::{IncidentLight, Light};
+use trace_math::ray::Ray;
+use trace_math::vec3::{Point, RGBColor, Vec3};
+
+use crate::film::Film;
+
+pub mod path;
+pub mod whitted;
+pub mod ambient_occlusion;
+pub mod debug;
+pub mod bdpt;
+pub mod photon;
+pub mod mlt;
+
+pub use path::PathIntegrator;
+pub use whitted::WhittedIntegrator;
+pub use ambient_occlusion::AmbientOcclusionIntegrator;
+pub use debug::{NormalIntegrator, DepthIntegrator, UvIntegrator, AlbedoIntegrator};
+pub use bdpt::BdptIntegrator;
+pub use photon::PhotonIntegrator;
+pub use mlt::MltIntegrator;
+
+/// Offset used to keep secondary rays from hitting the surface they leave.
+pub const T_MIN: f64 = 0.001;
+
+/// Rendering algorithm: estimates the radiance arriving along a camera ray.
+/// Contributions that belong to other pixels (e.g. from light tracing) can
+/// be splatted directly onto `film`.
+pub trait Integrator {
+    /// Called before each pass of one sample per pixel, for integrators that
+    /// need to prepare data (e.g. photon maps) ahead of tracing camera rays.
+    fn begin_pass(&mut self, _pass: u32, _scene: &HittableList) {}
+
+    fn li(&self, ray: Ray, scene: &HittableList, camera: &dyn CameraModel, film: &mut Film) -> RGBColor;
+}
+
+/// Color seen by rays that escape the scene.
+pub fn background(scene: &HittableList, ray: Ray) -> RGBColor {
+    scene.environment().radiance(ray.direction)
+}
+
+/// Color seen by camera rays that escape the scene without bouncing.
+pub fn camera_background(scene: &HittableList, ray: Ray) -> RGBColor {
+    match scene.camera_background() {
+        Some(background) => background.radiance(ray.direction),
+        None => background(scene, ray),
+    }
+}
+
+/// Light sample taken from a shading point: direction towards the light,
+/// the radiance arriving from it (zero when occluded) and its pdf. `mis`
+/// tells whether BSDF sampling can find the light as well, in which case
+/// the sample has to be weighted against it.
+pub struct LightSample {
+    pub direction: Vec3,
+    pub radiance: RGBColor,
+    pub pdf: f64,
+    pub mis: bool,
+}
+
+/// Picks one of the scene's lights, as chosen by its light sampler, and
+/// traces a shadow ray towards it. Analytic lights can't be hit by rays, so
+/// their samples are used as is, without MIS.
+pub fn sample_light(scene: &HittableList, hit: &HitInfo) -> Option<LightSample> {
+    let point = hit.get_point();
+    let (light, pmf) = scene.sample_light(point)?;
+
+    match light {
+        SceneLight::Shape(idx, shape) => {
+            let direction = shape.random(point);
+            let pdf = pmf * shape.pdf_value(point, direction);
+            if pdf <= 0. {
+                return None;
+            }
+
+            // Other lights in the way block this one like any other surface
+            let shadow_ray = Ray::new(point, direction);
+            let radiance = match scene.hit_surface(shadow_ray, T_MIN, f64::INFINITY) {
+                Some(light_hit) if light_hit.get_light() == Some(idx) => {
+                    let transmittance = scene.transmittance(shadow_ray, T_MIN, light_hit.get_t());
+                    light_hit.get_material().emitted(shadow_ray, light_hit) * transmittance
+                },
+                _ => RGBColor::zero(),
+            };
+
+            Some(LightSample { direction, radiance, pdf, mis: true })
+        },
+        SceneLight::Analytic(light) => {
+            let incident = light.sample_li(point)?;
+            let pdf = pmf * incident.pdf;
+            if pdf <= 0. {
+                return None;
+            }
+
+            let radiance = incident.radiance * visibility(scene, point, &incident);
+            Some(LightSample { direction: incident.direction, radiance, pdf, mis: false })
+        },
+    }
+}
+
+/// Samples a direction on the scene's environment, weighted by its
+/// brightness, and traces a shadow ray along it.
+pub fn sample_environment(scene: &HittableList, hit: &HitInfo) -> Option<LightSample> {
+    let incident = scene.environment().sample_li(hit.get_point())?;
+    let radiance = incident.radiance * visibility(scene, hit.get_point(), &incident);
+    Some(LightSample { direction: incident.direction, radiance, pdf: incident.pdf, mis: true })
+}
+
+/// Fraction of `incident` light that reaches `point`: zero when a surface is
+/// in the way, the transmittance of the media in between otherwise.
+fn visibility(scene: &HittableList, point: Point, incident: &IncidentLight) -> RGBColor {
+    let shadow_ray = Ray::new(point, incident.direction);
+    let t_max = incident.distance - T_MIN;
+    match scene.hit_surface(shadow_ray, T_MIN, t_max) {
+        Some(_) => RGBColor::zero(),
+        None => scene.transmittance(shadow_ray, T_MIN, t_max),
+    }
+}
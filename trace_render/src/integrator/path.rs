@@ -0,0 +1,242 @@
+use trace_camera::camera::CameraModel;
+use trace_math::hittable::{Hittable, HittableList};
+use trace_math::material::Lobe;
+use trace_math::ray::Ray;
+use trace_math::spectrum::{self, SampledWavelengths, upsample};
+use trace_math::utils::math_utils::power_heuristic;
+use trace_math::utils::rand_utils;
+use trace_math::vec3::RGBColor;
+
+use crate::film::Film;
+
+use super::{
+    background, camera_background, sample_environment, sample_light, Integrator, T_MIN,
+};
+
+/// Number of bounces after which paths start being randomly terminated.
+const RR_START_DEPTH: u32 = 3;
+
+/// Maximum number of scattering events a path may go through, both in total
+/// and per kind of lobe. Paths are otherwise ended by Russian roulette, so
+/// these only bias the image when they are hit.
+#[derive(Debug, Clone, Copy)]
+pub struct BounceLimits {
+    pub total: u32,
+    pub diffuse: u32,
+    pub specular: u32,
+    pub transmission: u32,
+}
+
+impl BounceLimits {
+    pub fn new(total: u32, diffuse: u32, specular: u32, transmission: u32) -> BounceLimits {
+        BounceLimits { total, diffuse, specular, transmission }
+    }
+
+    pub fn uniform(max_depth: u32) -> BounceLimits {
+        BounceLimits::new(max_depth, max_depth, max_depth, max_depth)
+    }
+
+    pub fn unlimited() -> BounceLimits {
+        BounceLimits::uniform(u32::MAX)
+    }
+
+    fn for_lobe(&self, lobe: Lobe) -> u32 {
+        match lobe {
+            Lobe::Diffuse => self.diffuse,
+            Lobe::Specular => self.specular,
+            Lobe::Transmission => self.transmission,
+        }
+    }
+}
+
+impl Default for BounceLimits {
+    fn default() -> BounceLimits {
+        BounceLimits::unlimited()
+    }
+}
+
+/// Unidirectional path tracer with next-event estimation, MIS and Russian roulette.
+///
+/// In spectral mode every sample follows three wavelengths (hero wavelength
+/// sampling) instead of RGB, which lets dispersive materials split light.
+pub struct PathIntegrator {
+    limits: BounceLimits,
+    spectral: bool,
+}
+
+impl PathIntegrator {
+    pub fn new(limits: BounceLimits) -> PathIntegrator {
+        PathIntegrator { limits, spectral: false }
+    }
+
+    pub fn spectral(limits: BounceLimits) -> PathIntegrator {
+        PathIntegrator { limits, spectral: true }
+    }
+
+    /// Radiance along `camera_ray`, per wavelength in spectral mode.
+    fn trace(&self, camera_ray: Ray, scene: &HittableList) -> RGBColor {
+        let mut color = RGBColor::zero();
+        let mut throughput = RGBColor::ones();
+        let mut ray = camera_ray;
+
+        // Density with which the previous vertex sampled `ray`, or `None` for
+        // camera rays and specular bounces, whose emission can't be reached by
+        // light sampling and is therefore counted in full.
+        let mut bsdf_pdf: Option<f64> = None;
+
+        let mut hero_only = false;
+        let mut bounces = 0;
+        let (mut diffuse, mut specular, mut transmission) = (0, 0, 0);
+
+        loop {
+            let hit = match scene.hit(ray, T_MIN, f64::INFINITY) {
+                Some(hit) => hit,
+                None if bounces == 0 => {
+                    color += upsample(camera_background(scene, ray));
+                    break;
+                },
+                None => {
+                    // The environment could have been sampled as a light too
+                    let weight = match bsdf_pdf {
+                        Some(pdf) => power_heuristic(pdf, scene.environment().pdf(ray.direction)),
+                        None => 1.,
+                    };
+                    color += throughput * upsample(background(scene, ray)) * weight;
+                    break;
+                },
+            };
+
+            let material = hit.get_material();
+            let emitted = upsample(material.emitted(ray, hit));
+            if emitted != RGBColor::zero() {
+                let weight = match (bsdf_pdf, hit.get_light()) {
+                    (Some(pdf), Some(light)) => power_heuristic(pdf, scene.light_pdf_value(ray.origin, ray.direction, light)),
+                    _ => 1.,
+                };
+                color += throughput * emitted * weight;
+            }
+
+            let srec = match material.scatter(ray, hit) {
+                Some(srec) => srec,
+                None => break,
+            };
+
+            bounces += 1;
+            let lobe_count = match srec.lobe {
+                Lobe::Diffuse => &mut diffuse,
+                Lobe::Specular => &mut specular,
+                Lobe::Transmission => &mut transmission,
+            };
+            *lobe_count += 1;
+            if bounces > self.limits.total || *lobe_count > self.limits.for_lobe(srec.lobe) {
+                break;
+            }
+
+            // Next-event estimation: pick a direction towards the lights and
+            // weight it against the chance of BSDF sampling finding it too
+            if !srec.is_specular {
+                if let Some(ls) = sample_light(scene, &hit) {
+                    if ls.radiance != RGBColor::zero() {
+                        let f = material.eval(ray, hit, ls.direction);
+                        let weight = match ls.mis {
+                            true => power_heuristic(ls.pdf, material.pdf(ray, hit, ls.direction)),
+                            false => 1.,
+                        };
+                        color += throughput * f * upsample(ls.radiance) * (weight / ls.pdf);
+                    }
+                }
+
+                if let Some(ls) = sample_environment(scene, &hit) {
+                    if ls.radiance != RGBColor::zero() {
+                        let f = material.eval(ray, hit, ls.direction);
+                        let scatter_pdf = material.pdf(ray, hit, ls.direction);
+                        let weight = power_heuristic(ls.pdf, scatter_pdf);
+                        color += throughput * f * upsample(ls.radiance) * (weight / ls.pdf);
+                    }
+                }
+            }
+
+            throughput = throughput * srec.attenuation;
+            if !hero_only && spectrum::secondary_terminated() {
+                hero_only = true;
+                throughput = RGBColor::new(3. * throughput.x, 0., 0.);
+            }
+
+            // Russian roulette keeps the estimate unbiased by boosting the
+            // surviving paths by the inverse of their survival probability
+            if bounces >= RR_START_DEPTH {
+                let survival = throughput.max_component().min(0.95);
+                if survival <= 0. || rand_utils::rand_f64() > survival {
+                    break;
+                }
+                throughput /= survival;
+            }
+
+            bsdf_pdf = match srec.is_specular {
+                true => None,
+                false => Some(srec.pdf),
+            };
+            ray = srec.ray;
+        }
+
+        color
+    }
+}
+
+impl Integrator for PathIntegrator {
+    fn li(&self, camera_ray: Ray, scene: &HittableList, _: &dyn CameraModel, _: &mut Film) -> RGBColor {
+        match self.spectral {
+            true => {
+                let wavelengths = SampledWavelengths::sample(rand_utils::rand_f64());
+                let radiance = spectrum::with_wavelengths(wavelengths, || self.trace(camera_ray, scene));
+                wavelengths.to_rgb(radiance)
+            },
+            false => self.trace(camera_ray, scene),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use trace_camera::camera::Camera;
+    use trace_math::environment::Environment;
+    use trace_math::material::{Conductor, Dielectric, Material};
+    use trace_math::sphere::Sphere;
+    use trace_math::thin_film::ThinFilm;
+    use trace_math::vec3::{Point, Vec3};
+
+    /// Average color seen looking straight at a sphere of `material` under
+    /// a white sky.
+    fn render(integrator: &PathIntegrator, material: Box<dyn Material>) -> RGBColor {
+        let mut scene = HittableList::empty();
+        scene.add(Box::new(Sphere::new(Point::zero(), 1., material)));
+        scene.set_environment(Environment::Constant(RGBColor::ones()));
+
+        let lookfrom = Point::new(0., 0., 3.);
+        let camera = Camera::new(lookfrom, Point::zero(), Vec3::new(0., 1., 0.), 20., 1., 0., 3.);
+        let mut film = Film::new(1, 1);
+        let ray = Ray::new(lookfrom, Vec3::new(0., 0., -1.));
+
+        const SAMPLES: usize = 20_000;
+        let total = (0..SAMPLES).fold(RGBColor::zero(), |sum, _| sum + integrator.li(ray, &scene, &camera, &mut film));
+        total / SAMPLES as f64
+    }
+
+    fn assert_close(a: RGBColor, b: RGBColor) {
+        assert!((a - b).length() < 0.03, "{:?} vs {:?}", a, b);
+    }
+
+    #[test]
+    fn spectral_thin_films_match_rgb() {
+        let rgb = PathIntegrator::new(BounceLimits::uniform(8));
+        let spectral = PathIntegrator::spectral(BounceLimits::uniform(8));
+        let film = || ThinFilm::new(1.45, 350.);
+
+        let metal = || Box::new(Conductor::silver(0.).with_thin_film(film()));
+        assert_close(render(&rgb, metal()), render(&spectral, metal()));
+
+        let glass = || Box::new(Dielectric::tinted(1.5, RGBColor::new(0.9, 0.6, 0.3), 1.).with_thin_film(film()));
+        assert_close(render(&rgb, glass()), render(&spectral, glass()));
+    }
+}
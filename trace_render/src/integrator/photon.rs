@@ -0,0 +1,165 @@
+use std::f64::consts::PI;
+
+use trace_camera::camera::CameraModel;
+use trace_math::hit_info::HitInfo;
+use trace_math::hittable::{Hittable, HittableList};
+use trace_math::onb::Onb;
+use trace_math::ray::Ray;
+use trace_math::utils::rand_utils;
+use trace_math::vec3::{RGBColor, Vec3};
+
+use crate::film::Film;
+use crate::photon_map::{Photon, PhotonMap};
+
+use super::{background, camera_background, sample_light, Integrator, T_MIN};
+
+/// Maximum number of bounces for both photons and camera rays.
+const MAX_DEPTH: u32 = 32;
+
+/// Radius reduction parameter of progressive photon mapping, the fraction of
+/// photons kept from one pass to the next.
+const PPM_ALPHA: f64 = 2. / 3.;
+
+/// Photon mapping: photons shot from the lights are stored at diffuse
+/// surfaces after at least one bounce, and the indirect light (caustics
+/// included) at the first diffuse camera hit is a density estimate over
+/// them. Direct light is computed with light sampling as usual; light from
+/// the background only contributes directly. Camera rays entering media
+/// are traced through them until they reach a surface.
+///
+/// The progressive variant shoots a fresh photon map every pass and shrinks
+/// the gather radius, so that the average of all passes converges.
+pub struct PhotonIntegrator {
+    photons_per_pass: u32,
+    radius: f64,
+    progressive: bool,
+    photon_map: PhotonMap,
+}
+
+impl PhotonIntegrator {
+    pub fn new(photons_per_pass: u32, radius: f64, progressive: bool) -> PhotonIntegrator {
+        PhotonIntegrator { photons_per_pass, radius, progressive, photon_map: PhotonMap::empty() }
+    }
+
+    fn trace_photons(&self, scene: &HittableList) -> PhotonMap {
+        let mut photons = vec![];
+
+        for _ in 0..self.photons_per_pass {
+            let (hit, pdf_pos) = match scene.sample_light_surface() {
+                Some(sample) => sample,
+                None => break,
+            };
+
+            let normal = hit.get_normal();
+            let direction = Onb::from_w(normal).local(Vec3::rand_cosine_direction());
+            let ray = Ray::new(hit.get_point() + direction, -direction);
+            let le = hit.get_material().emitted(ray, hit.facing(ray));
+
+            // Cosine and direction pdf cancel out, leaving pi
+            let power = le * (PI / (pdf_pos * self.photons_per_pass as f64));
+            self.trace_photon(scene, Ray::new(hit.get_point(), direction), power, &mut photons);
+        }
+
+        PhotonMap::build(photons)
+    }
+
+    fn trace_photon(&self, scene: &HittableList, mut ray: Ray, mut power: RGBColor, photons: &mut Vec<Photon>) {
+        for depth in 0..MAX_DEPTH {
+            let hit = match scene.hit(ray, T_MIN, f64::INFINITY) {
+                Some(hit) => hit,
+                None => return,
+            };
+
+            let srec = match hit.get_material().scatter(ray, hit) {
+                Some(srec) => srec,
+                None => return,
+            };
+
+            // Direct light is handled by light sampling, and photons are
+            // only stored on surfaces
+            if !srec.is_specular && hit.is_on_surface() && depth > 0 {
+                photons.push(Photon { position: hit.get_point(), direction: ray.direction.normalized(), power });
+            }
+
+            let new_power = power * srec.attenuation;
+            let survival = (new_power.max_component() / power.max_component()).min(1.);
+            if survival <= 0. || rand_utils::rand_f64() > survival {
+                return;
+            }
+            power = new_power / survival;
+            ray = srec.ray;
+        }
+    }
+
+    /// Reflected radiance at `hit` estimated from the photons around it.
+    fn density_estimate(&self, ray: Ray, hit: HitInfo) -> RGBColor {
+        let mut flux = RGBColor::zero();
+        let normal = hit.get_normal();
+
+        self.photon_map.for_each_in_radius(hit.get_point(), self.radius, |photon| {
+            let wi = -photon.direction;
+            let cosine = Vec3::dot(normal, wi);
+            if cosine > 0. {
+                let f = hit.get_material().eval(ray, hit, wi) / cosine;
+                flux += f * photon.power;
+            }
+        });
+
+        flux / (PI * self.radius * self.radius)
+    }
+}
+
+impl Integrator for PhotonIntegrator {
+    fn begin_pass(&mut self, pass: u32, scene: &HittableList) {
+        if pass == 0 {
+            self.photon_map = self.trace_photons(scene);
+        } else if self.progressive {
+            let shrink = (pass as f64 + PPM_ALPHA) / (pass as f64 + 1.);
+            self.radius *= shrink.sqrt();
+            self.photon_map = self.trace_photons(scene);
+        }
+    }
+
+    fn li(&self, camera_ray: Ray, scene: &HittableList, _: &dyn CameraModel, _: &mut Film) -> RGBColor {
+        let mut color = RGBColor::zero();
+        let mut throughput = RGBColor::ones();
+        let mut ray = camera_ray;
+
+        // Follow specular bounces until the first diffuse surface
+        for depth in 0..MAX_DEPTH {
+            let hit = match scene.hit(ray, T_MIN, f64::INFINITY) {
+                Some(hit) => hit,
+                None if depth == 0 => return camera_background(scene, ray),
+                None => return color + throughput * background(scene, ray),
+            };
+
+            let material = hit.get_material();
+            color += throughput * material.emitted(ray, hit);
+
+            let srec = match material.scatter(ray, hit) {
+                Some(srec) => srec,
+                None => return color,
+            };
+
+            // Scattering inside media is followed like a specular bounce,
+            // as the photon map only holds surface photons
+            if srec.is_specular || !hit.is_on_surface() {
+                throughput = throughput * srec.attenuation;
+                ray = srec.ray;
+                continue;
+            }
+
+            if let Some(ls) = sample_light(scene, &hit) {
+                color += throughput * material.eval(ray, hit, ls.direction) * ls.radiance / ls.pdf;
+            }
+
+            if scene.hit(srec.ray, T_MIN, f64::INFINITY).is_none() {
+                color += throughput * srec.attenuation * background(scene, srec.ray);
+            }
+
+            return color + throughput * self.density_estimate(ray, hit);
+        }
+
+        color
+    }
+}
@@ -0,0 +1,60 @@
+use trace_camera::camera::CameraModel;
+use trace_math::hittable::{Hittable, HittableList};
+use trace_math::ray::Ray;
+use trace_math::vec3::RGBColor;
+
+use crate::film::Film;
+
+use super::{
+    background, camera_background, sample_environment, sample_light, Integrator, T_MIN,
+};
+
+/// Whitted-style ray tracer: direct lighting on non-specular surfaces and
+/// recursive reflection/refraction through specular ones. Indirect diffuse
+/// light is ignored.
+pub struct WhittedIntegrator {
+    max_depth: u32,
+}
+
+impl WhittedIntegrator {
+    pub fn new(max_depth: u32) -> WhittedIntegrator {
+        WhittedIntegrator { max_depth }
+    }
+
+    fn trace(&self, ray: Ray, scene: &HittableList, depth: u32) -> RGBColor {
+        if depth >= self.max_depth {
+            return RGBColor::zero();
+        }
+
+        let hit = match scene.hit(ray, T_MIN, f64::INFINITY) {
+            Some(hit) => hit,
+            None if depth == 0 => return camera_background(scene, ray),
+            None => return background(scene, ray),
+        };
+
+        let material = hit.get_material();
+        let emitted = material.emitted(ray, hit);
+
+        match material.scatter(ray, hit) {
+            Some(srec) if srec.is_specular => {
+                emitted + srec.attenuation * self.trace(srec.ray, scene, depth + 1)
+            },
+            Some(_) => {
+                let direct = [sample_light(scene, &hit), sample_environment(scene, &hit)]
+                    .into_iter()
+                    .flatten()
+                    .fold(RGBColor::zero(), |direct, ls| {
+                        direct + material.eval(ray, hit, ls.direction) * ls.radiance / ls.pdf
+                    });
+                emitted + direct
+            },
+            None => emitted,
+        }
+    }
+}
+
+impl Integrator for WhittedIntegrator {
+    fn li(&self, ray: Ray, scene: &HittableList, _: &dyn CameraModel, _: &mut Film) -> RGBColor {
+        self.trace(ray, scene, 0)
+    }
+}
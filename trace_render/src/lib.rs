@@ -0,0 +1,5 @@
+pub mod integrator;
+pub mod settings;
+pub mod film;
+pub mod renderer;
+pub mod photon_map;
@@ -0,0 +1,110 @@
+use trace_math::vec3::{Point, RGBColor, Vec3};
+
+/// Light particle deposited on a surface: where it landed, the direction it
+/// was travelling in and the flux it carries.
+#[derive(Debug, Clone, Copy)]
+pub struct Photon {
+    pub position: Point,
+    pub direction: Vec3,
+    pub power: RGBColor,
+}
+
+/// Photons stored as an implicit kd-tree: every sub-range of `photons` is
+/// split at its middle element, along the axis recorded for it in `axes`.
+pub struct PhotonMap {
+    photons: Vec<Photon>,
+    axes: Vec<usize>,
+}
+
+fn coord(p: Point, axis: usize) -> f64 {
+    match axis {
+        0 => p.x,
+        1 => p.y,
+        _ => p.z,
+    }
+}
+
+impl PhotonMap {
+    pub fn build(mut photons: Vec<Photon>) -> PhotonMap {
+        let mut axes = vec![0; photons.len()];
+        PhotonMap::build_range(&mut photons, &mut axes);
+        PhotonMap { photons, axes }
+    }
+
+    pub fn empty() -> PhotonMap {
+        PhotonMap { photons: vec![], axes: vec![] }
+    }
+
+    pub fn len(&self) -> usize {
+        self.photons.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.photons.is_empty()
+    }
+
+    fn build_range(photons: &mut [Photon], axes: &mut [usize]) {
+        if photons.len() <= 1 {
+            return;
+        }
+
+        // Split along the axis with the largest extent
+        let (mut min, mut max) = (photons[0].position, photons[0].position);
+        for photon in photons.iter() {
+            let p = photon.position;
+            min = Point::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+            max = Point::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+        }
+        let extent = max - min;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        };
+
+        let mid = photons.len() / 2;
+        photons.select_nth_unstable_by(mid, |a, b| {
+            coord(a.position, axis).total_cmp(&coord(b.position, axis))
+        });
+        axes[mid] = axis;
+
+        let (left, right) = photons.split_at_mut(mid);
+        let (left_axes, right_axes) = axes.split_at_mut(mid);
+        PhotonMap::build_range(left, left_axes);
+        PhotonMap::build_range(&mut right[1..], &mut right_axes[1..]);
+    }
+
+    /// Calls `f` on every photon closer than `radius` to `p`.
+    pub fn for_each_in_radius<F: FnMut(&Photon)>(&self, p: Point, radius: f64, mut f: F) {
+        self.query_range(0, self.photons.len(), p, radius * radius, &mut f);
+    }
+
+    fn query_range<F: FnMut(&Photon)>(&self, lo: usize, hi: usize, p: Point, radius_squared: f64, f: &mut F) {
+        if lo >= hi {
+            return;
+        }
+
+        let mid = lo + (hi - lo) / 2;
+        let photon = &self.photons[mid];
+        if (photon.position - p).length_squared() < radius_squared {
+            f(photon);
+        }
+        if hi - lo == 1 {
+            return;
+        }
+
+        let axis = self.axes[mid];
+        let diff = coord(p, axis) - coord(photon.position, axis);
+        let (near, far) = match diff < 0. {
+            true => ((lo, mid), (mid + 1, hi)),
+            false => ((mid + 1, hi), (lo, mid)),
+        };
+
+        self.query_range(near.0, near.1, p, radius_squared, f);
+        if diff * diff < radius_squared {
+            self.query_range(far.0, far.1, p, radius_squared, f);
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use trace_camera::camera::CameraModel;
+use trace_math::hittable::HittableList;
+use trace_math::utils::rand_utils;
+
+use crate::film::Film;
+use crate::integrator::Integrator;
+use crate::settings::RenderSettings;
+
+/// Takes one sample for every pixel of image row `y` (counted from the top)
+/// and adds it to `film`. A full pass over all rows makes up one sample per pixel.
+pub fn render_row(
+    y: u32,
+    scene: &HittableList,
+    camera: &dyn CameraModel,
+    integrator: &dyn Integrator,
+    settings: &RenderSettings,
+    film: &mut Film
+    )
+{
+    let (width, height) = (settings.width, settings.height);
+    let i = height - 1 - y;
+
+    for j in 0..width {
+        let (u, v) = (
+            (rand_utils::rand_f64() + j as f64) / width as f64,
+            (rand_utils::rand_f64() + i as f64) / height as f64
+        );
+        if let Some((r, weight)) = camera.generate_ray(u, v) {
+            let pixel_color = integrator.li(r, scene, camera, film) * weight;
+            film.add_sample(j, y, pixel_color);
+        }
+    }
+}
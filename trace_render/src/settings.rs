@@ -0,0 +1,132 @@
+use crate::integrator::{
+    Integrator, PathIntegrator, WhittedIntegrator, AmbientOcclusionIntegrator,
+    NormalIntegrator, DepthIntegrator, UvIntegrator, AlbedoIntegrator, BdptIntegrator,
+    PhotonIntegrator, MltIntegrator,
+};
+use crate::integrator::path::BounceLimits;
+
+use trace_math::aabb::Aabb;
+use trace_math::medium::ConstantMedium;
+use trace_math::vec3::{Point, RGBColor};
+
+/// Rendering algorithm to use, along with its parameters.
+#[derive(Debug, Clone, Copy)]
+pub enum IntegratorKind {
+    Path(BounceLimits),
+    SpectralPath(BounceLimits),
+    Whitted { max_depth: u32 },
+    Bdpt { max_depth: u32 },
+    PhotonMapping { photons_per_pass: u32, radius: f64, progressive: bool },
+    Metropolis { limits: BounceLimits, bootstrap_samples: u32, chains: u32, sigma: f64, large_step_probability: f64 },
+    AmbientOcclusion { samples: u32, max_distance: f64 },
+    Normals,
+    Depth { max_distance: f64 },
+    Uv,
+    Albedo,
+}
+
+impl IntegratorKind {
+    /// Integrator with default parameters from its short name.
+    pub fn from_name(name: &str) -> Option<IntegratorKind> {
+        match name {
+            "path" => Some(IntegratorKind::Path(BounceLimits::default())),
+            "spectral" => Some(IntegratorKind::SpectralPath(BounceLimits::default())),
+            "whitted" => Some(IntegratorKind::Whitted { max_depth: 16 }),
+            "bdpt" => Some(IntegratorKind::Bdpt { max_depth: 8 }),
+            "photon" => Some(IntegratorKind::PhotonMapping { photons_per_pass: 200_000, radius: 0.05, progressive: false }),
+            "ppm" => Some(IntegratorKind::PhotonMapping { photons_per_pass: 50_000, radius: 0.1, progressive: true }),
+            "mlt" => Some(IntegratorKind::Metropolis {
+                limits: BounceLimits::default(),
+                bootstrap_samples: 100_000,
+                chains: 1000,
+                sigma: 0.01,
+                large_step_probability: 0.3,
+            }),
+            "ao" => Some(IntegratorKind::AmbientOcclusion { samples: 8, max_distance: 1.0 }),
+            "normals" => Some(IntegratorKind::Normals),
+            "depth" => Some(IntegratorKind::Depth { max_distance: 10.0 }),
+            "uv" => Some(IntegratorKind::Uv),
+            "albedo" => Some(IntegratorKind::Albedo),
+            _ => None,
+        }
+    }
+
+    pub fn build(&self) -> Box<dyn Integrator> {
+        match *self {
+            IntegratorKind::Path(limits) => Box::new(PathIntegrator::new(limits)),
+            IntegratorKind::SpectralPath(limits) => Box::new(PathIntegrator::spectral(limits)),
+            IntegratorKind::Whitted { max_depth } => Box::new(WhittedIntegrator::new(max_depth)),
+            IntegratorKind::Bdpt { max_depth } => Box::new(BdptIntegrator::new(max_depth)),
+            IntegratorKind::PhotonMapping { photons_per_pass, radius, progressive } => {
+                Box::new(PhotonIntegrator::new(photons_per_pass, radius, progressive))
+            },
+            IntegratorKind::AmbientOcclusion { samples, max_distance } => {
+                Box::new(AmbientOcclusionIntegrator::new(samples, max_distance))
+            },
+            IntegratorKind::Metropolis { limits, bootstrap_samples, chains, sigma, large_step_probability } => {
+                Box::new(MltIntegrator::new(limits, bootstrap_samples, chains, sigma, large_step_probability))
+            },
+            IntegratorKind::Normals => Box::new(NormalIntegrator),
+            IntegratorKind::Depth { max_distance } => Box::new(DepthIntegrator::new(max_distance)),
+            IntegratorKind::Uv => Box::new(UvIntegrator),
+            IntegratorKind::Albedo => Box::new(AlbedoIntegrator),
+        }
+    }
+}
+
+impl Default for IntegratorKind {
+    fn default() -> IntegratorKind {
+        IntegratorKind::Path(BounceLimits::default())
+    }
+}
+
+/// Scene-wide fog: a white, isotropic medium of constant `density` filling
+/// a cube of `half_size` around the origin.
+#[derive(Debug, Clone, Copy)]
+pub struct FogSettings {
+    pub density: f64,
+    pub half_size: f64,
+}
+
+impl FogSettings {
+    /// Fog from a `density[,half_size]` spec, the cube defaulting to 50 units.
+    pub fn from_spec(spec: &str) -> Option<FogSettings> {
+        let values = spec.split(',')
+            .map(|v| v.trim().parse::<f64>().ok())
+            .collect::<Option<Vec<f64>>>()?;
+        let (density, half_size) = match values[..] {
+            [density] => (density, 50.),
+            [density, half_size] => (density, half_size),
+            _ => return None,
+        };
+
+        match density.is_finite() && density >= 0. && half_size.is_finite() && half_size > 0. {
+            true => Some(FogSettings { density, half_size }),
+            false => None,
+        }
+    }
+
+    pub fn build(&self) -> ConstantMedium {
+        let corner = Point::new(self.half_size, self.half_size, self.half_size);
+        ConstantMedium::global(self.density, RGBColor::ones(), Aabb::new(-corner, corner))
+    }
+}
+
+pub struct RenderSettings {
+    pub width: u32,
+    pub height: u32,
+    pub samples_per_pixel: u32,
+    pub integrator: IntegratorKind,
+    pub fog: Option<FogSettings>,
+}
+
+impl RenderSettings {
+    pub fn new(width: u32, height: u32, samples_per_pixel: u32, integrator: IntegratorKind) -> RenderSettings {
+        RenderSettings { width, height, samples_per_pixel, integrator, fog: None }
+    }
+
+    pub fn with_fog(mut self, fog: FogSettings) -> RenderSettings {
+        self.fog = Some(fog);
+        self
+    }
+}
@@ -0,0 +1,103 @@
+use std::io::{Error, ErrorKind, Result};
+
+use trace_math::environment::Environment;
+use trace_math::environment_map::EnvironmentMap;
+use trace_math::sky::{PhysicalSky, SunPosition};
+use trace_math::vec3::RGBColor;
+
+use crate::hdr::load_hdr_image;
+
+/// Scale bringing the physical sky down to the brightness of the other environments.
+const SKY_INTENSITY: f64 = 0.02;
+
+/// Builds an environment from its description on the command line (the
+/// renderer's fourth argument), comma separated values being optional
+/// unless stated otherwise:
+///
+/// - `gradient`
+/// - `constant:r,g,b` (all three required)
+/// - `map:path[,intensity,rotation]`, for a `.hdr` or `.pfm` image
+/// - `sky[:elevation,azimuth,turbidity]`
+pub fn parse_environment(spec: &str) -> Result<Environment> {
+    let invalid = |msg: &str| Error::new(ErrorKind::InvalidInput, format!("environment '{}': {}", spec, msg));
+    let (kind, args) = spec.split_once(':').unwrap_or((spec, ""));
+    let numbers = |args: &str| -> Result<Vec<f64>> {
+        args.split(',')
+            .filter(|a| !a.is_empty())
+            .map(|a| a.trim().parse::<f64>().map_err(|_| invalid("invalid number")))
+            .collect()
+    };
+
+    match kind {
+        "gradient" => Ok(Environment::gradient()),
+        "constant" => match numbers(args)?.as_slice() {
+            &[r, g, b] => Ok(Environment::Constant(RGBColor::new(r, g, b))),
+            _ => Err(invalid("expected r,g,b")),
+        },
+        "map" => {
+            let mut parts = args.split(',');
+            let path = parts.next().filter(|p| !p.is_empty()).ok_or_else(|| invalid("missing image path"))?;
+            let values = numbers(&parts.collect::<Vec<_>>().join(","))?;
+            let intensity = values.first().copied().unwrap_or(1.);
+            let rotation = values.get(1).copied().unwrap_or(0.);
+            Ok(Environment::Map(EnvironmentMap::new(load_hdr_image(path)?, intensity, rotation)))
+        },
+        "sky" => {
+            let values = numbers(args)?;
+            let elevation = values.first().copied().unwrap_or(45.);
+            let azimuth = values.get(1).copied().unwrap_or(180.);
+            let turbidity = values.get(2).copied().unwrap_or(3.);
+            let sun = SunPosition::new(elevation, azimuth);
+            Ok(Environment::Sky(PhysicalSky::new(sun, turbidity, RGBColor::ones() * 0.3, SKY_INTENSITY)))
+        },
+        _ => Err(invalid("unknown environment")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_each_kind() {
+        assert!(matches!(parse_environment("gradient"), Ok(Environment::Gradient { .. })));
+        assert!(matches!(parse_environment("sky"), Ok(Environment::Sky(_))));
+        assert!(matches!(parse_environment("sky:30,90"), Ok(Environment::Sky(_))));
+        assert!(matches!(parse_environment("sky:30, 90, 5"), Ok(Environment::Sky(_))));
+
+        match parse_environment("constant:0.5,1,2") {
+            Ok(Environment::Constant(color)) => assert_eq!(color, RGBColor::new(0.5, 1., 2.)),
+            _ => panic!("expected a constant environment"),
+        }
+    }
+
+    #[test]
+    fn loads_maps() {
+        let path = std::env::temp_dir().join(format!("trace_environment_{}.pfm", std::process::id()));
+        let mut bytes = b"PF\n1 1\n-1.0\n".to_vec();
+        for value in [0.25f32, 0.5, 2.] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        std::fs::write(&path, bytes).unwrap();
+
+        let spec = path.to_str().unwrap();
+        assert!(matches!(parse_environment(&format!("map:{}", spec)), Ok(Environment::Map(_))));
+        assert!(matches!(parse_environment(&format!("map:{},2,90", spec)), Ok(Environment::Map(_))));
+        assert!(parse_environment(&format!("map:{},bright", spec)).is_err());
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(parse_environment("map:").is_err());
+        assert!(parse_environment(&format!("map:{}", spec)).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_specs() {
+        assert!(parse_environment("").is_err());
+        assert!(parse_environment("starry").is_err());
+        assert!(parse_environment("constant").is_err());
+        assert!(parse_environment("constant:1,1").is_err());
+        assert!(parse_environment("constant:1,1,1,1").is_err());
+        assert!(parse_environment("constant:1,x,1").is_err());
+        assert!(parse_environment("sky:high").is_err());
+    }
+}
@@ -0,0 +1,307 @@
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+use trace_math::image::Image;
+use trace_math::vec3::RGBColor;
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Loads an HDR image, picking the format from the file extension:
+/// `.hdr` for Radiance RGBE, `.pfm` for portable float maps.
+pub fn load_hdr_image<P: AsRef<Path>>(path: P) -> Result<Image> {
+    let extension = path.as_ref()
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+
+    match extension.as_deref() {
+        Some("hdr") => parse_hdr(&fs::read(path)?),
+        Some("pfm") => parse_pfm(&fs::read(path)?),
+        _ => Err(Error::new(ErrorKind::InvalidInput, "unsupported HDR image format")),
+    }
+}
+
+/// Loads a Radiance RGBE (`.hdr`) image, flat or run-length encoded.
+/// Only the standard `-Y height +X width` orientation is supported.
+pub fn load_hdr<P: AsRef<Path>>(path: P) -> Result<Image> {
+    parse_hdr(&fs::read(path)?)
+}
+
+pub fn parse_hdr(bytes: &[u8]) -> Result<Image> {
+    let mut pos = 0;
+    let next_line = |pos: &mut usize| -> Result<String> {
+        let start = *pos;
+        let end = bytes[start..].iter().position(|&b| b == b'\n')
+            .ok_or_else(|| invalid("HDR header is truncated"))?;
+        *pos = start + end + 1;
+        Ok(String::from_utf8_lossy(&bytes[start..start + end]).trim().to_string())
+    };
+
+    let magic = next_line(&mut pos)?;
+    if !magic.starts_with("#?") {
+        return Err(invalid("not a Radiance HDR file"));
+    }
+
+    // Header variables end at the first empty line
+    loop {
+        let line = next_line(&mut pos)?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(invalid("unsupported HDR pixel format"));
+            }
+        }
+    }
+
+    let resolution = next_line(&mut pos)?;
+    let tokens: Vec<&str> = resolution.split_whitespace().collect();
+    let (height, width) = match tokens.as_slice() {
+        ["-Y", h, "+X", w] => (
+            h.parse::<usize>().map_err(|_| invalid("invalid HDR height"))?,
+            w.parse::<usize>().map_err(|_| invalid("invalid HDR width"))?,
+        ),
+        _ => return Err(invalid("unsupported HDR orientation")),
+    };
+    if width == 0 || height == 0 {
+        return Err(invalid("HDR image is empty"));
+    }
+
+    // Even run-length encoded, a scanline can't pack more than 127 pixels in
+    // 8 bytes, which bounds what a header may claim before anything is allocated
+    let pixel_count = width.checked_mul(height).ok_or_else(|| invalid("HDR image is too large"))?;
+    if pixel_count / 16 > bytes.len() - pos {
+        return Err(invalid("HDR pixel data is truncated"));
+    }
+
+    let mut pixels = Vec::with_capacity(pixel_count);
+    let mut scanline = vec![[0u8; 4]; width];
+    for _ in 0..height {
+        pos = read_scanline(bytes, pos, &mut scanline)?;
+        pixels.extend(scanline.iter().map(|&rgbe| rgbe_to_rgb(rgbe)));
+    }
+
+    Ok(Image::new(width, height, pixels))
+}
+
+/// Decodes one scanline starting at `pos`, returning the position after it.
+fn read_scanline(bytes: &[u8], mut pos: usize, scanline: &mut [[u8; 4]]) -> Result<usize> {
+    let width = scanline.len();
+    let truncated = || invalid("HDR pixel data is truncated");
+    let header = bytes.get(pos..pos + 4).ok_or_else(truncated)?;
+
+    // New-style RLE scanlines start with 2, 2 and the width, then store each
+    // channel separately as runs and literal spans
+    let is_rle = (8..0x8000).contains(&width) && header[0] == 2 && header[1] == 2 && header[2] & 0x80 == 0;
+    if !is_rle {
+        for pixel in scanline.iter_mut() {
+            let rgbe = bytes.get(pos..pos + 4).ok_or_else(truncated)?;
+            pixel.copy_from_slice(rgbe);
+            pos += 4;
+        }
+        return Ok(pos);
+    }
+
+    if ((header[2] as usize) << 8 | header[3] as usize) != width {
+        return Err(invalid("HDR scanline width doesn't match the image"));
+    }
+    pos += 4;
+
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = *bytes.get(pos).ok_or_else(truncated)? as usize;
+            pos += 1;
+            if count > 128 {
+                let run = count - 128;
+                let value = *bytes.get(pos).ok_or_else(truncated)?;
+                pos += 1;
+                if run > width - x {
+                    return Err(invalid("HDR run overflows its scanline"));
+                }
+                for pixel in scanline[x..x + run].iter_mut() {
+                    pixel[channel] = value;
+                }
+                x += run;
+            } else {
+                if count == 0 || count > width - x {
+                    return Err(invalid("invalid HDR literal span"));
+                }
+                let values = bytes.get(pos..pos + count).ok_or_else(truncated)?;
+                for (pixel, &value) in scanline[x..x + count].iter_mut().zip(values) {
+                    pixel[channel] = value;
+                }
+                pos += count;
+                x += count;
+            }
+        }
+    }
+
+    Ok(pos)
+}
+
+/// Shared-exponent RGBE pixel to linear RGB.
+fn rgbe_to_rgb([r, g, b, e]: [u8; 4]) -> RGBColor {
+    if e == 0 {
+        return RGBColor::zero();
+    }
+
+    let scale = 2f64.powi(e as i32 - (128 + 8));
+    RGBColor::new(r as f64 * scale, g as f64 * scale, b as f64 * scale)
+}
+
+/// Loads a portable float map (`.pfm`), in color (`PF`) or greyscale (`Pf`).
+pub fn load_pfm<P: AsRef<Path>>(path: P) -> Result<Image> {
+    parse_pfm(&fs::read(path)?)
+}
+
+pub fn parse_pfm(bytes: &[u8]) -> Result<Image> {
+    // The header is four whitespace separated tokens, followed by a single
+    // whitespace character before the binary data
+    let mut pos = 0;
+    let mut tokens: Vec<String> = vec![];
+    while tokens.len() < 4 {
+        while bytes.get(pos).is_some_and(|b| b.is_ascii_whitespace()) {
+            pos += 1;
+        }
+        let start = pos;
+        while bytes.get(pos).is_some_and(|b| !b.is_ascii_whitespace()) {
+            pos += 1;
+        }
+        if start == pos {
+            return Err(invalid("PFM header is truncated"));
+        }
+        tokens.push(String::from_utf8_lossy(&bytes[start..pos]).to_string());
+    }
+    pos += 1;
+
+    let channels = match tokens[0].as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err(invalid("not a PFM file")),
+    };
+    let width = tokens[1].parse::<usize>().map_err(|_| invalid("invalid PFM width"))?;
+    let height = tokens[2].parse::<usize>().map_err(|_| invalid("invalid PFM height"))?;
+    let scale = tokens[3].parse::<f64>().map_err(|_| invalid("invalid PFM scale"))?;
+    if width == 0 || height == 0 || scale == 0. {
+        return Err(invalid("PFM image is empty"));
+    }
+
+    // A negative scale marks little-endian data
+    let little_endian = scale < 0.;
+    let truncated = || invalid("PFM pixel data is truncated");
+    let end = width.checked_mul(height)
+        .and_then(|n| n.checked_mul(channels))
+        .and_then(|n| n.checked_mul(4))
+        .and_then(|size| pos.checked_add(size))
+        .ok_or_else(truncated)?;
+    let body = bytes.get(pos..end).ok_or_else(truncated)?;
+    let values: Vec<f64> = body.chunks_exact(4)
+        .map(|c| {
+            let raw = [c[0], c[1], c[2], c[3]];
+            match little_endian {
+                true => f32::from_le_bytes(raw) as f64,
+                false => f32::from_be_bytes(raw) as f64,
+            }
+        })
+        .collect();
+
+    // Rows are stored from the bottom of the image up
+    let mut pixels = Vec::with_capacity(width * height);
+    for row in values.chunks_exact(width * channels).rev() {
+        pixels.extend(row.chunks_exact(channels).map(|p| match channels {
+            3 => RGBColor::new(p[0], p[1], p[2]),
+            _ => RGBColor::ones() * p[0],
+        }));
+    }
+
+    Ok(Image::new(width, height, pixels))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hdr_bytes(width: usize, height: usize, data: &[u8]) -> Vec<u8> {
+        let mut bytes = format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", height, width).into_bytes();
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    /// Run-length encoded 8 pixel scanline: red and exponent as runs, green
+    /// as a literal span ramping up, no blue.
+    fn rle_scanline() -> Vec<u8> {
+        let mut data = vec![2, 2, 0, 8];
+        data.extend([128 + 8, 128]);
+        data.push(8);
+        data.extend((0..8).map(|x| x * 16));
+        data.extend([128 + 8, 0]);
+        data.extend([128 + 8, 129]);
+        data
+    }
+
+    fn pfm_bytes(header: &str, values: &[f32], little_endian: bool) -> Vec<u8> {
+        let mut bytes = header.as_bytes().to_vec();
+        bytes.extend(values.iter().flat_map(|v| match little_endian {
+            true => v.to_le_bytes(),
+            false => v.to_be_bytes(),
+        }));
+        bytes
+    }
+
+    #[test]
+    fn parses_flat_hdr() {
+        let image = parse_hdr(&hdr_bytes(2, 1, &[128, 64, 0, 129, 0, 0, 0, 0])).unwrap();
+        assert_eq!(image.pixel(0, 0), RGBColor::new(1., 0.5, 0.));
+        assert_eq!(image.pixel(1, 0), RGBColor::zero());
+    }
+
+    #[test]
+    fn parses_run_length_encoded_hdr() {
+        let image = parse_hdr(&hdr_bytes(8, 1, &rle_scanline())).unwrap();
+        for x in 0..8 {
+            assert_eq!(image.pixel(x, 0), RGBColor::new(1., x as f64 * 0.125, 0.));
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_hdr() {
+        let rle = rle_scanline();
+        assert!(parse_hdr(&hdr_bytes(8, 1, &rle[..rle.len() - 1])).is_err());
+        assert!(parse_hdr(&hdr_bytes(8, 2, &rle)).is_err());
+        assert!(parse_hdr(&hdr_bytes(2, 1, &[128, 64, 0, 129])).is_err());
+        assert!(parse_hdr(b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n").is_err());
+    }
+
+    #[test]
+    fn rejects_hdr_resolutions_beyond_the_data() {
+        assert!(parse_hdr(&hdr_bytes(100_000, 100_000, &[0; 64])).is_err());
+        assert!(parse_hdr(&hdr_bytes(usize::MAX, 2, &[0; 64])).is_err());
+    }
+
+    #[test]
+    fn parses_pfm() {
+        let image = parse_pfm(&pfm_bytes("PF\n1 1\n-1.0\n", &[0.25, 0.5, 2.], true)).unwrap();
+        assert_eq!(image.pixel(0, 0), RGBColor::new(0.25, 0.5, 2.));
+
+        // Rows go from the bottom up
+        let image = parse_pfm(&pfm_bytes("Pf\n1 2\n1.0\n", &[1., 3.], false)).unwrap();
+        assert_eq!(image.pixel(0, 0), RGBColor::ones() * 3.);
+        assert_eq!(image.pixel(0, 1), RGBColor::ones());
+    }
+
+    #[test]
+    fn rejects_truncated_and_oversized_pfm() {
+        assert!(parse_pfm(&pfm_bytes("PF\n1 1\n-1.0\n", &[0.25, 0.5], true)).is_err());
+        assert!(parse_pfm(b"PF\n1 1\n").is_err());
+
+        let header = format!("PF\n{} 3\n-1.0\n", usize::MAX / 3 + 1);
+        assert!(parse_pfm(&pfm_bytes(&header, &[0.; 3], true)).is_err());
+        let header = format!("Pf\n{} 1\n-1.0\n", usize::MAX / 4 + 1);
+        assert!(parse_pfm(&pfm_bytes(&header, &[0.; 3], true)).is_err());
+    }
+}
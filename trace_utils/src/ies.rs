@@ -0,0 +1,135 @@
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+use trace_math::ies::IesProfile;
+
+/// Largest number of entries accepted in a table, well above what
+/// measured fixtures use (at most a few thousand angles).
+const MAX_ENTRIES: usize = 100_000;
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Size of a table, which has to be a whole, non-negative number.
+fn entry_count(value: f64) -> Result<usize> {
+    match value >= 0. && value.fract() == 0. && value <= MAX_ENTRIES as f64 {
+        true => Ok(value as usize),
+        false => Err(invalid("invalid IES table size")),
+    }
+}
+
+/// Loads an IES LM-63 photometric file (1986 to 2019 revisions). Only type C
+/// photometry, which is what architectural fixtures use, is supported.
+pub fn load_ies<P: AsRef<Path>>(path: P) -> Result<IesProfile> {
+    parse_ies(&fs::read_to_string(path)?)
+}
+
+pub fn parse_ies(text: &str) -> Result<IesProfile> {
+    // Keywords come first, up to the line giving the lamp tilt data
+    let mut lines = text.lines();
+    let tilt = loop {
+        let line = lines.next().ok_or_else(|| invalid("IES file has no TILT line"))?;
+        if let Some(tilt) = line.trim().strip_prefix("TILT=") {
+            break tilt.trim().to_string();
+        }
+    };
+
+    // The rest is numbers, split across lines in any way
+    let rest: Vec<&str> = lines.collect();
+    let mut numbers = rest.iter().flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ','))
+        .filter(|token| !token.is_empty())
+        .map(|token| token.parse::<f64>().map_err(|_| invalid("invalid number in IES data")));
+    let mut next = || numbers.next().unwrap_or_else(|| Err(invalid("IES data is truncated")));
+
+    match tilt.as_str() {
+        "NONE" => {},
+        // Tilt only matters for lamps mounted at an angle; skip its table
+        "INCLUDE" => {
+            let _geometry = next()?;
+            let count = entry_count(next()?)?;
+            for _ in 0..2 * count {
+                next()?;
+            }
+        },
+        _ => return Err(invalid("IES tilt files are not supported")),
+    }
+
+    let _lamps = next()?;
+    let _lumens_per_lamp = next()?;
+    let multiplier = next()?;
+    let vertical_count = entry_count(next()?)?;
+    let horizontal_count = entry_count(next()?)?;
+    let photometric_type = next()?;
+    let _units = next()?;
+    let (_width, _length, _height) = (next()?, next()?, next()?);
+    let ballast_factor = next()?;
+    let _ballast_lamp_factor = next()?;
+    let _input_watts = next()?;
+
+    if photometric_type != 1. {
+        return Err(invalid("only type C IES photometry is supported"));
+    }
+    if vertical_count == 0 || horizontal_count == 0 {
+        return Err(invalid("IES file has no angles"));
+    }
+
+    let mut read = |count: usize| (0..count).map(|_| next()).collect::<Result<Vec<f64>>>();
+    let vertical = read(vertical_count)?;
+    let horizontal = read(horizontal_count)?;
+    let scale = multiplier * ballast_factor;
+    let candela = (0..horizontal_count)
+        .map(|_| Ok(read(vertical_count)?.into_iter().map(|cd| (cd * scale).max(0.)).collect()))
+        .collect::<Result<Vec<Vec<f64>>>>()?;
+
+    let is_sorted = |angles: &[f64]| angles.windows(2).all(|w| w[0] < w[1]);
+    if !is_sorted(&vertical) || !is_sorted(&horizontal) {
+        return Err(invalid("IES angles are not increasing"));
+    }
+
+    Ok(IesProfile::new(vertical, horizontal, candela))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Type C file with a lamp of 1000 lm and unit multipliers.
+    fn ies_text(tilt: &str, vertical: &[f64], horizontal: &[f64], candela: &[f64]) -> String {
+        let list = |values: &[f64]| values.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(" ");
+        format!(
+            "IESNA:LM-63-2002\n[TEST] fixture\nTILT={}\n1 1000 1 {} {} 1 2 0 0 0\n1 1 100\n{}\n{}\n{}\n",
+            tilt, vertical.len(), horizontal.len(), list(vertical), list(horizontal), list(candela),
+        )
+    }
+
+    #[test]
+    fn parses_a_profile() {
+        let profile = parse_ies(&ies_text("NONE", &[0., 90.], &[0.], &[100., 50.])).unwrap();
+        assert_eq!(profile.candela(0., 0.), 100.);
+        assert_eq!(profile.candela(45., 200.), 75.);
+
+        // Tilt tables are skipped
+        let text = ies_text("INCLUDE\n1\n2\n0 90\n1 1", &[0., 90.], &[0.], &[100., 50.]);
+        assert_eq!(parse_ies(&text).unwrap().candela(90., 0.), 50.);
+    }
+
+    #[test]
+    fn rejects_invalid_table_sizes() {
+        let text = ies_text("NONE", &[0., 90.], &[0.], &[100., 50.]);
+        for count in ["-1", "1.5", "1e300", "NaN", "inf"] {
+            let bad = text.replacen("1 1000 1 2", &format!("1 1000 1 {}", count), 1);
+            assert!(parse_ies(&bad).is_err(), "{}", count);
+
+            let bad_tilt = ies_text(&format!("INCLUDE\n1\n{}\n", count), &[0., 90.], &[0.], &[100., 50.]);
+            assert!(parse_ies(&bad_tilt).is_err(), "{}", count);
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        let text = ies_text("NONE", &[0., 90.], &[0., 90.], &[100., 50., 80.]);
+        assert!(parse_ies(&text).is_err());
+    }
+}
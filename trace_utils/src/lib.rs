@@ -1,3 +1,9 @@
+pub mod obj;
+pub mod voxel;
+pub mod hdr;
+pub mod environment;
+pub mod ies;
+
 use std::fs::File;
 use std::io::{prelude::*, Result};
 
@@ -16,7 +22,7 @@ pub fn add_pixel_to_ppm(img: &mut String, color: RGBColor, samples_per_pixels: u
     let ig = (256. * g.clamp(0., 0.999999)) as i32;
     let ib = (256. * b.clamp(0., 0.999999)) as i32;
 
-    *img = format!("{}\n{} {} {}\n", img, ir, ig, ib);
+    img.push_str(&format!("\n{} {} {}\n", ir, ig, ib));
 }
 
 pub fn save_ppm(img: String) -> Result<()> {
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+use trace_math::material::Material;
+use trace_math::triangle::TriangleMesh;
+use trace_math::vec3::{Point, Vec3};
+
+/// Loads the geometry of a Wavefront OBJ file as a single mesh: positions,
+/// texture coordinates, normals and polygonal faces (triangulated as fans).
+/// Groups, objects and material libraries are ignored.
+pub fn load_obj<P: AsRef<Path>>(path: P, material: Box<dyn Material>) -> Result<TriangleMesh> {
+    let text = fs::read_to_string(path)?;
+    parse_obj(&text, material)
+}
+
+pub fn parse_obj(text: &str, material: Box<dyn Material>) -> Result<TriangleMesh> {
+    let mut positions: Vec<Point> = vec![];
+    let mut tex_coords: Vec<(f64, f64)> = vec![];
+    let mut normals: Vec<Vec3> = vec![];
+
+    // OBJ indexes attributes separately: each distinct combination becomes a mesh vertex
+    let mut vertex_ids: HashMap<(usize, Option<usize>, Option<usize>), usize> = HashMap::new();
+    let mut corners: Vec<(usize, Option<usize>, Option<usize>)> = vec![];
+    let mut faces: Vec<[usize; 3]> = vec![];
+
+    for (line_number, line) in text.lines().enumerate() {
+        let invalid = |msg: &str| Error::new(ErrorKind::InvalidData, format!("line {}: {}", line_number + 1, msg));
+        let mut tokens = line.split_whitespace();
+        let floats = |tokens: std::str::SplitWhitespace, count: usize| -> Result<Vec<f64>> {
+            let values: Vec<f64> = tokens.take(count)
+                .map(|t| t.parse::<f64>().map_err(|_| invalid("invalid number")))
+                .collect::<Result<_>>()?;
+            match values.len() == count {
+                true => Ok(values),
+                false => Err(invalid("missing coordinates")),
+            }
+        };
+
+        match tokens.next() {
+            Some("v") => {
+                let v = floats(tokens, 3)?;
+                positions.push(Point::new(v[0], v[1], v[2]));
+            },
+            Some("vt") => {
+                let v = floats(tokens, 2)?;
+                tex_coords.push((v[0], v[1]));
+            },
+            Some("vn") => {
+                let v = floats(tokens, 3)?;
+                let normal = Vec3::new(v[0], v[1], v[2]);
+                let length_squared = normal.length_squared();
+                if !(length_squared > 0. && length_squared.is_finite()) {
+                    return Err(invalid("degenerate normal"));
+                }
+                normals.push(normal.normalized());
+            },
+            Some("f") => {
+                let mut polygon = vec![];
+                for corner in tokens {
+                    let mut parts = corner.split('/');
+                    let resolve = |part: Option<&str>, count: usize| -> Result<Option<usize>> {
+                        match part {
+                            None | Some("") => Ok(None),
+                            Some(index) => {
+                                let index: i64 = index.parse().map_err(|_| invalid("invalid index"))?;
+                                // Negative indices count back from the latest element
+                                let resolved = match index < 0 {
+                                    true => count as i64 + index,
+                                    false => index - 1,
+                                };
+                                match resolved >= 0 && (resolved as usize) < count {
+                                    true => Ok(Some(resolved as usize)),
+                                    false => Err(invalid("index out of range")),
+                                }
+                            },
+                        }
+                    };
+                    let v = resolve(parts.next(), positions.len())?.ok_or_else(|| invalid("missing position index"))?;
+                    let vt = resolve(parts.next(), tex_coords.len())?;
+                    let vn = resolve(parts.next(), normals.len())?;
+
+                    let key = (v, vt, vn);
+                    let id = *vertex_ids.entry(key).or_insert_with(|| {
+                        corners.push(key);
+                        corners.len() - 1
+                    });
+                    polygon.push(id);
+                }
+
+                if polygon.len() < 3 {
+                    return Err(invalid("face with fewer than 3 vertices"));
+                }
+                for i in 1..polygon.len() - 1 {
+                    faces.push([polygon[0], polygon[i], polygon[i + 1]]);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    // Attributes are only kept when every vertex has them
+    let mesh_positions = corners.iter().map(|&(v, _, _)| positions[v]).collect();
+    let mesh_uvs = match corners.iter().all(|&(_, vt, _)| vt.is_some()) {
+        true => corners.iter().map(|&(_, vt, _)| tex_coords[vt.unwrap()]).collect(),
+        false => vec![],
+    };
+    let mesh_normals = match corners.iter().all(|&(_, _, vn)| vn.is_some()) {
+        true => corners.iter().map(|&(_, _, vn)| normals[vn.unwrap()]).collect(),
+        false => vec![],
+    };
+
+    TriangleMesh::new(mesh_positions, mesh_normals, mesh_uvs, faces, material)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use trace_math::material::Lambertian;
+    use trace_math::vec3::RGBColor;
+
+    fn parse(text: &str) -> Result<TriangleMesh> {
+        parse_obj(text, Box::new(Lambertian::new(RGBColor::ones())))
+    }
+
+    #[test]
+    fn parses_a_polygon() {
+        let mesh = parse("v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvn 0 0 2\nf 1//1 2//1 3//1 -1//1\n").unwrap();
+        assert_eq!(mesh.face_count(), 2);
+    }
+
+    #[test]
+    fn rejects_degenerate_normals() {
+        assert!(parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 0\nf 1//1 2//1 3//1\n").is_err());
+        assert!(parse("vn inf 0 0\n").is_err());
+    }
+}
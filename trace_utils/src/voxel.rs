@@ -0,0 +1,79 @@
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+use trace_math::voxel_grid::VoxelGrid;
+
+/// Loads a dense voxel grid from a raw binary file: three little-endian `u32`
+/// giving the resolution along x, y and z, followed by one little-endian
+/// `f32` per voxel with x varying fastest, then y, then z.
+pub fn load_voxel_grid<P: AsRef<Path>>(path: P) -> Result<VoxelGrid> {
+    let bytes = fs::read(path)?;
+    parse_voxel_grid(&bytes)
+}
+
+pub fn parse_voxel_grid(bytes: &[u8]) -> Result<VoxelGrid> {
+    let invalid = |msg: &str| Error::new(ErrorKind::InvalidData, msg.to_string());
+
+    if bytes.len() < 12 {
+        return Err(invalid("voxel grid header is truncated"));
+    }
+    let dim = |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]) as usize;
+    let (nx, ny, nz) = (dim(0), dim(4), dim(8));
+
+    let size = nx.checked_mul(ny)
+        .and_then(|n| n.checked_mul(nz))
+        .and_then(|n| n.checked_mul(4))
+        .ok_or_else(|| invalid("voxel grid resolution is too large"))?;
+    let body = &bytes[12..];
+    if body.len() != size {
+        return Err(invalid("voxel grid data doesn't match its resolution"));
+    }
+
+    let data: Vec<f64> = body.chunks_exact(4)
+        .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]) as f64)
+        .collect();
+    VoxelGrid::new(nx, ny, nz, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid_bytes(resolution: [u32; 3], values: &[f32]) -> Vec<u8> {
+        let mut bytes: Vec<u8> = resolution.iter().flat_map(|n| n.to_le_bytes()).collect();
+        bytes.extend(values.iter().flat_map(|v| v.to_le_bytes()));
+        bytes
+    }
+
+    #[test]
+    fn parses_a_grid() {
+        let grid = parse_voxel_grid(&grid_bytes([2, 1, 1], &[0.5, 1.5])).unwrap();
+        assert_eq!(grid.resolution(), (2, 1, 1));
+        assert_eq!(grid.max_value(), 1.5);
+    }
+
+    #[test]
+    fn rejects_mismatched_data() {
+        assert!(parse_voxel_grid(&grid_bytes([2, 2, 1], &[0.5, 1.5])).is_err());
+        assert!(parse_voxel_grid(&grid_bytes([1, 1, 0], &[])).is_err());
+        assert!(parse_voxel_grid(&[0; 8]).is_err());
+    }
+
+    #[test]
+    fn rejects_resolutions_overflowing_the_data_size() {
+        // 2^62 + 1 cells, whose byte size wraps around to 4 on 64 bits
+        let bytes = grid_bytes([5 * 5581 * 8681, 49477, 384773], &[1.]);
+        assert!(parse_voxel_grid(&bytes).is_err());
+
+        let bytes = grid_bytes([u32::MAX, u32::MAX, u32::MAX], &[1.]);
+        assert!(parse_voxel_grid(&bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_negative_and_non_finite_values() {
+        assert!(parse_voxel_grid(&grid_bytes([1, 1, 1], &[-1.])).is_err());
+        assert!(parse_voxel_grid(&grid_bytes([1, 1, 1], &[f32::NAN])).is_err());
+        assert!(parse_voxel_grid(&grid_bytes([1, 1, 1], &[f32::INFINITY])).is_err());
+    }
+}